@@ -0,0 +1,5 @@
+-- Add Status Column To Subscriptions
+-- Rows inserted before double opt-in are treated as already confirmed.
+ALTER TABLE subscriptions ADD COLUMN status TEXT NULL;
+UPDATE subscriptions SET status = 'confirmed' WHERE status IS NULL;
+ALTER TABLE subscriptions ALTER COLUMN status SET NOT NULL;
@@ -0,0 +1,8 @@
+-- Align Subscriptions Columns With Domain Limits
+-- Keep in sync with `src/domain/limits.rs`.
+ALTER TABLE subscriptions ALTER COLUMN name TYPE varchar(255);
+ALTER TABLE subscriptions ALTER COLUMN email TYPE varchar(254);
+ALTER TABLE subscriptions
+    ADD CONSTRAINT subscriptions_name_max_bytes CHECK (octet_length(name) <= 512);
+ALTER TABLE subscriptions
+    ADD CONSTRAINT subscriptions_email_max_bytes CHECK (octet_length(email) <= 254);
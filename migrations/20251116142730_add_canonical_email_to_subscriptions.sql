@@ -0,0 +1,33 @@
+-- Add Canonical Email To Subscriptions
+-- Duplicates are detected on `email_canonical`, while `email` keeps the
+-- spelling the subscriber typed. `email` stays unique too, as rows
+-- canonicalized under different settings can hold the same address under
+-- different canonical forms.
+--
+-- Existing rows are backfilled with the lowercased address, which matches the
+-- canonical form for ASCII domains. Internationalized domains keep their
+-- Unicode spelling instead of punycode, so only an exact repeat of such an
+-- address is caught as a duplicate.
+DO $$
+DECLARE
+    duplicate TEXT;
+BEGIN
+    SELECT lower(email) INTO duplicate
+    FROM subscriptions
+    GROUP BY lower(email)
+    HAVING COUNT(*) > 1
+    LIMIT 1;
+
+    IF FOUND THEN
+        RAISE EXCEPTION
+            'Several subscribers have the address % up to case, merge them before running this migration.',
+            duplicate;
+    END IF;
+END
+$$;
+
+ALTER TABLE subscriptions ADD COLUMN email_canonical TEXT NULL;
+UPDATE subscriptions SET email_canonical = lower(email) WHERE email_canonical IS NULL;
+ALTER TABLE subscriptions ALTER COLUMN email_canonical SET NOT NULL;
+ALTER TABLE subscriptions
+    ADD CONSTRAINT subscriptions_email_canonical_key UNIQUE (email_canonical);
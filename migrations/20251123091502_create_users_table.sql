@@ -0,0 +1,6 @@
+-- Create Users Table
+CREATE TABLE users (
+    user_id uuid NOT NULL PRIMARY KEY,
+    username TEXT NOT NULL UNIQUE,
+    password_hash TEXT NOT NULL
+);
@@ -0,0 +1,9 @@
+-- Create Newsletter Issues Table
+CREATE TABLE newsletter_issues (
+    newsletter_issue_id uuid NOT NULL PRIMARY KEY,
+    title TEXT NOT NULL,
+    text_content TEXT NOT NULL,
+    html_content TEXT NOT NULL,
+    published_by uuid NOT NULL REFERENCES users (user_id),
+    published_at timestamptz NOT NULL
+);
@@ -0,0 +1,16 @@
+-- Create Issue Delivery Queue Table
+-- One row per issue and recipient. Rows stay around once handled, so that the
+-- outcome of an issue can be inspected: `pending` rows are picked up by the
+-- workers once `execute_after` has passed, `delivered` rows were accepted by
+-- the provider and `dead` rows ran out of attempts.
+CREATE TABLE issue_delivery_queue (
+    newsletter_issue_id uuid NOT NULL REFERENCES newsletter_issues (newsletter_issue_id),
+    subscriber_email TEXT NOT NULL,
+    status TEXT NOT NULL DEFAULT 'pending',
+    n_attempts INT NOT NULL DEFAULT 0,
+    execute_after timestamptz NOT NULL DEFAULT now(),
+    last_error TEXT NULL,
+    PRIMARY KEY (newsletter_issue_id, subscriber_email)
+);
+CREATE INDEX issue_delivery_queue_pending_idx
+    ON issue_delivery_queue (execute_after) WHERE status = 'pending';
@@ -0,0 +1,15 @@
+-- Create Idempotency Table
+-- The response columns stay NULL until the first request with a key has
+-- finished; the row lock makes concurrent duplicates wait for it.
+CREATE TABLE idempotency (
+    user_id uuid NOT NULL REFERENCES users (user_id),
+    idempotency_key TEXT NOT NULL,
+    request_fingerprint BYTEA NOT NULL,
+    response_status_code SMALLINT NULL,
+    response_header_names TEXT[] NULL,
+    response_header_values BYTEA[] NULL,
+    response_body BYTEA NULL,
+    created_at timestamptz NOT NULL,
+    PRIMARY KEY (user_id, idempotency_key)
+);
+CREATE INDEX idempotency_created_at_idx ON idempotency (created_at);
@@ -0,0 +1,11 @@
+-- Add Scheduling To Newsletter Issues
+-- Issues are either `scheduled`, `cancelled` or `published`, the latter
+-- meaning that they were moved into `issue_delivery_queue` at `published_at`.
+-- Existing issues were published right away.
+ALTER TABLE newsletter_issues ADD COLUMN status TEXT NULL;
+UPDATE newsletter_issues SET status = 'published' WHERE status IS NULL;
+ALTER TABLE newsletter_issues ALTER COLUMN status SET NOT NULL;
+ALTER TABLE newsletter_issues ADD COLUMN scheduled_at timestamptz NULL;
+ALTER TABLE newsletter_issues ALTER COLUMN published_at DROP NOT NULL;
+CREATE INDEX newsletter_issues_scheduled_idx
+    ON newsletter_issues (scheduled_at) WHERE status = 'scheduled';
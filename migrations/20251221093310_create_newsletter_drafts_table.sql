@@ -0,0 +1,10 @@
+-- Create Newsletter Drafts Table
+CREATE TABLE newsletter_drafts (
+    draft_id uuid NOT NULL PRIMARY KEY,
+    title TEXT NOT NULL,
+    text_content TEXT NOT NULL,
+    html_content TEXT NOT NULL,
+    created_by uuid NOT NULL REFERENCES users (user_id),
+    created_at timestamptz NOT NULL,
+    updated_at timestamptz NOT NULL
+);
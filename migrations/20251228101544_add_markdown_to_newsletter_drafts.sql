@@ -0,0 +1,2 @@
+-- Keep the Markdown source of drafts written in it, so they can be edited
+ALTER TABLE newsletter_drafts ADD COLUMN markdown_content TEXT NULL;
@@ -0,0 +1,3 @@
+-- Add Attributes Column To Subscriptions
+-- Custom values collected at sign-up, for newsletter templates.
+ALTER TABLE subscriptions ADD COLUMN attributes jsonb NOT NULL DEFAULT '{}';
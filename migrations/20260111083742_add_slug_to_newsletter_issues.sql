@@ -0,0 +1,51 @@
+-- Add Slug To Newsletter Issues
+-- The issue's address in the public archive, `/archive/{slug}`, derived from
+-- its title. Existing issues get the slug `unique_slug` would have given them
+-- had they been published one after the other: placeholders left out, the
+-- title run through `IssueSlug::from_title`, and the lowest free number
+-- appended to slugs already taken.
+ALTER TABLE newsletter_issues ADD COLUMN slug TEXT NULL;
+DO $$
+DECLARE
+    issue RECORD;
+    stripped TEXT;
+    base TEXT;
+    candidate TEXT;
+    n INTEGER;
+BEGIN
+    FOR issue IN
+        SELECT newsletter_issue_id, title
+        FROM newsletter_issues
+        ORDER BY published_at, newsletter_issue_id
+    LOOP
+        -- A title with an unclosed placeholder does not parse, and is left
+        -- with nothing.
+        stripped := regexp_replace(issue.title, '\{\{.*?\}\}', '', 'g');
+        IF stripped LIKE '%{{%' THEN
+            stripped := '';
+        END IF;
+
+        base := rtrim(
+            left(ltrim(lower(regexp_replace(stripped, '[^A-Za-z0-9]+', '-', 'g')), '-'), 80),
+            '-'
+        );
+        IF base = '' THEN
+            base := 'issue';
+        END IF;
+
+        candidate := base;
+        n := 1;
+        WHILE EXISTS (SELECT 1 FROM newsletter_issues WHERE slug = candidate) LOOP
+            n := n + 1;
+            candidate := base || '-' || n;
+        END LOOP;
+
+        UPDATE newsletter_issues SET slug = candidate
+        WHERE newsletter_issue_id = issue.newsletter_issue_id;
+    END LOOP;
+END
+$$;
+ALTER TABLE newsletter_issues ALTER COLUMN slug SET NOT NULL;
+ALTER TABLE newsletter_issues ADD CONSTRAINT newsletter_issues_slug_key UNIQUE (slug);
+CREATE INDEX newsletter_issues_archive_idx
+    ON newsletter_issues (published_at DESC) WHERE status = 'published';
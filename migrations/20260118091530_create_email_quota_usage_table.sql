@@ -0,0 +1,7 @@
+-- Create Email Quota Usage Table
+-- Emails handed to the provider per UTC day, shared by every replica so that
+-- the daily quota holds across restarts.
+CREATE TABLE email_quota_usage (
+    day date NOT NULL PRIMARY KEY,
+    sent integer NOT NULL
+);
@@ -0,0 +1,6 @@
+-- Add Locked Until To Idempotency
+-- A key whose response columns are still NULL is in progress until
+-- `locked_until`. After that, the request that took it is assumed to be gone,
+-- e.g. because the process restarted, and a retry can take the key over.
+ALTER TABLE idempotency ADD COLUMN locked_until timestamptz NOT NULL DEFAULT now();
+ALTER TABLE idempotency ALTER COLUMN locked_until DROP DEFAULT;
@@ -1,34 +1,101 @@
 use std::sync::Arc;
+use std::time::Duration;
 
 use axum::Router;
-use axum::routing::{get, post};
+use axum::middleware::from_fn_with_state;
+use axum::routing::{get, post, put};
 use sqlx::PgPool;
 use tokio::net::TcpListener;
 
-use crate::config::Config;
-use crate::email_client::EmailClient;
+use crate::authentication::{Credentials, ensure_admin, require_admin};
+use crate::config::{Config, DeliveryConfig, EmailProvider, Env};
+use crate::email_client::{CircuitBreaker, EmailClient, MailCatcher, RateLimiter, RetryPolicy};
+use crate::idempotency::{IdempotencyKeyTtl, idempotency};
+use crate::issue_delivery_worker::DeliveryWorker;
+use crate::issue_scheduler::IssueScheduler;
 use crate::routes::*;
+use crate::state::{AppState, ApplicationBaseUrl, TestRecipients};
 use crate::telemetry::with_request_id;
+use crate::unsubscribe::UnsubscribeLinks;
 
 pub struct App {
     listener: TcpListener,
-    conn_pool: PgPool,
-    email_client: EmailClient,
+    state: AppState,
+    mail_catcher: Option<MailCatcher>,
+    delivery_config: DeliveryConfig,
     port: u16,
 }
 
 impl App {
     pub async fn build(config: Config) -> Result<Self, std::io::Error> {
+        // `/dev/mailbox` is unauthenticated and shows every token sent out.
+        if config.email_client_config.provider == EmailProvider::MailCatcher
+            && config.env != Env::Dev
+        {
+            return Err(std::io::Error::other(
+                "The `mailcatcher` email provider is only available with `APP_ENV=dev`.",
+            ));
+        }
+
         let listener = TcpListener::bind((config.app_config.host, config.app_config.port)).await?;
         let conn_pool = PgPool::connect_lazy_with(config.db_config.connection_options());
-        let email_client = EmailClient::from(config.email_client_config);
+
+        if let Some(admin) = config.app_config.admin {
+            let credentials = Credentials {
+                username: admin.username,
+                password: admin.password,
+            };
+            ensure_admin(&conn_pool, &credentials)
+                .await
+                .map_err(std::io::Error::other)?;
+        }
+
+        let unsubscribe_links = UnsubscribeLinks::new(
+            config.app_config.base_url.clone(),
+            config.app_config.hmac_secret,
+        );
+        let mut test_recipients = config.app_config.test_recipients;
+        test_recipients.push(config.email_client_config.sender.clone());
+        let rate_limiter =
+            RateLimiter::new(&config.email_client_config.rate_limit, conn_pool.clone());
+        let retry_policy = RetryPolicy::from(&config.email_client_config.retry);
+        let circuit_breaker = CircuitBreaker::new(&config.email_client_config.circuit_breaker);
+        // Kept outside of the client so that `/dev/mailbox` can read it back.
+        let mail_catcher = (config.email_client_config.provider == EmailProvider::MailCatcher)
+            .then(MailCatcher::default);
+        let email_client = match &mail_catcher {
+            Some(mail_catcher) => {
+                EmailClient::new(config.email_client_config.sender, mail_catcher.clone())
+            }
+            None => {
+                EmailClient::try_from(config.email_client_config).map_err(std::io::Error::other)?
+            }
+        }
+        .with_unsubscribe_links(unsubscribe_links.clone())
+        .with_rate_limiter(rate_limiter)
+        .with_retry_policy(retry_policy)
+        .with_circuit_breaker(circuit_breaker);
+        let base_url = ApplicationBaseUrl(config.app_config.base_url);
         let port = listener.local_addr().unwrap().port();
 
+        let state = AppState {
+            conn_pool,
+            email_client: Arc::new(email_client),
+            base_url,
+            unsubscribe_links,
+            email_canonicalization: config.app_config.email_canonicalization,
+            idempotency_key_ttl: IdempotencyKeyTtl(Duration::from_secs(
+                config.app_config.idempotency_key_ttl_secs,
+            )),
+            test_recipients: TestRecipients(test_recipients.into()),
+        };
+
         Ok(Self {
             port,
             listener,
-            conn_pool,
-            email_client,
+            state,
+            mail_catcher,
+            delivery_config: config.delivery_config,
         })
     }
 
@@ -37,12 +104,81 @@ impl App {
         self.port
     }
 
+    /// A worker sharing the app's database and email client, which
+    /// [`App::run`] spawns `delivery.workers` of.
+    pub fn delivery_worker(&self) -> DeliveryWorker {
+        DeliveryWorker::new(
+            self.state.conn_pool.clone(),
+            self.state.email_client.clone(),
+            self.delivery_config.clone(),
+        )
+    }
+
+    /// Publishes scheduled issues once they are due; [`App::run`] spawns one
+    /// unless `delivery.scheduler` is off.
+    pub fn issue_scheduler(&self) -> IssueScheduler {
+        IssueScheduler::new(
+            self.state.conn_pool.clone(),
+            Duration::from_millis(self.delivery_config.poll_interval_ms),
+        )
+    }
+
     pub async fn run(self) -> Result<(), std::io::Error> {
+        for _ in 0..self.delivery_config.workers {
+            tokio::spawn(self.delivery_worker().run_until_stopped());
+        }
+
+        if self.delivery_config.scheduler {
+            tokio::spawn(self.issue_scheduler().run_until_stopped());
+        }
+
+        let admin = Router::new()
+            .route("/admin/newsletters", post(publish_newsletter))
+            .route("/admin/newsletters/{issue_id}", get(get_newsletter_issue))
+            .route(
+                "/admin/newsletters/{issue_id}/schedule",
+                put(reschedule_newsletter_issue),
+            )
+            .route(
+                "/admin/newsletters/{issue_id}/cancel",
+                post(cancel_newsletter_issue),
+            )
+            .route("/admin/drafts", get(list_drafts).post(create_draft))
+            .route(
+                "/admin/drafts/{draft_id}",
+                get(get_draft).put(update_draft).delete(delete_draft),
+            )
+            .route("/admin/drafts/{draft_id}/preview", get(preview_draft))
+            .route("/admin/drafts/{draft_id}/test", post(send_test_draft))
+            // Layers run bottom to top: authenticate first, then deduplicate.
+            .route_layer(from_fn_with_state(self.state.clone(), idempotency))
+            .route_layer(from_fn_with_state(self.state.clone(), require_admin));
+
         let mut router = Router::new()
             .route("/health_check", get(health_check))
+            .route("/health_check/details", get(health_check_details))
+            .route("/metrics", get(metrics))
             .route("/subscriptions", post(subscribe))
-            .with_state(self.conn_pool)
-            .with_state(Arc::new(self.email_client));
+            .route("/subscriptions/confirm", get(confirm))
+            .route(
+                "/subscriptions/unsubscribe",
+                get(unsubscribe_page).post(unsubscribe),
+            )
+            .route("/archive", get(archive))
+            .route("/archive/{slug}", get(archived_issue))
+            .route("/feed.xml", get(rss_feed))
+            .route("/atom.xml", get(atom_feed))
+            .merge(admin)
+            .with_state(self.state);
+
+        if let Some(mail_catcher) = self.mail_catcher {
+            router = router.merge(
+                Router::new()
+                    .route("/dev/mailbox", get(list_mailbox).delete(clear_mailbox))
+                    .route("/dev/mailbox/{id}", get(show_mailbox_message))
+                    .with_state(mail_catcher),
+            );
+        }
 
         router = with_request_id(router);
 
@@ -0,0 +1,245 @@
+use argon2::password_hash::SaltString;
+use argon2::password_hash::rand_core::OsRng;
+use argon2::{Algorithm, Argon2, Params, PasswordHash, PasswordHasher, PasswordVerifier, Version};
+use axum::extract::{Request, State};
+use axum::http::{HeaderMap, header};
+use axum::middleware::Next;
+use axum::response::Response;
+use base64::Engine;
+use base64::engine::general_purpose::STANDARD;
+use secrecy::{ExposeSecret, SecretString};
+use sqlx::PgPool;
+use tracing::instrument;
+use uuid::Uuid;
+
+use crate::error::AppError;
+
+/// Verified against when the username is unknown, so that unknown users take
+/// as long to reject as wrong passwords. It must be computed with
+/// [`hasher`]'s parameters, which a test checks.
+const DUMMY_PASSWORD_HASH: &str = "$argon2id$v=19$m=19456,t=2,p=1$\
+    +5U+gdQKheOeGcGig6sIvA$\
+    liBk8mwFyOOVPvV9tA7I8ZIKeY3IJqnj7ZWMxn6pZyk";
+
+/// The hasher for new password hashes. Changing its parameters means
+/// recomputing [`DUMMY_PASSWORD_HASH`].
+fn hasher() -> Argon2<'static> {
+    Argon2::new(Algorithm::Argon2id, Version::V0x13, Params::DEFAULT)
+}
+
+#[derive(Debug)]
+pub struct Credentials {
+    pub username: String,
+    pub password: SecretString,
+}
+
+/// The admin a request was authenticated as, inserted into the request
+/// extensions by [`require_admin`].
+#[derive(Debug, Clone, Copy)]
+pub struct AdminUser {
+    pub user_id: Uuid,
+}
+
+#[derive(Debug, thiserror::Error)]
+pub enum AuthError {
+    #[error("Invalid credentials.")]
+    InvalidCredentials,
+    #[error(transparent)]
+    Unexpected(#[from] Box<dyn std::error::Error + Send + Sync>),
+}
+
+impl From<AuthError> for AppError {
+    fn from(value: AuthError) -> Self {
+        match value {
+            AuthError::InvalidCredentials => AppError::Unauthenticated,
+            AuthError::Unexpected(e) => AppError::Unexpected(e),
+        }
+    }
+}
+
+/// Rejects requests without valid HTTP Basic credentials of a user in the
+/// `users` table.
+pub async fn require_admin(
+    State(pool): State<PgPool>,
+    mut request: Request,
+    next: Next,
+) -> Result<Response, AppError> {
+    let credentials = basic_authentication(request.headers()).ok_or(AppError::Unauthenticated)?;
+
+    let user_id = validate_credentials(&pool, credentials).await?;
+
+    request.extensions_mut().insert(AdminUser { user_id });
+
+    Ok(next.run(request).await)
+}
+
+fn basic_authentication(headers: &HeaderMap) -> Option<Credentials> {
+    let encoded = headers
+        .get(header::AUTHORIZATION)?
+        .to_str()
+        .ok()?
+        .strip_prefix("Basic ")?;
+
+    let decoded = String::from_utf8(STANDARD.decode(encoded).ok()?).ok()?;
+    let (username, password) = decoded.split_once(':')?;
+
+    Some(Credentials {
+        username: username.into(),
+        password: SecretString::from(password),
+    })
+}
+
+#[instrument(skip_all, name = "Validating credentials", fields(username = %credentials.username))]
+pub async fn validate_credentials(
+    pool: &PgPool,
+    credentials: Credentials,
+) -> Result<Uuid, AuthError> {
+    let (user_id, expected_password_hash) =
+        match get_stored_credentials(pool, &credentials.username)
+            .await
+            .map_err(|e| AuthError::Unexpected(Box::new(e)))?
+        {
+            Some((user_id, hash)) => (Some(user_id), hash),
+            None => (None, SecretString::from(DUMMY_PASSWORD_HASH)),
+        };
+
+    let current_span = tracing::Span::current();
+    tokio::task::spawn_blocking(move || {
+        current_span.in_scope(|| verify_password_hash(expected_password_hash, credentials.password))
+    })
+    .await
+    .map_err(|e| AuthError::Unexpected(Box::new(e)))??;
+
+    user_id.ok_or(AuthError::InvalidCredentials)
+}
+
+#[instrument(skip_all, name = "Verifying password hash")]
+fn verify_password_hash(
+    expected_password_hash: SecretString,
+    password_candidate: SecretString,
+) -> Result<(), AuthError> {
+    let expected_password_hash = PasswordHash::new(expected_password_hash.expose_secret())
+        .map_err(|e| AuthError::Unexpected(e.to_string().into()))?;
+
+    hasher()
+        .verify_password(
+            password_candidate.expose_secret().as_bytes(),
+            &expected_password_hash,
+        )
+        .map_err(|_| AuthError::InvalidCredentials)
+}
+
+#[instrument(skip_all, name = "Getting stored credentials")]
+async fn get_stored_credentials(
+    pool: &PgPool,
+    username: &str,
+) -> Result<Option<(Uuid, SecretString)>, sqlx::Error> {
+    let row = sqlx::query!(
+        r#"SELECT user_id, password_hash FROM users WHERE username = $1"#,
+        username,
+    )
+    .fetch_optional(pool)
+    .await
+    .inspect_err(|e| tracing::error!("Failed to execute query: {e:?}"))?;
+
+    Ok(row.map(|r| (r.user_id, SecretString::from(r.password_hash))))
+}
+
+/// Hashes a password into a PHC string with [`hasher`]'s parameters.
+pub fn compute_password_hash(password: &SecretString) -> Result<SecretString, AuthError> {
+    let salt = SaltString::generate(&mut OsRng);
+
+    let hash = hasher()
+        .hash_password(password.expose_secret().as_bytes(), &salt)
+        .map_err(|e| AuthError::Unexpected(e.to_string().into()))?;
+
+    Ok(SecretString::from(hash.to_string()))
+}
+
+/// Creates the admin user from the configuration, or resets its password if
+/// it already exists.
+#[instrument(skip_all, name = "Ensuring admin user", fields(username = %credentials.username))]
+pub async fn ensure_admin(pool: &PgPool, credentials: &Credentials) -> Result<(), AuthError> {
+    let password = credentials.password.clone();
+    let password_hash = tokio::task::spawn_blocking(move || compute_password_hash(&password))
+        .await
+        .map_err(|e| AuthError::Unexpected(Box::new(e)))??;
+
+    sqlx::query!(
+        r#"
+        INSERT INTO users (user_id, username, password_hash)
+        VALUES ($1, $2, $3)
+        ON CONFLICT (username) DO UPDATE SET password_hash = EXCLUDED.password_hash
+        "#,
+        Uuid::new_v4(),
+        credentials.username,
+        password_hash.expose_secret(),
+    )
+    .execute(pool)
+    .await
+    .inspect_err(|e| tracing::error!("Failed to execute query: {e:?}"))
+    .map_err(|e| AuthError::Unexpected(Box::new(e)))?;
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use axum::http::{HeaderMap, HeaderValue, header};
+    use claims::{assert_none, assert_ok, assert_some};
+    use secrecy::{ExposeSecret, SecretString};
+
+    use argon2::{Params, PasswordHash};
+
+    use super::{
+        DUMMY_PASSWORD_HASH, basic_authentication, compute_password_hash, verify_password_hash,
+    };
+
+    #[test]
+    fn basic_credentials_are_decoded() {
+        let mut headers = HeaderMap::new();
+        // "admin:pass:word"
+        headers.insert(
+            header::AUTHORIZATION,
+            HeaderValue::from_static("Basic YWRtaW46cGFzczp3b3Jk"),
+        );
+
+        let credentials = assert_some!(basic_authentication(&headers));
+        assert_eq!(credentials.username, "admin");
+        assert_eq!(credentials.password.expose_secret(), "pass:word");
+    }
+
+    #[test]
+    fn other_schemes_are_ignored() {
+        let mut headers = HeaderMap::new();
+        headers.insert(header::AUTHORIZATION, HeaderValue::from_static("Bearer x"));
+
+        assert_none!(basic_authentication(&headers));
+    }
+
+    #[test]
+    fn computed_hashes_verify_their_password() {
+        let password = SecretString::from("correct horse");
+        let hash = compute_password_hash(&password).unwrap();
+
+        assert_ok!(verify_password_hash(hash, password));
+    }
+
+    #[test]
+    fn dummy_hash_is_a_valid_phc_string() {
+        let result = verify_password_hash(
+            SecretString::from(DUMMY_PASSWORD_HASH),
+            SecretString::from("anything"),
+        );
+
+        assert!(matches!(result, Err(super::AuthError::InvalidCredentials)));
+    }
+
+    #[test]
+    fn dummy_hash_takes_as_long_as_computed_hashes() {
+        let hash = compute_password_hash(&SecretString::from("correct horse")).unwrap();
+        let params = |hash: &str| Params::try_from(&PasswordHash::new(hash).unwrap()).unwrap();
+
+        assert_eq!(params(DUMMY_PASSWORD_HASH), params(hash.expose_secret()));
+    }
+}
@@ -3,10 +3,12 @@ use std::fmt::Display;
 use reqwest::Url;
 use secrecy::{ExposeSecret, SecretString};
 use serde::Deserialize;
-use serde_aux::field_attributes::deserialize_number_from_string;
+use serde_aux::field_attributes::{
+    deserialize_number_from_string, deserialize_option_number_from_string,
+};
 use sqlx::postgres::{PgConnectOptions, PgSslMode};
 
-use crate::domain::Email;
+use crate::domain::{Canonicalization, Email};
 
 #[derive(Deserialize)]
 pub struct Config {
@@ -16,6 +18,11 @@ pub struct Config {
     pub db_config: DBConfig,
     #[serde(rename = "email_client")]
     pub email_client_config: EmailClientConfig,
+    #[serde(rename = "delivery", default)]
+    pub delivery_config: DeliveryConfig,
+    /// Set from `APP_ENV` by [`get_config`].
+    #[serde(skip)]
+    pub env: Env,
 }
 
 #[derive(Deserialize)]
@@ -23,6 +30,26 @@ pub struct AppConfig {
     #[serde(deserialize_with = "deserialize_number_from_string")]
     pub port: u16,
     pub host: String,
+    pub base_url: String,
+    pub hmac_secret: SecretString,
+    /// How long responses to admin requests are kept for `Idempotency-Key`
+    /// replays.
+    #[serde(deserialize_with = "deserialize_number_from_string")]
+    pub idempotency_key_ttl_secs: u64,
+    #[serde(default)]
+    pub email_canonicalization: Canonicalization,
+    /// Addresses drafts can be sent to as a test, on top of the sender's.
+    #[serde(default)]
+    pub test_recipients: Vec<Email>,
+    /// Created or updated on startup, so that a fresh deployment has someone
+    /// able to use the admin routes.
+    pub admin: Option<AdminConfig>,
+}
+
+#[derive(Deserialize)]
+pub struct AdminConfig {
+    pub username: String,
+    pub password: SecretString,
 }
 
 #[derive(Deserialize, Clone)]
@@ -56,15 +83,211 @@ impl DBConfig {
 
 #[derive(Deserialize)]
 pub struct EmailClientConfig {
+    #[serde(default)]
+    pub provider: EmailProvider,
     #[serde(rename = "sender_email")]
     pub sender: Email,
     pub base_url: Url,
     pub token: SecretString,
     pub timeout_ms: u32,
+    #[serde(default)]
+    pub smtp: SmtpConfig,
+    #[serde(default)]
+    pub rate_limit: RateLimitConfig,
+    #[serde(default)]
+    pub retry: RetryConfig,
+    #[serde(default)]
+    pub circuit_breaker: CircuitBreakerConfig,
+}
+
+/// When sends stop reaching the provider after it kept failing.
+#[derive(Deserialize, Clone, Debug)]
+#[serde(default)]
+pub struct CircuitBreakerConfig {
+    /// Transient failures in a row, retries included, that open the circuit.
+    #[serde(deserialize_with = "deserialize_number_from_string")]
+    pub failure_threshold: u32,
+    /// How long the circuit stays open before a single send probes the
+    /// provider again.
+    #[serde(deserialize_with = "deserialize_number_from_string")]
+    pub open_ms: u64,
+}
+
+impl Default for CircuitBreakerConfig {
+    fn default() -> Self {
+        Self {
+            failure_threshold: 5,
+            open_ms: 30_000,
+        }
+    }
+}
+
+/// How sends are retried after transient failures, on top of `timeout_ms`
+/// for each attempt.
+#[derive(Deserialize, Clone, Debug)]
+#[serde(default)]
+pub struct RetryConfig {
+    /// Attempts per send, the first one included; 1 turns retries off.
+    #[serde(deserialize_with = "deserialize_number_from_string")]
+    pub max_attempts: u32,
+    /// Longest delay before the first retry, doubled after every further
+    /// failure; the actual delay is picked at random below it.
+    #[serde(deserialize_with = "deserialize_number_from_string")]
+    pub base_backoff_ms: u64,
+    #[serde(deserialize_with = "deserialize_number_from_string")]
+    pub max_backoff_ms: u64,
+    /// How long a send may take across all of its attempts.
+    #[serde(deserialize_with = "deserialize_number_from_string")]
+    pub deadline_ms: u64,
 }
 
+impl Default for RetryConfig {
+    fn default() -> Self {
+        Self {
+            max_attempts: 3,
+            base_backoff_ms: 200,
+            max_backoff_ms: 5_000,
+            deadline_ms: 30_000,
+        }
+    }
+}
+
+/// The provider plan's caps; each one is off unless set.
+#[derive(Deserialize, Clone, Debug, Default)]
+pub struct RateLimitConfig {
+    /// Emails sent per second, on average, by each replica.
+    #[serde(default, deserialize_with = "deserialize_option_number_from_string")]
+    pub per_second: Option<u32>,
+    /// Emails that can go out at once after a quiet period, `per_second` by
+    /// default.
+    #[serde(default, deserialize_with = "deserialize_option_number_from_string")]
+    pub burst: Option<u32>,
+    /// Emails sent per UTC day, across every replica.
+    #[serde(default, deserialize_with = "deserialize_option_number_from_string")]
+    pub daily_quota: Option<u32>,
+}
+
+#[derive(Deserialize, Clone, Copy, Debug, Default, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum EmailProvider {
+    #[default]
+    SendGrid,
+    Postmark,
+    Mailgun,
+    Smtp,
+    /// Keeps emails in memory and serves them, unauthenticated, on
+    /// `/dev/mailbox`. Refused unless `APP_ENV=dev`.
+    MailCatcher,
+}
+
+/// Only read when `provider = "smtp"`; `base_url` and `token` are ignored then.
+#[derive(Deserialize)]
+pub struct SmtpConfig {
+    #[serde(default = "SmtpConfig::default_host")]
+    pub host: String,
+    #[serde(
+        default = "SmtpConfig::default_port",
+        deserialize_with = "deserialize_number_from_string"
+    )]
+    pub port: u16,
+    #[serde(default)]
+    pub tls: SmtpTls,
+    pub username: Option<String>,
+    pub password: Option<SecretString>,
+    #[serde(
+        default = "SmtpConfig::default_pool_size",
+        deserialize_with = "deserialize_number_from_string"
+    )]
+    pub pool_size: u32,
+}
+
+impl SmtpConfig {
+    fn default_host() -> String {
+        "localhost".into()
+    }
+
+    fn default_port() -> u16 {
+        25
+    }
+
+    fn default_pool_size() -> u32 {
+        4
+    }
+}
+
+impl Default for SmtpConfig {
+    fn default() -> Self {
+        Self {
+            host: Self::default_host(),
+            port: Self::default_port(),
+            tls: SmtpTls::default(),
+            username: None,
+            password: None,
+            pool_size: Self::default_pool_size(),
+        }
+    }
+}
+
+#[derive(Deserialize, Clone, Copy, Debug, Default, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum SmtpTls {
+    /// Plaintext, for local relays and test servers only.
+    #[default]
+    None,
+    /// Upgrade a plaintext connection with `STARTTLS`, usually on port 587.
+    StartTls,
+    /// TLS from the first byte, usually on port 465.
+    Implicit,
+}
+
+/// How newsletter issues are taken from `issue_delivery_queue`.
+#[derive(Deserialize, Clone, Debug)]
+#[serde(default)]
+pub struct DeliveryConfig {
+    /// Worker tasks spawned by `App::run`; zero leaves the queue untouched.
+    #[serde(deserialize_with = "deserialize_number_from_string")]
+    pub workers: u16,
+    /// How long an idle worker waits before looking at the queue again.
+    #[serde(deserialize_with = "deserialize_number_from_string")]
+    pub poll_interval_ms: u64,
+    /// Attempts made for a recipient before the delivery is dead-lettered.
+    #[serde(deserialize_with = "deserialize_number_from_string")]
+    pub max_attempts: u32,
+    /// Delay before the first retry, doubled after every further failure.
+    #[serde(deserialize_with = "deserialize_number_from_string")]
+    pub base_backoff_ms: u64,
+    #[serde(deserialize_with = "deserialize_number_from_string")]
+    pub max_backoff_ms: u64,
+    /// Most deliveries of the same issue a worker claims at once, never more
+    /// than a single request to the provider carries: up to 1000 for
+    /// SendGrid, one for providers without a batch API.
+    #[serde(deserialize_with = "deserialize_number_from_string")]
+    pub batch_size: u16,
+    /// Whether `App::run` spawns the loop publishing scheduled issues once
+    /// they are due; it polls every `poll_interval_ms` as well.
+    pub scheduler: bool,
+}
+
+impl Default for DeliveryConfig {
+    fn default() -> Self {
+        Self {
+            workers: 2,
+            poll_interval_ms: 1_000,
+            max_attempts: 5,
+            base_backoff_ms: 30_000,
+            max_backoff_ms: 3_600_000,
+            batch_size: 1_000,
+            scheduler: true,
+        }
+    }
+}
+
+/// Production unless set otherwise, so that development-only features stay
+/// off by default.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
 pub enum Env {
     Dev,
+    #[default]
     Prod,
 }
 
@@ -110,5 +333,8 @@ pub async fn get_config() -> Result<Config, config::ConfigError> {
         )
         .build()?;
 
-    settings.try_deserialize()
+    let mut config: Config = settings.try_deserialize()?;
+    config.env = env;
+
+    Ok(config)
 }
@@ -1,7 +1,12 @@
 mod email;
+mod issue_slug;
+pub mod limits;
 mod subscriber;
+mod subscriber_attributes;
 mod subscriber_name;
 
 pub use email::*;
+pub use issue_slug::*;
 pub use subscriber::*;
+pub use subscriber_attributes::*;
 pub use subscriber_name::*;
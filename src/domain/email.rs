@@ -4,6 +4,8 @@ use serde::{
 };
 use validator::ValidateEmail;
 
+use super::limits::{EMAIL_MAX_BYTES, EMAIL_MAX_CHARS};
+
 #[derive(Debug, Serialize, Clone)]
 pub struct Email {
     #[serde(rename = "email")]
@@ -38,15 +40,153 @@ impl<'de> Deserialize<'de> for Email {
     }
 }
 
+#[derive(Debug, Clone, PartialEq, Eq, thiserror::Error)]
+pub enum EmailError {
+    #[error("The email address must not be empty.")]
+    Empty,
+    #[error(
+        "The email address must not be longer than {EMAIL_MAX_CHARS} characters or {EMAIL_MAX_BYTES} bytes."
+    )]
+    TooLong,
+    #[error("The email address is not valid.")]
+    Syntax,
+    #[error("The email address has nothing left before `@` once provider aliases are removed.")]
+    EmptyCanonicalLocalPart,
+}
+
+impl EmailError {
+    pub fn code(&self) -> &'static str {
+        match self {
+            EmailError::Empty => "empty",
+            EmailError::TooLong => "too_long",
+            EmailError::Syntax => "syntax",
+            EmailError::EmptyCanonicalLocalPart => "empty_canonical_local_part",
+        }
+    }
+}
+
+/// How far [`Email::canonical`] goes when folding equivalent spellings of
+/// an address together.
+///
+/// The canonical form is stored in `subscriptions.email_canonical` when a
+/// subscriber signs up, and is not recomputed afterwards. Changing these
+/// settings leaves existing rows with the form of the settings they signed up
+/// under, so an address that now folds into one of them can sign up again as
+/// a separate subscriber. Unsubscribing still reaches those rows, through the
+/// exact address the newsletter was sent to.
+#[derive(Debug, Clone, Copy, Default, Deserialize)]
+pub struct Canonicalization {
+    /// Also apply the aliasing rules of well-known providers, such as Gmail
+    /// ignoring dots and `+tags` in the local part.
+    #[serde(default)]
+    pub provider_rules: bool,
+}
+
+struct ProviderRule {
+    domains: &'static [&'static str],
+    canonical_domain: Option<&'static str>,
+    ignore_dots: bool,
+}
+
+/// Providers known to deliver `local+tag@domain` to `local@domain`.
+const PROVIDER_RULES: &[ProviderRule] = &[
+    ProviderRule {
+        domains: &["gmail.com", "googlemail.com"],
+        canonical_domain: Some("gmail.com"),
+        ignore_dots: true,
+    },
+    ProviderRule {
+        domains: &["outlook.com", "hotmail.com", "live.com"],
+        canonical_domain: None,
+        ignore_dots: false,
+    },
+    ProviderRule {
+        domains: &["icloud.com", "me.com", "mac.com"],
+        canonical_domain: None,
+        ignore_dots: false,
+    },
+    ProviderRule {
+        domains: &["fastmail.com", "protonmail.com", "proton.me"],
+        canonical_domain: None,
+        ignore_dots: false,
+    },
+];
+
+impl Email {
+    pub fn domain(&self) -> &str {
+        self.inner
+            .rsplit_once('@')
+            .map(|(_, domain)| domain)
+            .expect("A valid email always contains `@`")
+    }
+
+    /// The form used to detect duplicate subscribers: the whole address is
+    /// lowercased and an internationalized domain is converted to punycode.
+    ///
+    /// Lowercasing the local part is a deliberate rule. RFC 5321 lets a
+    /// server treat `Main@` and `main@` as different mailboxes, but no
+    /// provider we send to does, and people mix up the case when signing up
+    /// again far more often than two people share an address up to case.
+    ///
+    /// The spelling the subscriber typed is still available via `as_ref`
+    /// and should be used for display and delivery.
+    ///
+    /// Fails if the provider rules leave nothing before the `@`, as with
+    /// `+tag@gmail.com` or `...@gmail.com`.
+    pub fn canonical(&self, rules: Canonicalization) -> Result<String, EmailError> {
+        let (local, domain) = self
+            .inner
+            .rsplit_once('@')
+            .expect("A valid email always contains `@`");
+
+        let mut local = local.to_lowercase();
+        let mut domain = idna::domain_to_ascii(domain).unwrap_or_else(|_| domain.to_lowercase());
+
+        if rules.provider_rules
+            && let Some(rule) = PROVIDER_RULES
+                .iter()
+                .find(|r| r.domains.contains(&domain.as_str()))
+        {
+            if let Some((base, _tag)) = local.split_once('+') {
+                local = base.to_string();
+            }
+
+            if rule.ignore_dots {
+                local.retain(|c| c != '.');
+            }
+
+            if let Some(canonical_domain) = rule.canonical_domain {
+                domain = canonical_domain.to_string();
+            }
+        }
+
+        if local.is_empty() {
+            return Err(EmailError::EmptyCanonicalLocalPart);
+        }
+
+        Ok(format!("{local}@{domain}"))
+    }
+}
+
 impl TryFrom<String> for Email {
-    type Error = String;
+    type Error = EmailError;
 
     fn try_from(value: String) -> Result<Self, Self::Error> {
-        if value.validate_email() {
-            Ok(Self { inner: value })
-        } else {
-            Err(format!("Email: {value} is not valid."))
+        let value = value.trim().to_string();
+
+        if value.is_empty() {
+            return Err(EmailError::Empty);
+        }
+
+        if value.chars().count() > EMAIL_MAX_CHARS || value.len() > EMAIL_MAX_BYTES {
+            return Err(EmailError::TooLong);
+        }
+
+        if !value.validate_email() {
+            return Err(EmailError::Syntax);
         }
+
+        Ok(Self { inner: value })
     }
 }
 
@@ -58,8 +198,8 @@ impl AsRef<str> for Email {
 
 #[cfg(test)]
 mod tests {
-    use super::Email;
-    use claims::assert_err;
+    use super::{Canonicalization, Email, EmailError};
+    use claims::{assert_err_eq, assert_ok, assert_ok_eq};
     use fake::Fake;
     use rand::{SeedableRng, rngs::StdRng};
 
@@ -82,18 +222,99 @@ mod tests {
     #[test]
     fn empty_string_is_rejected() {
         let email = "".to_string();
-        assert_err!(Email::try_from(email));
+        assert_err_eq!(Email::try_from(email), EmailError::Empty);
     }
 
     #[test]
     fn email_missing_at_symbol_is_rejected() {
         let email = "mainlzzzt.cc".to_string();
-        assert_err!(Email::try_from(email));
+        assert_err_eq!(Email::try_from(email), EmailError::Syntax);
     }
 
     #[test]
     fn email_missing_subject_is_rejected() {
         let email = "@lzzzt.cc".to_string();
-        assert_err!(Email::try_from(email));
+        assert_err_eq!(Email::try_from(email), EmailError::Syntax);
+    }
+
+    #[test]
+    fn surrounding_whitespace_is_trimmed() {
+        let email = assert_ok!(Email::try_from("  main@lzzzt.cc\n".to_string()));
+        assert_eq!(email.as_ref(), "main@lzzzt.cc");
+    }
+
+    #[test]
+    fn canonical_form_is_lowercased() {
+        let email = Email::try_from("Main@LZZZT.cc".to_string()).unwrap();
+
+        assert_ok_eq!(
+            email.canonical(Canonicalization::default()),
+            "main@lzzzt.cc"
+        );
+        assert_eq!(email.as_ref(), "Main@LZZZT.cc");
+    }
+
+    #[test]
+    fn canonical_form_uses_punycode_for_internationalized_domains() {
+        let email = Email::try_from("main@bücher.example".to_string()).unwrap();
+
+        assert_ok_eq!(
+            email.canonical(Canonicalization::default()),
+            "main@xn--bcher-kva.example"
+        );
+    }
+
+    #[test]
+    fn provider_rules_are_only_applied_when_enabled() {
+        let email = Email::try_from("Lz.Zzt+news@googlemail.com".to_string()).unwrap();
+
+        assert_ok_eq!(
+            email.canonical(Canonicalization::default()),
+            "lz.zzt+news@googlemail.com"
+        );
+        assert_ok_eq!(
+            email.canonical(Canonicalization {
+                provider_rules: true
+            }),
+            "lzzzt@gmail.com"
+        );
+    }
+
+    #[test]
+    fn provider_rules_keep_dots_for_providers_that_honour_them() {
+        let email = Email::try_from("lz.zzt+news@outlook.com".to_string()).unwrap();
+        let rules = Canonicalization {
+            provider_rules: true,
+        };
+
+        assert_ok_eq!(email.canonical(rules), "lz.zzt@outlook.com");
+    }
+
+    #[test]
+    fn provider_rules_leave_other_domains_alone() {
+        let email = Email::try_from("lz.zzt+news@lzzzt.cc".to_string()).unwrap();
+        let rules = Canonicalization {
+            provider_rules: true,
+        };
+
+        assert_ok_eq!(email.canonical(rules), "lz.zzt+news@lzzzt.cc");
+    }
+
+    #[test]
+    fn provider_rules_reject_an_empty_local_part() {
+        let rules = Canonicalization {
+            provider_rules: true,
+        };
+
+        for address in ["+news@gmail.com", "...@gmail.com", ".+news@googlemail.com"] {
+            let email = Email::try_from(address.to_string()).unwrap();
+            assert_err_eq!(email.canonical(rules), EmailError::EmptyCanonicalLocalPart);
+        }
+    }
+
+    #[test]
+    fn email_longer_than_254_characters_is_rejected() {
+        let email = format!("{}@lzzzt.cc", "a".repeat(246));
+        assert_err_eq!(Email::try_from(email), EmailError::TooLong);
     }
 }
@@ -0,0 +1,70 @@
+/// Longest slug derived from a title, before any number is appended.
+const SLUG_MAX_CHARS: usize = 80;
+
+/// The part of `/archive/{slug}` that names an issue: lowercase ASCII letters
+/// and digits separated by single dashes.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct IssueSlug(String);
+
+impl IssueSlug {
+    /// Keeps the letters and digits of `title`, every other run of characters
+    /// becoming a dash. Titles with nothing to keep are named `issue`.
+    pub fn from_title(title: &str) -> Self {
+        let mut slug = String::new();
+
+        for c in title.chars().map(|c| c.to_ascii_lowercase()) {
+            if c.is_ascii_lowercase() || c.is_ascii_digit() {
+                slug.push(c);
+            } else if !slug.is_empty() && !slug.ends_with('-') {
+                slug.push('-');
+            }
+
+            if slug.len() >= SLUG_MAX_CHARS {
+                break;
+            }
+        }
+
+        match slug.trim_end_matches('-') {
+            "" => Self("issue".into()),
+            slug => Self(slug.into()),
+        }
+    }
+
+    /// The slug of the `n`th issue with the same title, counting from 1.
+    pub fn nth(&self, n: usize) -> Self {
+        match n {
+            0 | 1 => self.clone(),
+            n => Self(format!("{}-{n}", self.0)),
+        }
+    }
+}
+
+impl AsRef<str> for IssueSlug {
+    fn as_ref(&self) -> &str {
+        &self.0
+    }
+}
+
+impl From<IssueSlug> for String {
+    fn from(value: IssueSlug) -> Self {
+        value.0
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::domain::IssueSlug;
+
+    #[test]
+    fn titles_become_dashed_words() {
+        let slug = IssueSlug::from_title("  Rust 2024: what's new?! ");
+        assert_eq!(slug.as_ref(), "rust-2024-what-s-new");
+        assert_eq!(slug.nth(3).as_ref(), "rust-2024-what-s-new-3");
+    }
+
+    #[test]
+    fn slugs_are_never_empty_nor_too_long() {
+        assert_eq!(IssueSlug::from_title("¡¿?!").as_ref(), "issue");
+        assert_eq!(IssueSlug::from_title(&"ab ".repeat(100)).as_ref().len(), 80);
+    }
+}
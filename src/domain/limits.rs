@@ -0,0 +1,25 @@
+//! Field limits shared by the domain types and the `subscriptions` table.
+//!
+//! Postgres `varchar(n)` counts characters, so every `*_MAX_CHARS` below must
+//! match the width of its column in `migrations/`, and every `*_MAX_BYTES`
+//! the matching `octet_length` check constraint. The API test suite compares
+//! these values against the migrated schema.
+
+/// Width of `subscriptions.name`.
+pub const NAME_MAX_CHARS: usize = 255;
+/// Limit of the `subscriptions_name_max_bytes` constraint.
+pub const NAME_MAX_BYTES: usize = 512;
+
+/// Width of `subscriptions.email`, which is also the longest forward path
+/// allowed by RFC 5321.
+pub const EMAIL_MAX_CHARS: usize = 254;
+/// Limit of the `subscriptions_email_max_bytes` constraint.
+pub const EMAIL_MAX_BYTES: usize = 254;
+
+// `subscriptions.attributes` is a `jsonb` object, so these are only checked
+// by `SubscriberAttributes`.
+
+/// Most attributes a subscriber can have.
+pub const ATTRIBUTES_MAX_COUNT: usize = 20;
+pub const ATTRIBUTE_KEY_MAX_CHARS: usize = 32;
+pub const ATTRIBUTE_VALUE_MAX_CHARS: usize = 256;
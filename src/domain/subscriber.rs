@@ -2,18 +2,104 @@ use crate::routes::FormData;
 
 use super::*;
 
+#[derive(Debug)]
 pub struct Subscriber {
     pub name: SubscriberName,
     pub email: Email,
+    pub attributes: SubscriberAttributes,
 }
 
+#[derive(Debug, Clone, PartialEq, Eq, thiserror::Error)]
+pub enum SubscriberFieldError {
+    #[error(transparent)]
+    Name(#[from] NameError),
+    #[error(transparent)]
+    Email(#[from] EmailError),
+    #[error(transparent)]
+    Attributes(#[from] AttributesError),
+}
+
+impl SubscriberFieldError {
+    pub fn field(&self) -> &'static str {
+        match self {
+            SubscriberFieldError::Name(_) => "name",
+            SubscriberFieldError::Email(_) => "email",
+            SubscriberFieldError::Attributes(_) => "attributes",
+        }
+    }
+
+    pub fn code(&self) -> &'static str {
+        match self {
+            SubscriberFieldError::Name(e) => e.code(),
+            SubscriberFieldError::Email(e) => e.code(),
+            SubscriberFieldError::Attributes(e) => e.code(),
+        }
+    }
+}
+
+/// Every field of a [`Subscriber`] that failed validation, in field order.
+#[derive(Debug, thiserror::Error)]
+#[error("The subscriber is not valid.")]
+pub struct SubscriberError(pub Vec<SubscriberFieldError>);
+
 impl TryFrom<FormData> for Subscriber {
-    type Error = String;
+    type Error = SubscriberError;
 
     fn try_from(value: FormData) -> Result<Self, Self::Error> {
-        Ok(Self {
-            name: value.name.try_into()?,
-            email: value.email.try_into()?,
-        })
+        let name = SubscriberName::try_from(value.name);
+        let email = Email::try_from(value.email);
+        let attributes = SubscriberAttributes::try_from(value.attributes);
+
+        match (name, email, attributes) {
+            (Ok(name), Ok(email), Ok(attributes)) => Ok(Self {
+                name,
+                email,
+                attributes,
+            }),
+            (name, email, attributes) => Err(SubscriberError(
+                [
+                    name.err().map(Into::into),
+                    email.err().map(Into::into),
+                    attributes.err().map(Into::into),
+                ]
+                .into_iter()
+                .flatten()
+                .collect(),
+            )),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use claims::{assert_err, assert_ok};
+
+    use crate::domain::{EmailError, NameError, Subscriber, SubscriberFieldError};
+    use crate::routes::FormData;
+
+    fn form(name: &str, email: &str) -> FormData {
+        FormData {
+            name: name.into(),
+            email: email.into(),
+            attributes: Default::default(),
+        }
+    }
+
+    #[test]
+    fn valid_form_data_is_parsed_successfully() {
+        assert_ok!(Subscriber::try_from(form("Lzzzt", "main@lzzzt.cc")));
+    }
+
+    #[test]
+    fn every_invalid_field_is_reported() {
+        let error = assert_err!(Subscriber::try_from(form("", "mainlzzzt.cc")));
+
+        assert_eq!(
+            error.0,
+            vec![
+                SubscriberFieldError::Name(NameError::Empty),
+                SubscriberFieldError::Email(EmailError::Syntax),
+            ]
+        );
     }
 }
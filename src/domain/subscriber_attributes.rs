@@ -0,0 +1,121 @@
+use std::collections::BTreeMap;
+
+use super::limits::{ATTRIBUTE_KEY_MAX_CHARS, ATTRIBUTE_VALUE_MAX_CHARS, ATTRIBUTES_MAX_COUNT};
+
+/// Custom values collected at sign-up, which newsletters can refer to as
+/// `{{ attributes.<key> }}`.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct SubscriberAttributes(BTreeMap<String, String>);
+
+#[derive(Debug, Clone, PartialEq, Eq, thiserror::Error)]
+pub enum AttributesError {
+    #[error("There must not be more than {ATTRIBUTES_MAX_COUNT} attributes.")]
+    TooMany,
+    #[error(
+        "`{0}` is not a valid attribute name: use up to {ATTRIBUTE_KEY_MAX_CHARS} lowercase \
+         letters, digits or underscores."
+    )]
+    InvalidKey(String),
+    #[error("The `{0}` attribute must not be longer than {ATTRIBUTE_VALUE_MAX_CHARS} characters.")]
+    TooLong(String),
+}
+
+impl AttributesError {
+    pub fn code(&self) -> &'static str {
+        match self {
+            AttributesError::TooMany => "too_many",
+            AttributesError::InvalidKey(_) => "invalid_key",
+            AttributesError::TooLong(_) => "too_long",
+        }
+    }
+}
+
+/// Whether `key` can name an attribute, and be used in a template.
+pub fn is_valid_attribute_key(key: &str) -> bool {
+    !key.is_empty()
+        && key.chars().count() <= ATTRIBUTE_KEY_MAX_CHARS
+        && key
+            .chars()
+            .all(|c| c.is_ascii_lowercase() || c.is_ascii_digit() || c == '_')
+}
+
+impl TryFrom<BTreeMap<String, String>> for SubscriberAttributes {
+    type Error = AttributesError;
+
+    fn try_from(value: BTreeMap<String, String>) -> Result<Self, Self::Error> {
+        if value.len() > ATTRIBUTES_MAX_COUNT {
+            return Err(AttributesError::TooMany);
+        }
+
+        for (key, value) in &value {
+            if !is_valid_attribute_key(key) {
+                return Err(AttributesError::InvalidKey(key.clone()));
+            }
+
+            if value.chars().count() > ATTRIBUTE_VALUE_MAX_CHARS {
+                return Err(AttributesError::TooLong(key.clone()));
+            }
+        }
+
+        Ok(Self(value))
+    }
+}
+
+impl AsRef<BTreeMap<String, String>> for SubscriberAttributes {
+    fn as_ref(&self) -> &BTreeMap<String, String> {
+        &self.0
+    }
+}
+
+impl From<SubscriberAttributes> for BTreeMap<String, String> {
+    fn from(value: SubscriberAttributes) -> Self {
+        value.0
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::BTreeMap;
+
+    use claims::{assert_err_eq, assert_ok};
+
+    use crate::domain::{AttributesError, SubscriberAttributes};
+
+    fn attributes(pairs: &[(&str, &str)]) -> BTreeMap<String, String> {
+        pairs
+            .iter()
+            .map(|(k, v)| (k.to_string(), v.to_string()))
+            .collect()
+    }
+
+    #[test]
+    fn keys_must_be_usable_in_templates() {
+        assert_ok!(SubscriberAttributes::try_from(attributes(&[(
+            "company_2",
+            "Lzzzt"
+        )])));
+        assert_err_eq!(
+            SubscriberAttributes::try_from(attributes(&[("Company", "Lzzzt")])),
+            AttributesError::InvalidKey("Company".into())
+        );
+        assert_err_eq!(
+            SubscriberAttributes::try_from(attributes(&[("", "Lzzzt")])),
+            AttributesError::InvalidKey("".into())
+        );
+    }
+
+    #[test]
+    fn values_and_counts_are_bounded() {
+        let long = "a".repeat(257);
+        assert_err_eq!(
+            SubscriberAttributes::try_from(attributes(&[("bio", &long)])),
+            AttributesError::TooLong("bio".into())
+        );
+
+        let many: BTreeMap<_, _> = (0..21).map(|i| (format!("k{i}"), String::new())).collect();
+        assert_err_eq!(
+            SubscriberAttributes::try_from(many),
+            AttributesError::TooMany
+        );
+    }
+}
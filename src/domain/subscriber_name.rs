@@ -1,25 +1,49 @@
-use unicode_segmentation::UnicodeSegmentation;
+use super::limits::{NAME_MAX_BYTES, NAME_MAX_CHARS};
+
+const FORBIDDEN_CHARACTERS: [char; 9] = ['/', '(', ')', '"', '<', '>', '\\', '{', '}'];
 
 #[derive(Debug)]
 pub struct SubscriberName(String);
 
+#[derive(Debug, Clone, PartialEq, Eq, thiserror::Error)]
+pub enum NameError {
+    #[error("The name must not be empty.")]
+    Empty,
+    #[error(
+        "The name must not be longer than {NAME_MAX_CHARS} characters or {NAME_MAX_BYTES} bytes."
+    )]
+    TooLong,
+    #[error("The name must not contain `{0}`.")]
+    ForbiddenChar(char),
+}
+
+impl NameError {
+    pub fn code(&self) -> &'static str {
+        match self {
+            NameError::Empty => "empty",
+            NameError::TooLong => "too_long",
+            NameError::ForbiddenChar(_) => "forbidden_char",
+        }
+    }
+}
+
 impl TryFrom<String> for SubscriberName {
-    type Error = String;
+    type Error = NameError;
 
     fn try_from(value: String) -> Result<Self, Self::Error> {
-        let is_empty_or_whitespace = value.trim().is_empty();
-
-        let is_too_long = value.graphemes(true).count() > 255;
+        if value.trim().is_empty() {
+            return Err(NameError::Empty);
+        }
 
-        const FORBIDDEN_CHARACTERS: [char; 9] = ['/', '(', ')', '"', '<', '>', '\\', '{', '}'];
-        let contains_forbidden_characters =
-            value.chars().any(|c| FORBIDDEN_CHARACTERS.contains(&c));
+        if value.chars().count() > NAME_MAX_CHARS || value.len() > NAME_MAX_BYTES {
+            return Err(NameError::TooLong);
+        }
 
-        if is_empty_or_whitespace || is_too_long || contains_forbidden_characters {
-            Err(format!("Subscriber name: {value} is not valid."))
-        } else {
-            Ok(SubscriberName(value))
+        if let Some(c) = value.chars().find(|c| FORBIDDEN_CHARACTERS.contains(c)) {
+            return Err(NameError::ForbiddenChar(c));
         }
+
+        Ok(SubscriberName(value))
     }
 }
 
@@ -37,38 +61,47 @@ impl From<SubscriberName> for String {
 
 #[cfg(test)]
 mod tests {
-    use crate::domain::SubscriberName;
-    use claims::{assert_err, assert_ok};
+    use crate::domain::limits::{NAME_MAX_BYTES, NAME_MAX_CHARS};
+    use crate::domain::{NameError, SubscriberName};
+    use claims::{assert_err_eq, assert_ok};
 
     #[test]
-    fn a_255_grapheme_long_name_is_valid() {
-        let name = "ё".repeat(255);
+    fn a_name_at_the_character_limit_and_within_the_byte_limit_is_valid() {
+        let name = "ё".repeat(NAME_MAX_CHARS);
+        assert!(name.len() <= NAME_MAX_BYTES);
         assert_ok!(SubscriberName::try_from(name));
     }
 
     #[test]
-    fn a_name_longer_than_255_graphemes_is_rejected() {
-        let name = "a".repeat(256);
-        assert_err!(SubscriberName::try_from(name));
+    fn a_name_longer_than_the_character_limit_is_rejected() {
+        let name = "a".repeat(NAME_MAX_CHARS + 1);
+        assert_err_eq!(SubscriberName::try_from(name), NameError::TooLong);
+    }
+
+    #[test]
+    fn a_name_within_the_character_limit_but_over_the_byte_limit_is_rejected() {
+        let name = "😀".repeat(NAME_MAX_BYTES / '😀'.len_utf8() + 1);
+        assert!(name.chars().count() <= NAME_MAX_CHARS);
+        assert_err_eq!(SubscriberName::try_from(name), NameError::TooLong);
     }
 
     #[test]
     fn whitespace_only_names_are_rejected() {
         let name = " ".to_string();
-        assert_err!(SubscriberName::try_from(name));
+        assert_err_eq!(SubscriberName::try_from(name), NameError::Empty);
     }
 
     #[test]
     fn empty_string_is_rejected() {
         let name = "".to_string();
-        assert_err!(SubscriberName::try_from(name));
+        assert_err_eq!(SubscriberName::try_from(name), NameError::Empty);
     }
 
     #[test]
     fn names_containing_an_invalid_character_are_rejected() {
-        for name in &['/', '(', ')', '"', '<', '>', '\\', '{', '}'] {
-            let name = name.to_string();
-            assert_err!(SubscriberName::try_from(name));
+        for c in &['/', '(', ')', '"', '<', '>', '\\', '{', '}'] {
+            let name = c.to_string();
+            assert_err_eq!(SubscriberName::try_from(name), NameError::ForbiddenChar(*c));
         }
     }
 
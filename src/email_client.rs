@@ -1,134 +1,500 @@
+mod circuit_breaker;
+mod mail_catcher;
+mod mailgun;
+mod message;
+mod postmark;
+mod rate_limit;
+mod retry;
+mod sendgrid;
+mod smtp;
+
+use std::collections::BTreeMap;
 use std::time::Duration;
 
-use reqwest::{Client, Url};
-use secrecy::{ExposeSecret, SecretString};
+use async_trait::async_trait;
+use chrono::{DateTime, Utc};
+
+use crate::config::{EmailClientConfig, EmailProvider};
+use crate::domain::Email;
+use crate::template::{NewsletterTemplate, Recipient};
+use crate::unsubscribe::UnsubscribeLinks;
+
+pub use circuit_breaker::{CircuitBreaker, CircuitState, CircuitStatus};
+pub use mail_catcher::{CaughtEmail, MailCatcher};
+pub use mailgun::Mailgun;
+pub use message::Message;
+pub use postmark::Postmark;
+pub use rate_limit::RateLimiter;
+pub use retry::RetryPolicy;
+pub use sendgrid::SendGrid;
+pub use smtp::Smtp;
+
+pub type Headers = BTreeMap<&'static str, String>;
+
+/// The same email for many recipients, each with their own values for the
+/// substitution keys found in its subject and content.
+pub struct OutgoingBatch<'a> {
+    pub from: &'a Email,
+    pub subject: &'a str,
+    pub raw_content: &'a str,
+    pub html_content: &'a str,
+    pub recipients: &'a [BatchRecipient],
+}
+
+#[derive(Debug, Clone)]
+pub struct BatchRecipient {
+    pub to: Email,
+    pub substitutions: BTreeMap<String, String>,
+    pub headers: Headers,
+}
+
+impl BatchRecipient {
+    pub fn new(to: Email, substitutions: BTreeMap<String, String>) -> Self {
+        Self {
+            to,
+            substitutions,
+            headers: Headers::new(),
+        }
+    }
+}
+
+impl OutgoingBatch<'_> {
+    /// The email `recipient` gets, with their substitutions filled in.
+    pub fn personalize(&self, recipient: &BatchRecipient) -> Message {
+        let substitute = |text: &str| substitute(text, &recipient.substitutions);
+
+        let mut message = Message::new(self.from.clone(), recipient.to.clone())
+            .with_subject(substitute(self.subject))
+            .with_text(substitute(self.raw_content))
+            .with_html(substitute(self.html_content));
+        message.headers = recipient.headers.clone();
+
+        message
+    }
+}
+
+/// Replaces every key in `text` with its value, in a single pass so that
+/// values are never substituted themselves.
+fn substitute(text: &str, substitutions: &BTreeMap<String, String>) -> String {
+    let mut out = String::with_capacity(text.len());
+    let mut rest = text;
+
+    'outer: while !rest.is_empty() {
+        for (key, value) in substitutions {
+            if !key.is_empty()
+                && let Some(after) = rest.strip_prefix(key.as_str())
+            {
+                out.push_str(value);
+                rest = after;
+                continue 'outer;
+            }
+        }
+
+        let mut chars = rest.chars();
+        out.extend(chars.next());
+        rest = chars.as_str();
+    }
+
+    out
+}
+
+/// A backend able to deliver a [`Message`], usually a provider's API.
+#[async_trait]
+pub trait EmailSender: Send + Sync {
+    async fn send(&self, message: &Message) -> Result<(), SendError>;
+
+    /// Most recipients [`EmailSender::send_batch`] is handed at once.
+    fn max_batch_size(&self) -> usize {
+        1
+    }
 
-use crate::{config::EmailClientConfig, domain::Email};
+    /// Delivers the batch to all of its recipients, or fails as a whole.
+    ///
+    /// Backends without a batch API send one email per recipient, which is
+    /// why they only get one recipient at a time by default.
+    async fn send_batch(&self, batch: &OutgoingBatch<'_>) -> Result<(), SendError> {
+        for recipient in batch.recipients {
+            self.send(&batch.personalize(recipient)).await?;
+        }
+
+        Ok(())
+    }
+}
+
+/// How one of the requests made by [`EmailClient::send_batch`] went, for all
+/// of the recipients it carried.
+#[derive(Debug)]
+pub struct BatchResult {
+    pub recipients: Vec<Email>,
+    pub result: Result<(), SendError>,
+}
+
+#[derive(Debug, thiserror::Error)]
+pub enum SendError {
+    #[error(transparent)]
+    Http(#[from] reqwest::Error),
+    #[error("The provider answered {status}.")]
+    Rejected {
+        status: reqwest::StatusCode,
+        retry_after: Option<Duration>,
+    },
+    /// The backend has no way to express part of the message.
+    #[error("The email provider does not support {0}.")]
+    Unsupported(&'static str),
+    #[error("Sending took longer than the retry deadline.")]
+    DeadlineExceeded,
+    /// Nothing was sent, the provider has been failing lately.
+    #[error("The email provider is unavailable until {retry_at}.")]
+    CircuitOpen { retry_at: DateTime<Utc> },
+    #[error(transparent)]
+    Smtp(#[from] lettre::transport::smtp::Error),
+    #[error(transparent)]
+    Message(#[from] lettre::error::Error),
+    #[error(transparent)]
+    Address(#[from] lettre::address::AddressError),
+    /// Nothing was sent, the daily quota has been used up.
+    #[error("The daily email quota is used up until {resets_at}.")]
+    QuotaExceeded { resets_at: DateTime<Utc> },
+    #[error(transparent)]
+    Database(#[from] sqlx::Error),
+}
 
 pub struct EmailClient {
     sender: Email,
-    base_url: Url,
-    http_client: Client,
-    token: SecretString,
-    timeout: Duration,
+    backend: Box<dyn EmailSender>,
+    unsubscribe_links: Option<UnsubscribeLinks>,
+    rate_limiter: Option<RateLimiter>,
+    retry_policy: RetryPolicy,
+    circuit_breaker: Option<CircuitBreaker>,
 }
 
 impl EmailClient {
-    pub fn new(base_url: Url, sender: Email, token: SecretString, timeout_ms: u32) -> Self {
+    pub fn new(sender: Email, backend: impl EmailSender + 'static) -> Self {
         Self {
-            base_url,
             sender,
-            timeout: Duration::from_millis(timeout_ms as u64),
-            token: SecretString::from(format!("Bearer {}", token.expose_secret())),
-            http_client: Client::new(),
+            backend: Box::new(backend),
+            unsubscribe_links: None,
+            rate_limiter: None,
+            retry_policy: RetryPolicy::none(),
+            circuit_breaker: None,
         }
     }
 
-    pub async fn send_email(
+    /// Fails sends right away while the provider is down, instead of waiting
+    /// for each one to time out.
+    pub fn with_circuit_breaker(mut self, circuit_breaker: CircuitBreaker) -> Self {
+        self.circuit_breaker = Some(circuit_breaker);
+        self
+    }
+
+    /// `None` without a circuit breaker.
+    pub fn circuit_status(&self) -> Option<CircuitStatus> {
+        self.circuit_breaker.as_ref().map(CircuitBreaker::status)
+    }
+
+    /// Retries transient failures of every send; there are none by default.
+    pub fn with_retry_policy(mut self, retry_policy: RetryPolicy) -> Self {
+        self.retry_policy = retry_policy;
+        self
+    }
+
+    /// Holds every send, transactional or not, to the limiter's rate and
+    /// daily quota.
+    pub fn with_rate_limiter(mut self, rate_limiter: RateLimiter) -> Self {
+        self.rate_limiter = Some(rate_limiter);
+        self
+    }
+
+    /// Adds RFC 8058 one-click `List-Unsubscribe` headers to every email sent.
+    pub fn with_unsubscribe_links(mut self, unsubscribe_links: UnsubscribeLinks) -> Self {
+        self.unsubscribe_links = Some(unsubscribe_links);
+        self
+    }
+
+    pub fn sender(&self) -> &Email {
+        &self.sender
+    }
+
+    /// An empty message from the sender, to build on and hand to
+    /// [`EmailClient::send_message`].
+    pub fn message(&self, to: Email) -> Message {
+        Message::new(self.sender.clone(), to)
+    }
+
+    /// Builds the email [`EmailClient::send_email`] would hand to the backend,
+    /// so that it can be previewed before it goes out.
+    pub fn render(
         &self,
         to: Email,
         subject: impl AsRef<str>,
         raw_content: impl AsRef<str>,
         html_content: impl AsRef<str>,
-    ) -> Result<(), reqwest::Error> {
-        let url = self
-            .base_url
-            .join("/v3/mail/send")
-            .expect("Failed to join url");
-
-        let body = request::Body::new(
-            &self.sender,
-            to,
-            subject.as_ref(),
-            raw_content.as_ref(),
-            html_content.as_ref(),
-        );
+    ) -> Message {
+        self.prepare(
+            self.message(to)
+                .with_subject(subject.as_ref())
+                .with_text(raw_content.as_ref())
+                .with_html(html_content.as_ref()),
+        )
+    }
 
-        let _ = self
-            .http_client
-            .post(url)
-            .json(&body)
-            .header("Authorization", self.token.expose_secret())
-            .timeout(self.timeout)
-            .send()
-            .await?
-            .error_for_status()?;
+    /// Adds the `List-Unsubscribe` headers to messages for a single
+    /// recipient, since the link only unsubscribes them.
+    fn prepare(&self, mut message: Message) -> Message {
+        if let [to] = message.to.as_slice()
+            && message.cc.is_empty()
+            && message.bcc.is_empty()
+        {
+            self.add_headers(to, &mut message.headers);
+        }
 
-        Ok(())
+        message
     }
-}
 
-impl From<EmailClientConfig> for EmailClient {
-    fn from(value: EmailClientConfig) -> Self {
-        Self::new(value.base_url, value.sender, value.token, value.timeout_ms)
+    /// Empty when unsubscribe links are not set up.
+    fn unsubscribe_url(&self, to: &Email) -> String {
+        self.unsubscribe_links
+            .as_ref()
+            .map(|links| links.url_for(to))
+            .unwrap_or_default()
     }
-}
 
-mod request {
-    use serde::Serialize;
-
-    use crate::domain::Email;
-
-    #[derive(Serialize)]
-    pub struct Body<'a> {
-        personalizations: Vec<Personalization>,
-        from: &'a Email,
-        subject: &'a str,
-        content: Vec<Content<'a>>,
-    }
-
-    impl<'a> Body<'a> {
-        pub fn new(
-            from: &'a Email,
-            to: Email,
-            subject: &'a str,
-            raw_content: &'a str,
-            html_content: &'a str,
-        ) -> Self {
-            Body {
-                personalizations: vec![Personalization::new().add_one(to)],
-                from,
-                subject,
-                content: vec![Content::text(raw_content), Content::html(html_content)],
-            }
+    /// Leaves alone the headers that are already set.
+    fn add_headers(&self, to: &Email, headers: &mut Headers) {
+        if let Some(links) = &self.unsubscribe_links {
+            headers
+                .entry("List-Unsubscribe")
+                .or_insert_with(|| format!("<{}>", links.url_for(to)));
+            headers
+                .entry("List-Unsubscribe-Post")
+                .or_insert_with(|| "List-Unsubscribe=One-Click".into());
         }
     }
 
-    #[derive(Serialize)]
-    struct Personalization {
-        to: Vec<Email>,
+    pub async fn send_email(
+        &self,
+        to: Email,
+        subject: impl AsRef<str>,
+        raw_content: impl AsRef<str>,
+        html_content: impl AsRef<str>,
+    ) -> Result<(), SendError> {
+        let email = self.render(to, subject, raw_content, html_content);
+
+        self.deliver(&email).await
+    }
+
+    /// Sends the message as built, with the `List-Unsubscribe` headers
+    /// [`EmailClient::send_email`] would add when it has a single recipient.
+    pub async fn send_message(&self, message: Message) -> Result<(), SendError> {
+        let message = self.prepare(message);
+
+        self.deliver(&message).await
+    }
+
+    /// Fills in the newsletter for one recipient and renders it like
+    /// [`EmailClient::send_email`] would.
+    pub fn render_newsletter(
+        &self,
+        newsletter: &NewsletterTemplate,
+        recipient: Recipient,
+    ) -> Message {
+        let unsubscribe_url = self.unsubscribe_url(&recipient.email);
+        let personalized = newsletter.render(&recipient, &unsubscribe_url);
+
+        self.render(
+            recipient.email,
+            personalized.subject,
+            personalized.text_content,
+            personalized.html_content,
+        )
     }
 
-    impl Personalization {
-        fn new() -> Self {
-            Self { to: vec![] }
+    pub async fn send_newsletter(
+        &self,
+        newsletter: &NewsletterTemplate,
+        recipient: Recipient,
+    ) -> Result<(), SendError> {
+        let email = self.render_newsletter(newsletter, recipient);
+
+        self.deliver(&email).await
+    }
+
+    async fn deliver(&self, message: &Message) -> Result<(), SendError> {
+        self.check_circuit()?;
+        if let Some(rate_limiter) = &self.rate_limiter {
+            rate_limiter.acquire(message.recipients().count()).await?;
         }
 
-        fn add_one(mut self, email: Email) -> Self {
-            self.to.push(email);
-            self
+        self.retry_policy
+            .run(|| self.attempt(self.backend.send(message)))
+            .await
+    }
+
+    /// Saves the quota and the wait for the rate limit when nothing would go
+    /// out anyway.
+    fn check_circuit(&self) -> Result<(), SendError> {
+        match &self.circuit_breaker {
+            Some(circuit_breaker) => circuit_breaker.check(),
+            None => Ok(()),
         }
     }
 
-    #[derive(Serialize)]
-    #[serde(tag = "type")]
-    enum Content<'a> {
-        #[serde(rename = "text/plain")]
-        Text { value: &'a str },
-        #[serde(rename = "text/html")]
-        Html { value: &'a str },
+    async fn attempt(
+        &self,
+        send: impl Future<Output = Result<(), SendError>>,
+    ) -> Result<(), SendError> {
+        match &self.circuit_breaker {
+            Some(circuit_breaker) => circuit_breaker.call(send).await,
+            None => send.await,
+        }
     }
 
-    impl<'a> Content<'a> {
-        #[inline]
-        fn text(value: &'a str) -> Self {
-            Self::Text { value }
+    /// Sends the same email to every recipient, in as few requests as the
+    /// backend allows, one after the other. Each recipient gets their own
+    /// substitutions and `List-Unsubscribe` headers.
+    ///
+    /// A request that only partly fits in the daily quota goes out to as many
+    /// recipients as fit, and the others are reported as
+    /// [`SendError::QuotaExceeded`] right after it.
+    pub async fn send_batch(
+        &self,
+        subject: impl AsRef<str>,
+        raw_content: impl AsRef<str>,
+        html_content: impl AsRef<str>,
+        mut recipients: Vec<BatchRecipient>,
+    ) -> Vec<BatchResult> {
+        for recipient in &mut recipients {
+            self.add_headers(&recipient.to, &mut recipient.headers);
         }
 
-        #[inline]
-        fn html(value: &'a str) -> Self {
-            Self::Html { value }
+        let mut results = vec![];
+        for chunk in recipients.chunks(self.max_batch_size()) {
+            let granted = match self.reserve_batch(chunk.len()).await {
+                Ok(granted) => granted,
+                Err(e) => {
+                    results.push(BatchResult {
+                        recipients: chunk.iter().map(|r| r.to.clone()).collect(),
+                        result: Err(e),
+                    });
+                    continue;
+                }
+            };
+            let (chunk, over_quota) = chunk.split_at(granted);
+
+            let batch = OutgoingBatch {
+                from: &self.sender,
+                subject: subject.as_ref(),
+                raw_content: raw_content.as_ref(),
+                html_content: html_content.as_ref(),
+                recipients: chunk,
+            };
+
+            results.push(BatchResult {
+                recipients: chunk.iter().map(|r| r.to.clone()).collect(),
+                result: self
+                    .retry_policy
+                    .run(|| self.attempt(self.backend.send_batch(&batch)))
+                    .await,
+            });
+
+            if !over_quota.is_empty() {
+                results.push(BatchResult {
+                    recipients: over_quota.iter().map(|r| r.to.clone()).collect(),
+                    result: Err(rate_limit::quota_exceeded()),
+                });
+            }
         }
+
+        results
+    }
+
+    /// Most recipients a single request of [`EmailClient::send_batch`]
+    /// carries, which is 1 for backends without a batch API.
+    pub fn max_batch_size(&self) -> usize {
+        match &self.rate_limiter {
+            Some(rate_limiter) => rate_limiter.max_batch_size(),
+            None => usize::MAX,
+        }
+        .min(self.backend.max_batch_size())
+        .max(1)
+    }
+
+    /// How many of the `n` recipients of a request can go out.
+    async fn reserve_batch(&self, n: usize) -> Result<usize, SendError> {
+        self.check_circuit()?;
+
+        match &self.rate_limiter {
+            Some(rate_limiter) => rate_limiter.acquire_up_to(n).await,
+            None => Ok(n),
+        }
+    }
+
+    /// Sends the newsletter to every recipient through
+    /// [`EmailClient::send_batch`], each filled in like
+    /// [`EmailClient::render_newsletter`] would.
+    pub async fn send_newsletter_batch(
+        &self,
+        newsletter: &NewsletterTemplate,
+        recipients: Vec<Recipient>,
+    ) -> Vec<BatchResult> {
+        let keyed = newsletter.to_keys();
+        let recipients = recipients
+            .into_iter()
+            .map(|recipient| {
+                let unsubscribe_url = self.unsubscribe_url(&recipient.email);
+                let substitutions = newsletter.substitutions(&recipient, &unsubscribe_url);
+
+                BatchRecipient::new(recipient.email, substitutions)
+            })
+            .collect();
+
+        self.send_batch(
+            keyed.subject,
+            keyed.text_content,
+            keyed.html_content,
+            recipients,
+        )
+        .await
+    }
+}
+
+#[derive(Debug, thiserror::Error)]
+pub enum EmailClientConfigError {
+    #[error("Invalid SMTP configuration: {0}")]
+    Smtp(#[from] lettre::transport::smtp::Error),
+    /// [`App::build`](crate::App::build) sets the mail catcher up itself, as
+    /// it keeps a handle to it for `/dev/mailbox`.
+    #[error("The mail catcher can only be set up by the app.")]
+    MailCatcher,
+}
+
+impl TryFrom<EmailClientConfig> for EmailClient {
+    type Error = EmailClientConfigError;
+
+    fn try_from(value: EmailClientConfig) -> Result<Self, Self::Error> {
+        let timeout = Duration::from_millis(value.timeout_ms as u64);
+
+        let email_client = match value.provider {
+            EmailProvider::SendGrid => Self::new(
+                value.sender,
+                SendGrid::new(value.base_url, value.token, timeout),
+            ),
+            EmailProvider::Postmark => Self::new(
+                value.sender,
+                Postmark::new(value.base_url, value.token, timeout),
+            ),
+            EmailProvider::Mailgun => {
+                let domain = value.sender.domain().to_string();
+                Self::new(
+                    value.sender,
+                    Mailgun::new(value.base_url, domain, value.token, timeout),
+                )
+            }
+            EmailProvider::Smtp => Self::new(value.sender, Smtp::new(&value.smtp, timeout)?),
+            EmailProvider::MailCatcher => return Err(EmailClientConfigError::MailCatcher),
+        };
+
+        Ok(email_client)
     }
 }
 
@@ -136,6 +502,7 @@ mod request {
 mod tests {
     use std::time::Duration;
 
+    use chrono::{TimeZone, Utc};
     use claims::{assert_err, assert_ok};
     use fake::{
         Fake, Faker,
@@ -147,7 +514,12 @@ mod tests {
         matchers::{any, header, header_exists, method},
     };
 
-    use crate::{domain::Email, email_client::EmailClient};
+    use crate::{
+        config::RetryConfig,
+        domain::Email,
+        email_client::{BatchRecipient, EmailClient, MailCatcher, RetryPolicy, SendGrid},
+        unsubscribe::UnsubscribeLinks,
+    };
 
     struct SendEmailBodyMatcher;
 
@@ -201,12 +573,13 @@ mod tests {
     }
 
     fn email_client(uri: String) -> EmailClient {
-        EmailClient::new(
+        let backend = SendGrid::new(
             uri.parse().unwrap(),
-            email(),
             SecretString::from(Faker.fake::<String>()),
-            200,
-        )
+            Duration::from_millis(200),
+        );
+
+        EmailClient::new(email(), backend)
     }
 
     #[tokio::test]
@@ -231,6 +604,87 @@ mod tests {
         assert_ok!(result);
     }
 
+    #[tokio::test]
+    async fn send_email_adds_one_click_unsubscribe_headers() {
+        let mock_server = MockServer::start().await;
+
+        let links = UnsubscribeLinks::new("http://127.0.0.1", SecretString::from("secret"));
+        let email_client = email_client(mock_server.uri()).with_unsubscribe_links(links);
+
+        Mock::given(any())
+            .respond_with(ResponseTemplate::new(200))
+            .expect(1)
+            .mount(&mock_server)
+            .await;
+
+        email_client
+            .send_email(email(), &subject(), &content(), &content())
+            .await
+            .unwrap();
+
+        let request = &mock_server.received_requests().await.unwrap()[0];
+        let body: serde_json::Value = serde_json::from_slice(&request.body).unwrap();
+        let headers = &body["personalizations"][0]["headers"];
+
+        assert!(
+            headers["List-Unsubscribe"].as_str().is_some_and(
+                |h| h.starts_with("<http://127.0.0.1/subscriptions/unsubscribe?token=")
+            )
+        );
+        assert_eq!(
+            headers["List-Unsubscribe-Post"],
+            "List-Unsubscribe=One-Click"
+        );
+    }
+
+    #[tokio::test]
+    async fn send_message_serializes_every_field_for_sendgrid() {
+        let mock_server = MockServer::start().await;
+
+        let links = UnsubscribeLinks::new("http://127.0.0.1", SecretString::from("secret"));
+        let email_client = email_client(mock_server.uri()).with_unsubscribe_links(links);
+
+        Mock::given(any())
+            .respond_with(ResponseTemplate::new(200))
+            .expect(1)
+            .mount(&mock_server)
+            .await;
+
+        let to = |address: &str| Email::try_from(address.to_string()).unwrap();
+        let send_at = Utc.with_ymd_and_hms(2026, 10, 20, 9, 0, 0).unwrap();
+        let message = email_client
+            .message(to("first@lzzzt.cc"))
+            .with_subject("Subject")
+            .add_to(to("second@lzzzt.cc"))
+            .add_cc(to("cc@lzzzt.cc"))
+            .add_bcc(to("bcc@lzzzt.cc"))
+            .with_reply_to(to("reply@lzzzt.cc"))
+            .with_header("X-Entity-Ref-ID", "42")
+            .add_category("newsletter")
+            .with_custom_arg("issue_id", "42")
+            .with_send_at(send_at);
+
+        assert_ok!(email_client.send_message(message).await);
+
+        let request = &mock_server.received_requests().await.unwrap()[0];
+        let body: serde_json::Value = serde_json::from_slice(&request.body).unwrap();
+
+        assert_eq!(
+            body["personalizations"],
+            serde_json::json!([{
+                "to": [{ "email": "first@lzzzt.cc" }, { "email": "second@lzzzt.cc" }],
+                "cc": [{ "email": "cc@lzzzt.cc" }],
+                "bcc": [{ "email": "bcc@lzzzt.cc" }],
+                // No `List-Unsubscribe`, the link would only work for one of them.
+                "headers": { "X-Entity-Ref-ID": "42" },
+            }])
+        );
+        assert_eq!(body["reply_to"]["email"], "reply@lzzzt.cc");
+        assert_eq!(body["categories"], serde_json::json!(["newsletter"]));
+        assert_eq!(body["custom_args"], serde_json::json!({ "issue_id": "42" }));
+        assert_eq!(body["send_at"], send_at.timestamp());
+    }
+
     #[tokio::test]
     async fn send_email_succeeds_if_the_server_returns_200() {
         let mock_server = MockServer::start().await;
@@ -287,4 +741,188 @@ mod tests {
 
         assert_err!(result);
     }
+
+    fn batch_recipient(name: &str) -> BatchRecipient {
+        BatchRecipient::new(email(), [("-name-".to_string(), name.to_string())].into())
+    }
+
+    #[tokio::test]
+    async fn send_batch_fits_up_to_1000_recipients_per_request() {
+        let mock_server = MockServer::start().await;
+
+        let email_client = email_client(mock_server.uri());
+
+        Mock::given(any())
+            .respond_with(ResponseTemplate::new(200))
+            .expect(2)
+            .mount(&mock_server)
+            .await;
+
+        let recipients = (0..1001).map(|i| batch_recipient(&i.to_string())).collect();
+        let results = email_client
+            .send_batch("Hi -name-", "Hi -name-", "Hi -name-", recipients)
+            .await;
+
+        assert_eq!(results.len(), 2);
+        assert_eq!(results[0].recipients.len(), 1000);
+        assert_eq!(results[1].recipients.len(), 1);
+        assert!(results.iter().all(|r| r.result.is_ok()));
+
+        let request = &mock_server.received_requests().await.unwrap()[1];
+        let body: serde_json::Value = serde_json::from_slice(&request.body).unwrap();
+        assert_eq!(body["subject"], "Hi -name-");
+        assert_eq!(
+            body["personalizations"][0]["substitutions"],
+            serde_json::json!({ "-name-": "1000" })
+        );
+    }
+
+    #[tokio::test]
+    async fn send_batch_reports_each_request_on_its_own() {
+        let mock_server = MockServer::start().await;
+
+        let email_client = email_client(mock_server.uri());
+
+        Mock::given(any())
+            .respond_with(ResponseTemplate::new(500))
+            .up_to_n_times(1)
+            .mount(&mock_server)
+            .await;
+        Mock::given(any())
+            .respond_with(ResponseTemplate::new(200))
+            .mount(&mock_server)
+            .await;
+
+        let recipients = (0..1500).map(|i| batch_recipient(&i.to_string())).collect();
+        let results = email_client
+            .send_batch(subject(), content(), content(), recipients)
+            .await;
+
+        assert_err!(&results[0].result);
+        assert_ok!(&results[1].result);
+        assert_eq!(results[1].recipients.len(), 500);
+    }
+
+    #[tokio::test]
+    async fn send_batch_sends_one_email_per_recipient_without_a_batch_api() {
+        let mail_catcher = MailCatcher::default();
+        let email_client = EmailClient::new(email(), mail_catcher.clone());
+
+        let results = email_client
+            .send_batch(
+                "Hi -name-",
+                "Hi -name-",
+                "<p>Hi -name-</p>",
+                vec![batch_recipient("Lzzzt"), batch_recipient("-name-")],
+            )
+            .await;
+
+        assert_eq!(results.len(), 2);
+        // Newest first.
+        let messages = mail_catcher.messages();
+        assert_eq!(messages[1].subject, "Hi Lzzzt");
+        assert_eq!(messages[1].html_content, "<p>Hi Lzzzt</p>");
+        // Values are not substituted again.
+        assert_eq!(messages[0].raw_content, "Hi -name-");
+    }
+
+    fn retrying(email_client: EmailClient, deadline_ms: u64) -> EmailClient {
+        email_client.with_retry_policy(RetryPolicy::from(&RetryConfig {
+            max_attempts: 5,
+            base_backoff_ms: 10,
+            max_backoff_ms: 50,
+            deadline_ms,
+        }))
+    }
+
+    #[tokio::test]
+    async fn send_email_retries_transient_failures() {
+        let mock_server = MockServer::start().await;
+
+        let email_client = retrying(email_client(mock_server.uri()), 5_000);
+
+        Mock::given(any())
+            .respond_with(ResponseTemplate::new(503))
+            .up_to_n_times(2)
+            .expect(2)
+            .mount(&mock_server)
+            .await;
+        Mock::given(any())
+            .respond_with(ResponseTemplate::new(200))
+            .expect(1)
+            .mount(&mock_server)
+            .await;
+
+        let result = email_client
+            .send_email(email(), &subject(), &content(), &content())
+            .await;
+
+        assert_ok!(result);
+    }
+
+    #[tokio::test]
+    async fn send_email_does_not_retry_rejected_emails() {
+        let mock_server = MockServer::start().await;
+
+        let email_client = retrying(email_client(mock_server.uri()), 5_000);
+
+        Mock::given(any())
+            .respond_with(ResponseTemplate::new(400))
+            .expect(1)
+            .mount(&mock_server)
+            .await;
+
+        let result = email_client
+            .send_email(email(), &subject(), &content(), &content())
+            .await;
+
+        assert_err!(result);
+    }
+
+    #[tokio::test]
+    async fn send_email_waits_as_long_as_retry_after_asks() {
+        let mock_server = MockServer::start().await;
+
+        let email_client = retrying(email_client(mock_server.uri()), 5_000);
+
+        Mock::given(any())
+            .respond_with(ResponseTemplate::new(429).insert_header("Retry-After", "1"))
+            .up_to_n_times(1)
+            .mount(&mock_server)
+            .await;
+        Mock::given(any())
+            .respond_with(ResponseTemplate::new(200))
+            .mount(&mock_server)
+            .await;
+
+        let start = std::time::Instant::now();
+        let result = email_client
+            .send_email(email(), &subject(), &content(), &content())
+            .await;
+
+        assert_ok!(result);
+        assert!(start.elapsed() >= Duration::from_secs(1));
+    }
+
+    #[tokio::test]
+    async fn send_email_gives_up_at_the_deadline() {
+        let mock_server = MockServer::start().await;
+
+        let email_client = retrying(email_client(mock_server.uri()), 300);
+
+        // Waiting that long would go past the deadline.
+        Mock::given(any())
+            .respond_with(ResponseTemplate::new(429).insert_header("Retry-After", "5"))
+            .mount(&mock_server)
+            .await;
+
+        let start = std::time::Instant::now();
+        let result = email_client
+            .send_email(email(), &subject(), &content(), &content())
+            .await;
+
+        assert_err!(result);
+        assert!(start.elapsed() < Duration::from_secs(1));
+        assert_eq!(mock_server.received_requests().await.unwrap().len(), 1);
+    }
 }
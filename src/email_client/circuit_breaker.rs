@@ -0,0 +1,305 @@
+use std::sync::Mutex;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::time::{Duration, Instant};
+
+use chrono::{DateTime, Utc};
+use serde::Serialize;
+
+use super::SendError;
+use crate::config::CircuitBreakerConfig;
+
+/// Stops calling the provider after `failure_threshold` transient failures in
+/// a row, so that callers fail right away instead of each waiting out the
+/// timeout.
+///
+/// Once `open_for` has passed a single probe goes through: the circuit closes
+/// again if it succeeds, and stays open for another `open_for` otherwise.
+pub struct CircuitBreaker {
+    failure_threshold: u32,
+    open_for: Duration,
+    state: Mutex<State>,
+    opened_total: AtomicU64,
+    rejected_total: AtomicU64,
+}
+
+#[derive(Debug, Clone, Copy)]
+enum State {
+    Closed {
+        failures: u32,
+    },
+    Open {
+        until: Instant,
+        retry_at: DateTime<Utc>,
+    },
+    HalfOpen {
+        retry_at: DateTime<Utc>,
+    },
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum CircuitState {
+    Closed,
+    Open,
+    HalfOpen,
+}
+
+/// What `/health_check/details` and `/metrics` report about the circuit.
+#[derive(Debug, Clone, Serialize)]
+pub struct CircuitStatus {
+    pub state: CircuitState,
+    pub consecutive_failures: u32,
+    /// When calls go through again, while the circuit is not closed.
+    pub retry_at: Option<DateTime<Utc>>,
+    pub opened_total: u64,
+    pub rejected_total: u64,
+}
+
+impl CircuitBreaker {
+    pub fn new(config: &CircuitBreakerConfig) -> Self {
+        Self {
+            failure_threshold: config.failure_threshold.max(1),
+            open_for: Duration::from_millis(config.open_ms),
+            state: Mutex::new(State::Closed { failures: 0 }),
+            opened_total: AtomicU64::new(0),
+            rejected_total: AtomicU64::new(0),
+        }
+    }
+
+    pub fn status(&self) -> CircuitStatus {
+        let state = *self.state.lock().unwrap();
+
+        let (state, consecutive_failures, retry_at) = match state {
+            State::Closed { failures } => (CircuitState::Closed, failures, None),
+            State::Open { retry_at, .. } => {
+                (CircuitState::Open, self.failure_threshold, Some(retry_at))
+            }
+            State::HalfOpen { retry_at } => (
+                CircuitState::HalfOpen,
+                self.failure_threshold,
+                Some(retry_at),
+            ),
+        };
+
+        CircuitStatus {
+            state,
+            consecutive_failures,
+            retry_at,
+            opened_total: self.opened_total.load(Ordering::Relaxed),
+            rejected_total: self.rejected_total.load(Ordering::Relaxed),
+        }
+    }
+
+    /// Fails with [`SendError::CircuitOpen`] when no call would go through,
+    /// without taking the half-open probe.
+    pub fn check(&self) -> Result<(), SendError> {
+        let state = *self.state.lock().unwrap();
+
+        match state {
+            State::Open { until, retry_at } if Instant::now() < until => Err(self.reject(retry_at)),
+            State::HalfOpen { retry_at } => Err(self.reject(retry_at)),
+            _ => Ok(()),
+        }
+    }
+
+    /// Runs `send` unless the circuit is open, and counts how it went.
+    pub async fn call<Fut>(&self, send: Fut) -> Result<(), SendError>
+    where
+        Fut: Future<Output = Result<(), SendError>>,
+    {
+        let permit = self.permit()?;
+        let result = send.await;
+        permit.record(&result);
+
+        result
+    }
+
+    fn permit(&self) -> Result<Permit<'_>, SendError> {
+        let mut state = self.state.lock().unwrap();
+
+        match *state {
+            State::Closed { .. } => Ok(Permit::new(self, false)),
+            State::Open { until, retry_at } if Instant::now() < until => Err(self.reject(retry_at)),
+            State::Open { .. } => {
+                tracing::info!("Probing the email provider");
+                *state = State::HalfOpen {
+                    retry_at: self.next_retry_at(),
+                };
+                Ok(Permit::new(self, true))
+            }
+            State::HalfOpen { retry_at } => Err(self.reject(retry_at)),
+        }
+    }
+
+    fn reject(&self, retry_at: DateTime<Utc>) -> SendError {
+        self.rejected_total.fetch_add(1, Ordering::Relaxed);
+        SendError::CircuitOpen { retry_at }
+    }
+
+    fn next_retry_at(&self) -> DateTime<Utc> {
+        Utc::now() + self.open_for
+    }
+
+    fn on_success(&self, probe: bool) {
+        let mut state = self.state.lock().unwrap();
+
+        match *state {
+            State::Closed { .. } => *state = State::Closed { failures: 0 },
+            State::HalfOpen { .. } if probe => {
+                tracing::info!("Closing the email circuit breaker");
+                *state = State::Closed { failures: 0 };
+            }
+            // Calls that started before the circuit opened do not count.
+            _ => {}
+        }
+    }
+
+    fn on_failure(&self, probe: bool) {
+        let mut state = self.state.lock().unwrap();
+
+        match *state {
+            State::Closed { failures } if failures + 1 < self.failure_threshold => {
+                *state = State::Closed {
+                    failures: failures + 1,
+                };
+            }
+            State::Closed { .. } => *state = self.open(),
+            State::HalfOpen { .. } if probe => *state = self.open(),
+            _ => {}
+        }
+    }
+
+    fn open(&self) -> State {
+        tracing::warn!(open_for = ?self.open_for, "Opening the email circuit breaker");
+        self.opened_total.fetch_add(1, Ordering::Relaxed);
+
+        State::Open {
+            until: Instant::now() + self.open_for,
+            retry_at: self.next_retry_at(),
+        }
+    }
+}
+
+/// A call let through by [`CircuitBreaker::permit`]. One dropped before its
+/// result is recorded, e.g. at the retry deadline, counts as a failure.
+struct Permit<'a> {
+    breaker: &'a CircuitBreaker,
+    probe: bool,
+    recorded: bool,
+}
+
+impl<'a> Permit<'a> {
+    fn new(breaker: &'a CircuitBreaker, probe: bool) -> Self {
+        Self {
+            breaker,
+            probe,
+            recorded: false,
+        }
+    }
+
+    /// Only transient failures count, anything else shows that the provider
+    /// is up.
+    fn record(mut self, result: &Result<(), SendError>) {
+        self.recorded = true;
+
+        match result {
+            Err(e) if e.is_transient() => self.breaker.on_failure(self.probe),
+            _ => self.breaker.on_success(self.probe),
+        }
+    }
+}
+
+impl Drop for Permit<'_> {
+    fn drop(&mut self) {
+        if !self.recorded {
+            self.breaker.on_failure(self.probe);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
+
+    use claims::{assert_err, assert_ok};
+    use reqwest::StatusCode;
+
+    use super::{CircuitBreaker, CircuitState};
+    use crate::config::CircuitBreakerConfig;
+    use crate::email_client::SendError;
+
+    fn breaker(open_ms: u64) -> CircuitBreaker {
+        CircuitBreaker::new(&CircuitBreakerConfig {
+            failure_threshold: 2,
+            open_ms,
+        })
+    }
+
+    fn unavailable() -> Result<(), SendError> {
+        Err(SendError::Rejected {
+            status: StatusCode::SERVICE_UNAVAILABLE,
+            retry_after: None,
+        })
+    }
+
+    #[tokio::test]
+    async fn opens_after_consecutive_transient_failures() {
+        let breaker = breaker(60_000);
+
+        assert_err!(breaker.call(async { unavailable() }).await);
+        assert_ok!(breaker.call(async { Ok(()) }).await);
+        assert_err!(breaker.call(async { unavailable() }).await);
+        assert_eq!(breaker.status().state, CircuitState::Closed);
+
+        // Rejections show that the provider is up.
+        let bad_request = SendError::Rejected {
+            status: StatusCode::BAD_REQUEST,
+            retry_after: None,
+        };
+        assert_err!(breaker.call(async { Err(bad_request) }).await);
+        assert_err!(breaker.call(async { unavailable() }).await);
+        assert_err!(breaker.call(async { unavailable() }).await);
+        assert_eq!(breaker.status().state, CircuitState::Open);
+
+        let mut called = false;
+        let result = breaker
+            .call(async {
+                called = true;
+                Ok(())
+            })
+            .await;
+        assert!(matches!(result, Err(SendError::CircuitOpen { .. })));
+        assert!(!called);
+        assert_err!(breaker.check());
+
+        let status = breaker.status();
+        assert_eq!(status.opened_total, 1);
+        assert_eq!(status.rejected_total, 2);
+    }
+
+    #[tokio::test]
+    async fn a_single_probe_closes_or_reopens_the_circuit() {
+        let breaker = breaker(50);
+        assert_err!(breaker.call(async { unavailable() }).await);
+        assert_err!(breaker.call(async { unavailable() }).await);
+        tokio::time::sleep(Duration::from_millis(60)).await;
+
+        assert_err!(
+            breaker
+                .call(async {
+                    // Others are turned away while the probe is in flight.
+                    assert_eq!(breaker.status().state, CircuitState::HalfOpen);
+                    assert_err!(breaker.check());
+                    unavailable()
+                })
+                .await
+        );
+        assert_eq!(breaker.status().state, CircuitState::Open);
+        assert_eq!(breaker.status().opened_total, 2);
+
+        tokio::time::sleep(Duration::from_millis(60)).await;
+        assert_ok!(breaker.call(async { Ok(()) }).await);
+        assert_eq!(breaker.status().state, CircuitState::Closed);
+        assert_eq!(breaker.status().consecutive_failures, 0);
+    }
+}
@@ -0,0 +1,154 @@
+use std::collections::{BTreeMap, VecDeque};
+use std::sync::{Arc, Mutex};
+
+use async_trait::async_trait;
+use chrono::{DateTime, Utc};
+
+use super::{EmailSender, Headers, Message, SendError};
+use crate::domain::Email;
+
+/// How many messages are kept before the oldest ones are dropped.
+const CAPACITY: usize = 200;
+
+/// Keeps outgoing emails in memory instead of delivering them.
+///
+/// Meant for local development, where the stored messages are browsed through
+/// `/dev/mailbox`. Clones share the same mailbox.
+#[derive(Clone, Default)]
+pub struct MailCatcher {
+    inner: Arc<Mutex<Mailbox>>,
+}
+
+#[derive(Default)]
+struct Mailbox {
+    next_id: u64,
+    messages: VecDeque<CaughtEmail>,
+}
+
+#[derive(Debug, Clone)]
+pub struct CaughtEmail {
+    pub id: u64,
+    pub from: String,
+    /// Addresses are comma-separated, like in the message's own headers.
+    pub to: String,
+    pub cc: String,
+    pub bcc: String,
+    pub reply_to: Option<String>,
+    pub subject: String,
+    pub raw_content: String,
+    pub html_content: String,
+    pub headers: Headers,
+    pub categories: Vec<String>,
+    pub custom_args: BTreeMap<String, String>,
+    pub send_at: Option<DateTime<Utc>>,
+    pub caught_at: DateTime<Utc>,
+}
+
+impl MailCatcher {
+    /// Every stored message, newest first.
+    pub fn messages(&self) -> Vec<CaughtEmail> {
+        let mailbox = self.inner.lock().unwrap();
+        mailbox.messages.iter().rev().cloned().collect()
+    }
+
+    pub fn message(&self, id: u64) -> Option<CaughtEmail> {
+        let mailbox = self.inner.lock().unwrap();
+        mailbox.messages.iter().find(|m| m.id == id).cloned()
+    }
+
+    pub fn clear(&self) {
+        self.inner.lock().unwrap().messages.clear();
+    }
+}
+
+#[async_trait]
+impl EmailSender for MailCatcher {
+    async fn send(&self, message: &Message) -> Result<(), SendError> {
+        let mut mailbox = self.inner.lock().unwrap();
+
+        mailbox.next_id += 1;
+        let id = mailbox.next_id;
+
+        if mailbox.messages.len() == CAPACITY {
+            mailbox.messages.pop_front();
+        }
+
+        let caught = CaughtEmail {
+            id,
+            from: message.from.as_ref().into(),
+            to: join(&message.to),
+            cc: join(&message.cc),
+            bcc: join(&message.bcc),
+            reply_to: message.reply_to.as_ref().map(|email| email.as_ref().into()),
+            subject: message.subject.clone(),
+            raw_content: message.raw_content.clone(),
+            html_content: message.html_content.clone(),
+            headers: message.headers.clone(),
+            categories: message.categories.clone(),
+            custom_args: message.custom_args.clone(),
+            send_at: message.send_at,
+            caught_at: Utc::now(),
+        };
+
+        tracing::info!(id, to = caught.to, "Caught an outgoing email");
+        mailbox.messages.push_back(caught);
+
+        Ok(())
+    }
+}
+
+fn join(emails: &[Email]) -> String {
+    emails
+        .iter()
+        .map(AsRef::as_ref)
+        .collect::<Vec<&str>>()
+        .join(", ")
+}
+
+#[cfg(test)]
+mod tests {
+    use claims::{assert_none, assert_ok, assert_some};
+
+    use super::{CAPACITY, MailCatcher};
+    use crate::domain::Email;
+    use crate::email_client::{EmailSender, Message};
+
+    async fn send(catcher: &MailCatcher, subject: &str) {
+        let from = Email::try_from("sender@lzzzt.cc".to_string()).unwrap();
+        let to = Email::try_from("main@lzzzt.cc".to_string()).unwrap();
+
+        let message = Message::new(from, to)
+            .with_subject(subject)
+            .with_text("Plain body")
+            .with_html("<p>Html body</p>");
+
+        assert_ok!(catcher.send(&message).await);
+    }
+
+    #[tokio::test]
+    async fn messages_are_listed_newest_first() {
+        let catcher = MailCatcher::default();
+
+        send(&catcher, "First").await;
+        send(&catcher, "Second").await;
+
+        let subjects: Vec<_> = catcher.messages().into_iter().map(|m| m.subject).collect();
+        assert_eq!(subjects, ["Second", "First"]);
+
+        let first = assert_some!(catcher.message(1));
+        assert_eq!(first.subject, "First");
+    }
+
+    #[tokio::test]
+    async fn oldest_messages_are_dropped_past_capacity() {
+        let catcher = MailCatcher::default();
+
+        for i in 0..=CAPACITY {
+            send(&catcher, &format!("Message {i}")).await;
+        }
+
+        assert_eq!(catcher.messages().len(), CAPACITY);
+        assert_none!(catcher.message(1));
+        assert_some!(catcher.message(2));
+    }
+}
@@ -0,0 +1,225 @@
+use std::time::Duration;
+
+use async_trait::async_trait;
+use reqwest::{Client, Url};
+use secrecy::{ExposeSecret, SecretString};
+
+use super::retry::check_status;
+use super::{EmailSender, Message, SendError};
+
+/// Sends through Mailgun's `/v3/{domain}/messages` form API.
+pub struct Mailgun {
+    base_url: Url,
+    domain: String,
+    http_client: Client,
+    token: SecretString,
+    timeout: Duration,
+}
+
+impl Mailgun {
+    pub fn new(base_url: Url, domain: String, token: SecretString, timeout: Duration) -> Self {
+        Self {
+            base_url,
+            domain,
+            token,
+            timeout,
+            http_client: Client::new(),
+        }
+    }
+}
+
+#[async_trait]
+impl EmailSender for Mailgun {
+    async fn send(&self, message: &Message) -> Result<(), SendError> {
+        let url = self
+            .base_url
+            .join(&format!("/v3/{}/messages", self.domain))
+            .expect("Failed to join url");
+
+        let mut form = vec![
+            ("from".to_string(), message.from.as_ref().to_string()),
+            ("subject".to_string(), message.subject.clone()),
+            ("text".to_string(), message.raw_content.clone()),
+            ("html".to_string(), message.html_content.clone()),
+        ];
+        // Repeated fields add up, for recipients and tags alike.
+        for (field, emails) in [
+            ("to", &message.to),
+            ("cc", &message.cc),
+            ("bcc", &message.bcc),
+        ] {
+            form.extend(
+                emails
+                    .iter()
+                    .map(|email| (field.to_string(), email.as_ref().to_string())),
+            );
+        }
+        if let Some(reply_to) = &message.reply_to {
+            form.push(("h:Reply-To".into(), reply_to.as_ref().into()));
+        }
+        // Mailgun takes custom MIME headers as `h:`-prefixed fields, and
+        // custom variables as `v:`-prefixed ones.
+        form.extend(
+            message
+                .headers
+                .iter()
+                .map(|(name, value)| (format!("h:{name}"), value.clone())),
+        );
+        form.extend(
+            message
+                .categories
+                .iter()
+                .map(|category| ("o:tag".to_string(), category.clone())),
+        );
+        form.extend(
+            message
+                .custom_args
+                .iter()
+                .map(|(key, value)| (format!("v:{key}"), value.clone())),
+        );
+        if let Some(send_at) = message.send_at {
+            form.push(("o:deliverytime".into(), send_at.to_rfc2822()));
+        }
+
+        let response = self
+            .http_client
+            .post(url)
+            .basic_auth("api", Some(self.token.expose_secret()))
+            .form(&form)
+            .timeout(self.timeout)
+            .send()
+            .await?;
+
+        check_status(response)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
+
+    use chrono::{TimeZone, Utc};
+    use claims::{assert_err, assert_ok};
+    use secrecy::SecretString;
+    use wiremock::matchers::{any, header, header_exists, method, path};
+    use wiremock::{Mock, MockServer, ResponseTemplate};
+
+    use crate::domain::Email;
+    use crate::email_client::{EmailClient, Mailgun};
+
+    fn email_client(uri: String) -> EmailClient {
+        let backend = Mailgun::new(
+            uri.parse().unwrap(),
+            "mail.lzzzt.cc".into(),
+            SecretString::from("api-key"),
+            Duration::from_millis(200),
+        );
+
+        EmailClient::new(
+            Email::try_from("from@lzzzt.cc".to_string()).unwrap(),
+            backend,
+        )
+    }
+
+    fn to() -> Email {
+        Email::try_from("to@lzzzt.cc".to_string()).unwrap()
+    }
+
+    /// The fields of the first form posted to the server.
+    async fn form(mock_server: &MockServer) -> Vec<(String, String)> {
+        let request = &mock_server.received_requests().await.unwrap()[0];
+        let mut url = reqwest::Url::parse("http://localhost").unwrap();
+        url.set_query(Some(std::str::from_utf8(&request.body).unwrap()));
+
+        url.query_pairs().into_owned().collect()
+    }
+
+    #[tokio::test]
+    async fn send_email_posts_a_form_to_the_domain_messages_endpoint() {
+        let mock_server = MockServer::start().await;
+
+        Mock::given(method("POST"))
+            .and(path("/v3/mail.lzzzt.cc/messages"))
+            .and(header_exists("Authorization"))
+            .and(header("Content-Type", "application/x-www-form-urlencoded"))
+            .respond_with(ResponseTemplate::new(200))
+            .expect(1)
+            .mount(&mock_server)
+            .await;
+
+        let result = email_client(mock_server.uri())
+            .send_email(to(), "subject", "text", "html")
+            .await;
+
+        assert_ok!(result);
+
+        let form = form(&mock_server).await;
+
+        for (field, value) in [
+            ("from", "from@lzzzt.cc"),
+            ("to", "to@lzzzt.cc"),
+            ("subject", "subject"),
+            ("text", "text"),
+            ("html", "html"),
+        ] {
+            assert!(form.contains(&(field.into(), value.into())), "{field}");
+        }
+    }
+
+    #[tokio::test]
+    async fn send_message_repeats_fields_and_prefixes_options_and_variables() {
+        let mock_server = MockServer::start().await;
+
+        Mock::given(any())
+            .respond_with(ResponseTemplate::new(200))
+            .expect(1)
+            .mount(&mock_server)
+            .await;
+
+        let email = |address: &str| Email::try_from(address.to_string()).unwrap();
+        let send_at = Utc.with_ymd_and_hms(2026, 10, 20, 9, 0, 0).unwrap();
+        let email_client = email_client(mock_server.uri());
+        let message = email_client
+            .message(to())
+            .add_to(email("second@lzzzt.cc"))
+            .add_cc(email("cc@lzzzt.cc"))
+            .with_reply_to(email("reply@lzzzt.cc"))
+            .add_category("newsletter")
+            .add_category("weekly")
+            .with_custom_arg("issue_id", "42")
+            .with_send_at(send_at);
+
+        assert_ok!(email_client.send_message(message).await);
+
+        let form = form(&mock_server).await;
+        for (field, value) in [
+            ("to", "to@lzzzt.cc"),
+            ("to", "second@lzzzt.cc"),
+            ("cc", "cc@lzzzt.cc"),
+            ("h:Reply-To", "reply@lzzzt.cc"),
+            ("o:tag", "newsletter"),
+            ("o:tag", "weekly"),
+            ("v:issue_id", "42"),
+            ("o:deliverytime", "Tue, 20 Oct 2026 09:00:00 +0000"),
+        ] {
+            assert!(form.contains(&(field.into(), value.into())), "{field}");
+        }
+    }
+
+    #[tokio::test]
+    async fn send_email_fails_if_mailgun_returns_500() {
+        let mock_server = MockServer::start().await;
+
+        Mock::given(any())
+            .respond_with(ResponseTemplate::new(500))
+            .expect(1)
+            .mount(&mock_server)
+            .await;
+
+        let result = email_client(mock_server.uri())
+            .send_email(to(), "subject", "text", "html")
+            .await;
+
+        assert_err!(result);
+    }
+}
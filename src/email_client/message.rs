@@ -0,0 +1,159 @@
+use std::collections::BTreeMap;
+
+use chrono::{DateTime, Utc};
+
+use super::Headers;
+use crate::domain::Email;
+
+/// An email as handed to an [`EmailSender`](super::EmailSender), built up
+/// from [`EmailClient::message`](super::EmailClient::message) or
+/// [`Message::new`].
+///
+/// Categories and custom args only tag the message for the provider's
+/// tracking and webhooks, so backends without them leave them out. Backends
+/// that cannot hold a message until `send_at` refuse it instead.
+#[derive(Debug, Clone)]
+pub struct Message {
+    pub from: Email,
+    pub to: Vec<Email>,
+    pub cc: Vec<Email>,
+    pub bcc: Vec<Email>,
+    pub reply_to: Option<Email>,
+    pub subject: String,
+    pub raw_content: String,
+    pub html_content: String,
+    pub headers: Headers,
+    pub categories: Vec<String>,
+    pub custom_args: BTreeMap<String, String>,
+    pub send_at: Option<DateTime<Utc>>,
+}
+
+impl Message {
+    pub fn new(from: Email, to: Email) -> Self {
+        Self {
+            from,
+            to: vec![to],
+            cc: vec![],
+            bcc: vec![],
+            reply_to: None,
+            subject: String::new(),
+            raw_content: String::new(),
+            html_content: String::new(),
+            headers: Headers::new(),
+            categories: vec![],
+            custom_args: BTreeMap::new(),
+            send_at: None,
+        }
+    }
+
+    pub fn with_subject(mut self, subject: impl Into<String>) -> Self {
+        self.subject = subject.into();
+        self
+    }
+
+    pub fn with_text(mut self, raw_content: impl Into<String>) -> Self {
+        self.raw_content = raw_content.into();
+        self
+    }
+
+    pub fn with_html(mut self, html_content: impl Into<String>) -> Self {
+        self.html_content = html_content.into();
+        self
+    }
+
+    pub fn add_to(mut self, to: Email) -> Self {
+        self.to.push(to);
+        self
+    }
+
+    pub fn add_cc(mut self, cc: Email) -> Self {
+        self.cc.push(cc);
+        self
+    }
+
+    pub fn add_bcc(mut self, bcc: Email) -> Self {
+        self.bcc.push(bcc);
+        self
+    }
+
+    pub fn with_reply_to(mut self, reply_to: Email) -> Self {
+        self.reply_to = Some(reply_to);
+        self
+    }
+
+    /// Replaces any header of the same name, including the ones
+    /// [`EmailClient`](super::EmailClient) adds.
+    pub fn with_header(mut self, name: &'static str, value: impl Into<String>) -> Self {
+        self.headers.insert(name, value.into());
+        self
+    }
+
+    /// A provider category, called a tag by Postmark and Mailgun.
+    pub fn add_category(mut self, category: impl Into<String>) -> Self {
+        self.categories.push(category.into());
+        self
+    }
+
+    /// A value the provider reports back in its events, e.g. to correlate
+    /// them with the issue the message belongs to.
+    pub fn with_custom_arg(mut self, key: impl Into<String>, value: impl Into<String>) -> Self {
+        self.custom_args.insert(key.into(), value.into());
+        self
+    }
+
+    /// Has the provider hold the message until `send_at`.
+    pub fn with_send_at(mut self, send_at: DateTime<Utc>) -> Self {
+        self.send_at = Some(send_at);
+        self
+    }
+
+    /// Every address the message goes to, `bcc` included.
+    pub fn recipients(&self) -> impl Iterator<Item = &Email> {
+        self.to.iter().chain(&self.cc).chain(&self.bcc)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use chrono::{TimeZone, Utc};
+
+    use super::Message;
+    use crate::domain::Email;
+
+    fn email(address: &str) -> Email {
+        Email::try_from(address.to_string()).unwrap()
+    }
+
+    #[test]
+    fn builder_adds_recipients_and_metadata() {
+        let send_at = Utc.with_ymd_and_hms(2026, 10, 20, 9, 0, 0).unwrap();
+
+        let message = Message::new(email("from@lzzzt.cc"), email("to@lzzzt.cc"))
+            .with_subject("Subject")
+            .add_to(email("second@lzzzt.cc"))
+            .add_cc(email("cc@lzzzt.cc"))
+            .add_bcc(email("bcc@lzzzt.cc"))
+            .with_reply_to(email("reply@lzzzt.cc"))
+            .with_header("X-Entity-Ref-ID", "1")
+            .with_header("X-Entity-Ref-ID", "2")
+            .add_category("newsletter")
+            .with_custom_arg("issue_id", "42")
+            .with_send_at(send_at);
+
+        let recipients: Vec<_> = message.recipients().map(AsRef::as_ref).collect();
+        assert_eq!(
+            recipients,
+            [
+                "to@lzzzt.cc",
+                "second@lzzzt.cc",
+                "cc@lzzzt.cc",
+                "bcc@lzzzt.cc"
+            ]
+        );
+        assert_eq!(message.reply_to.unwrap().as_ref(), "reply@lzzzt.cc");
+        assert_eq!(message.headers["X-Entity-Ref-ID"], "2");
+        assert_eq!(message.categories, ["newsletter"]);
+        assert_eq!(message.custom_args["issue_id"], "42");
+        assert_eq!(message.send_at, Some(send_at));
+    }
+}
@@ -0,0 +1,235 @@
+use std::collections::BTreeMap;
+use std::time::Duration;
+
+use async_trait::async_trait;
+use reqwest::{Client, Url};
+use secrecy::{ExposeSecret, SecretString};
+use serde::Serialize;
+
+use super::retry::check_status;
+use super::{EmailSender, Message, SendError};
+use crate::domain::Email;
+
+/// Sends through Postmark's `/email` JSON API.
+pub struct Postmark {
+    base_url: Url,
+    http_client: Client,
+    token: SecretString,
+    timeout: Duration,
+}
+
+impl Postmark {
+    pub fn new(base_url: Url, token: SecretString, timeout: Duration) -> Self {
+        Self {
+            base_url,
+            token,
+            timeout,
+            http_client: Client::new(),
+        }
+    }
+}
+
+#[derive(Serialize)]
+#[serde(rename_all = "PascalCase")]
+struct Body<'a> {
+    from: &'a str,
+    to: String,
+    #[serde(skip_serializing_if = "String::is_empty")]
+    cc: String,
+    #[serde(skip_serializing_if = "String::is_empty")]
+    bcc: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    reply_to: Option<&'a str>,
+    subject: &'a str,
+    text_body: &'a str,
+    html_body: &'a str,
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    headers: Vec<Header<'a>>,
+    /// Postmark takes a single tag, the first category.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    tag: Option<&'a str>,
+    #[serde(skip_serializing_if = "BTreeMap::is_empty")]
+    metadata: &'a BTreeMap<String, String>,
+}
+
+#[derive(Serialize)]
+#[serde(rename_all = "PascalCase")]
+struct Header<'a> {
+    name: &'a str,
+    value: &'a str,
+}
+
+#[async_trait]
+impl EmailSender for Postmark {
+    async fn send(&self, message: &Message) -> Result<(), SendError> {
+        // Postmark sends right away, it has no way to schedule an email.
+        if message.send_at.is_some() {
+            return Err(SendError::Unsupported("scheduled sending"));
+        }
+
+        let url = self.base_url.join("/email").expect("Failed to join url");
+
+        let body = Body {
+            from: message.from.as_ref(),
+            to: join(&message.to),
+            cc: join(&message.cc),
+            bcc: join(&message.bcc),
+            reply_to: message.reply_to.as_ref().map(AsRef::as_ref),
+            subject: &message.subject,
+            text_body: &message.raw_content,
+            html_body: &message.html_content,
+            headers: message
+                .headers
+                .iter()
+                .map(|(name, value)| Header { name, value })
+                .collect(),
+            tag: message.categories.first().map(String::as_str),
+            metadata: &message.custom_args,
+        };
+
+        let response = self
+            .http_client
+            .post(url)
+            .json(&body)
+            .header("Accept", "application/json")
+            .header("X-Postmark-Server-Token", self.token.expose_secret())
+            .timeout(self.timeout)
+            .send()
+            .await?;
+
+        check_status(response)
+    }
+}
+
+/// Postmark takes several addresses as a single comma-separated string.
+fn join(emails: &[Email]) -> String {
+    emails
+        .iter()
+        .map(AsRef::as_ref)
+        .collect::<Vec<&str>>()
+        .join(",")
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
+
+    use chrono::Utc;
+    use claims::{assert_err, assert_ok};
+    use secrecy::SecretString;
+    use wiremock::matchers::{any, body_partial_json, header, method, path};
+    use wiremock::{Mock, MockServer, ResponseTemplate};
+
+    use crate::domain::Email;
+    use crate::email_client::{EmailClient, Postmark, SendError};
+
+    fn email_client(uri: String) -> EmailClient {
+        let backend = Postmark::new(
+            uri.parse().unwrap(),
+            SecretString::from("server-token"),
+            Duration::from_millis(200),
+        );
+
+        EmailClient::new(
+            Email::try_from("from@lzzzt.cc".to_string()).unwrap(),
+            backend,
+        )
+    }
+
+    fn to() -> Email {
+        Email::try_from("to@lzzzt.cc".to_string()).unwrap()
+    }
+
+    #[tokio::test]
+    async fn send_email_posts_postmark_json_with_the_server_token() {
+        let mock_server = MockServer::start().await;
+
+        Mock::given(method("POST"))
+            .and(path("/email"))
+            .and(header("X-Postmark-Server-Token", "server-token"))
+            .and(body_partial_json(serde_json::json!({
+                "From": "from@lzzzt.cc",
+                "To": "to@lzzzt.cc",
+                "Subject": "subject",
+                "TextBody": "text",
+                "HtmlBody": "html",
+            })))
+            .respond_with(ResponseTemplate::new(200))
+            .expect(1)
+            .mount(&mock_server)
+            .await;
+
+        let result = email_client(mock_server.uri())
+            .send_email(to(), "subject", "text", "html")
+            .await;
+
+        assert_ok!(result);
+    }
+
+    #[tokio::test]
+    async fn send_message_joins_recipients_and_sets_the_tag_and_metadata() {
+        let mock_server = MockServer::start().await;
+
+        Mock::given(body_partial_json(serde_json::json!({
+            "To": "to@lzzzt.cc,second@lzzzt.cc",
+            "Cc": "cc@lzzzt.cc",
+            "Bcc": "bcc@lzzzt.cc",
+            "ReplyTo": "reply@lzzzt.cc",
+            "Tag": "newsletter",
+            "Metadata": { "issue_id": "42" },
+        })))
+        .respond_with(ResponseTemplate::new(200))
+        .expect(1)
+        .mount(&mock_server)
+        .await;
+
+        let email = |address: &str| Email::try_from(address.to_string()).unwrap();
+        let email_client = email_client(mock_server.uri());
+        let message = email_client
+            .message(to())
+            .add_to(email("second@lzzzt.cc"))
+            .add_cc(email("cc@lzzzt.cc"))
+            .add_bcc(email("bcc@lzzzt.cc"))
+            .with_reply_to(email("reply@lzzzt.cc"))
+            .add_category("newsletter")
+            .with_custom_arg("issue_id", "42");
+
+        assert_ok!(email_client.send_message(message).await);
+    }
+
+    #[tokio::test]
+    async fn scheduled_messages_are_refused() {
+        let mock_server = MockServer::start().await;
+
+        Mock::given(any())
+            .respond_with(ResponseTemplate::new(200))
+            .expect(0)
+            .mount(&mock_server)
+            .await;
+
+        let email_client = email_client(mock_server.uri());
+        let message = email_client.message(to()).with_send_at(Utc::now());
+
+        assert!(matches!(
+            email_client.send_message(message).await,
+            Err(SendError::Unsupported(_))
+        ));
+    }
+
+    #[tokio::test]
+    async fn send_email_fails_if_postmark_rejects_the_email() {
+        let mock_server = MockServer::start().await;
+
+        Mock::given(any())
+            .respond_with(ResponseTemplate::new(422))
+            .expect(1)
+            .mount(&mock_server)
+            .await;
+
+        let result = email_client(mock_server.uri())
+            .send_email(to(), "subject", "text", "html")
+            .await;
+
+        assert_err!(result);
+    }
+}
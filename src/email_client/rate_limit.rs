@@ -0,0 +1,239 @@
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+
+use chrono::{DateTime, NaiveTime, TimeDelta, Utc};
+use sqlx::PgPool;
+
+use super::SendError;
+use crate::config::RateLimitConfig;
+
+/// Keeps sends within the provider's plan: a token bucket for the per-second
+/// cap, and a count in `email_quota_usage` for the daily one.
+///
+/// The bucket is local to the process, the daily count is shared by every
+/// replica.
+pub struct RateLimiter {
+    bucket: Option<TokenBucket>,
+    daily_quota: Option<DailyQuota>,
+}
+
+impl RateLimiter {
+    pub fn new(config: &RateLimitConfig, pool: PgPool) -> Self {
+        Self {
+            bucket: config
+                .per_second
+                .map(|per_second| TokenBucket::new(per_second, config.burst.unwrap_or(per_second))),
+            daily_quota: config.daily_quota.map(|limit| DailyQuota { pool, limit }),
+        }
+    }
+
+    /// Most emails [`RateLimiter::acquire`] can ever let through at once.
+    pub fn max_batch_size(&self) -> usize {
+        self.daily_quota
+            .as_ref()
+            .map_or(usize::MAX, |quota| quota.limit as usize)
+    }
+
+    /// Waits until `n` emails can go out, after counting them against the
+    /// daily quota. Emails that end up failing still count, as providers
+    /// usually count them too.
+    pub async fn acquire(&self, n: usize) -> Result<(), SendError> {
+        if let Some(daily_quota) = &self.daily_quota {
+            daily_quota.consume(n).await?;
+        }
+
+        if let Some(bucket) = &self.bucket {
+            bucket.acquire(n).await;
+        }
+
+        Ok(())
+    }
+
+    /// Like [`RateLimiter::acquire`], but settles for what is left of the
+    /// daily quota when `n` emails do not fit in it. Returns how many emails
+    /// can go out, which is never 0.
+    pub async fn acquire_up_to(&self, n: usize) -> Result<usize, SendError> {
+        let n = match &self.daily_quota {
+            Some(daily_quota) => daily_quota.consume_up_to(n).await?,
+            None => n,
+        };
+
+        if let Some(bucket) = &self.bucket {
+            bucket.acquire(n).await;
+        }
+
+        Ok(n)
+    }
+}
+
+/// Refills at `rate` tokens per second up to `capacity`. Callers take what
+/// they need right away and wait for the bucket to refill from below zero, so
+/// that they are served in order and large batches are not starved.
+struct TokenBucket {
+    rate: f64,
+    capacity: f64,
+    state: Mutex<(f64, Instant)>,
+}
+
+impl TokenBucket {
+    fn new(per_second: u32, burst: u32) -> Self {
+        let capacity = f64::from(burst.max(1));
+
+        Self {
+            rate: f64::from(per_second.max(1)),
+            capacity,
+            state: Mutex::new((capacity, Instant::now())),
+        }
+    }
+
+    async fn acquire(&self, n: usize) {
+        let wait = {
+            let mut state = self.state.lock().unwrap();
+            let (tokens, refilled_at) = &mut *state;
+
+            let now = Instant::now();
+            let elapsed = now.duration_since(*refilled_at).as_secs_f64();
+            *tokens = (*tokens + elapsed * self.rate).min(self.capacity) - n as f64;
+            *refilled_at = now;
+
+            match *tokens < 0.0 {
+                true => Duration::from_secs_f64(-*tokens / self.rate),
+                false => Duration::ZERO,
+            }
+        };
+
+        if !wait.is_zero() {
+            tracing::debug!(?wait, n, "Waiting for the email rate limit");
+            tokio::time::sleep(wait).await;
+        }
+    }
+}
+
+struct DailyQuota {
+    pool: PgPool,
+    limit: u32,
+}
+
+impl DailyQuota {
+    /// Counts `n` more emails for today, unless that would go over the limit.
+    async fn consume(&self, n: usize) -> Result<(), SendError> {
+        let n = i32::try_from(n).unwrap_or(i32::MAX);
+        let limit = i32::try_from(self.limit).unwrap_or(i32::MAX);
+
+        let sent = sqlx::query_scalar!(
+            r#"
+            INSERT INTO email_quota_usage (day, sent)
+            SELECT (now() AT TIME ZONE 'UTC')::date, $1::integer
+            WHERE $1::integer <= $2::integer
+            ON CONFLICT (day) DO UPDATE
+            SET sent = email_quota_usage.sent + EXCLUDED.sent
+            WHERE email_quota_usage.sent + EXCLUDED.sent <= $2::integer
+            RETURNING sent
+            "#,
+            n,
+            limit,
+        )
+        .fetch_optional(&self.pool)
+        .await
+        .inspect_err(|e| tracing::error!("Failed to execute query: {e:?}"))?;
+
+        match sent {
+            Some(_) => Ok(()),
+            None => Err(quota_exceeded()),
+        }
+    }
+
+    /// Counts as many of `n` more emails for today as fit under the limit,
+    /// and returns how many that is.
+    async fn consume_up_to(&self, n: usize) -> Result<usize, SendError> {
+        let n = i32::try_from(n).unwrap_or(i32::MAX);
+        let limit = i32::try_from(self.limit).unwrap_or(i32::MAX);
+
+        let mut transaction = self.pool.begin().await?;
+
+        sqlx::query!(
+            r#"
+            INSERT INTO email_quota_usage (day, sent)
+            VALUES ((now() AT TIME ZONE 'UTC')::date, 0)
+            ON CONFLICT (day) DO NOTHING
+            "#
+        )
+        .execute(&mut *transaction)
+        .await
+        .inspect_err(|e| tracing::error!("Failed to execute query: {e:?}"))?;
+
+        // Locked, so that concurrent senders split what is left between them.
+        let sent = sqlx::query_scalar!(
+            r#"
+            SELECT sent FROM email_quota_usage
+            WHERE day = (now() AT TIME ZONE 'UTC')::date
+            FOR UPDATE
+            "#
+        )
+        .fetch_one(&mut *transaction)
+        .await
+        .inspect_err(|e| tracing::error!("Failed to execute query: {e:?}"))?;
+
+        let granted = n.min(limit - sent);
+        if granted <= 0 {
+            return Err(quota_exceeded());
+        }
+
+        sqlx::query!(
+            r#"
+            UPDATE email_quota_usage SET sent = sent + $1
+            WHERE day = (now() AT TIME ZONE 'UTC')::date
+            "#,
+            granted,
+        )
+        .execute(&mut *transaction)
+        .await
+        .inspect_err(|e| tracing::error!("Failed to execute query: {e:?}"))?;
+
+        transaction.commit().await?;
+
+        Ok(granted as usize)
+    }
+}
+
+/// What callers get back for emails that do not fit in today's quota.
+pub(super) fn quota_exceeded() -> SendError {
+    SendError::QuotaExceeded {
+        resets_at: next_utc_midnight(Utc::now()),
+    }
+}
+
+fn next_utc_midnight(now: DateTime<Utc>) -> DateTime<Utc> {
+    (now.date_naive() + TimeDelta::days(1))
+        .and_time(NaiveTime::MIN)
+        .and_utc()
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::{Duration, Instant};
+
+    use super::TokenBucket;
+
+    #[tokio::test]
+    async fn bursts_go_out_right_away() {
+        let bucket = TokenBucket::new(10, 5);
+
+        let start = Instant::now();
+        bucket.acquire(5).await;
+
+        assert!(start.elapsed() < Duration::from_millis(50));
+    }
+
+    #[tokio::test]
+    async fn sends_past_the_burst_wait_for_the_bucket_to_refill() {
+        let bucket = TokenBucket::new(10, 1);
+
+        let start = Instant::now();
+        bucket.acquire(1).await;
+        bucket.acquire(2).await;
+
+        // Two tokens at ten per second.
+        assert!(start.elapsed() >= Duration::from_millis(190));
+    }
+}
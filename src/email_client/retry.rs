@@ -0,0 +1,199 @@
+use std::time::Duration;
+
+use chrono::{DateTime, Utc};
+use rand::Rng;
+use reqwest::StatusCode;
+use reqwest::header::{HeaderMap, RETRY_AFTER};
+use tokio::time::Instant;
+
+use super::SendError;
+use crate::config::RetryConfig;
+
+/// How a send is retried after transient failures: timeouts, connection
+/// errors, `429 Too Many Requests` and `5xx` answers.
+///
+/// Delays grow exponentially with full jitter, unless the provider asks for
+/// one with `Retry-After`. No attempt starts, or runs, past the deadline.
+#[derive(Debug, Clone)]
+pub struct RetryPolicy {
+    max_attempts: u32,
+    base_backoff: Duration,
+    max_backoff: Duration,
+    deadline: Duration,
+}
+
+impl RetryPolicy {
+    /// A single attempt, with no deadline besides the backend's own timeout.
+    pub fn none() -> Self {
+        Self {
+            max_attempts: 1,
+            base_backoff: Duration::ZERO,
+            max_backoff: Duration::ZERO,
+            deadline: Duration::MAX,
+        }
+    }
+
+    /// Runs `attempt` until it succeeds, fails for good, runs out of attempts
+    /// or would go past the deadline.
+    pub async fn run<F, Fut>(&self, mut attempt: F) -> Result<(), SendError>
+    where
+        F: FnMut() -> Fut,
+        Fut: Future<Output = Result<(), SendError>>,
+    {
+        let deadline = Instant::now().checked_add(self.deadline);
+        let mut n_attempts = 0;
+
+        loop {
+            n_attempts += 1;
+
+            let result = match deadline {
+                Some(deadline) => tokio::time::timeout_at(deadline, attempt())
+                    .await
+                    .unwrap_or(Err(SendError::DeadlineExceeded)),
+                None => attempt().await,
+            };
+
+            let error = match result {
+                Ok(()) => return Ok(()),
+                Err(e) if !e.is_transient() || n_attempts >= self.max_attempts => return Err(e),
+                Err(e) => e,
+            };
+
+            let delay = error
+                .retry_after()
+                .unwrap_or_else(|| self.backoff(n_attempts));
+
+            if deadline.is_some_and(|deadline| Instant::now() + delay >= deadline) {
+                return Err(error);
+            }
+
+            tracing::warn!(error = %error, n_attempts, ?delay, "Sending failed, retrying");
+            tokio::time::sleep(delay).await;
+        }
+    }
+
+    /// A random delay of up to the exponential backoff after `n_attempts`.
+    fn backoff(&self, n_attempts: u32) -> Duration {
+        let factor = 2u32.saturating_pow(n_attempts.saturating_sub(1));
+        let ceiling = self
+            .base_backoff
+            .saturating_mul(factor)
+            .min(self.max_backoff);
+
+        ceiling.mul_f64(rand::rng().random::<f64>())
+    }
+}
+
+impl From<&RetryConfig> for RetryPolicy {
+    fn from(value: &RetryConfig) -> Self {
+        Self {
+            max_attempts: value.max_attempts.max(1),
+            base_backoff: Duration::from_millis(value.base_backoff_ms),
+            max_backoff: Duration::from_millis(value.max_backoff_ms),
+            deadline: Duration::from_millis(value.deadline_ms),
+        }
+    }
+}
+
+/// Turns an error answer from a provider's API into [`SendError::Rejected`],
+/// keeping what [`RetryPolicy`] needs to know.
+pub(super) fn check_status(response: reqwest::Response) -> Result<(), SendError> {
+    let status = response.status();
+    if status.is_success() {
+        return Ok(());
+    }
+
+    Err(SendError::Rejected {
+        status,
+        retry_after: retry_after(response.headers(), Utc::now()),
+    })
+}
+
+/// `Retry-After` as either a number of seconds or an HTTP date.
+fn retry_after(headers: &HeaderMap, now: DateTime<Utc>) -> Option<Duration> {
+    let value = headers.get(RETRY_AFTER)?.to_str().ok()?.trim();
+
+    if let Ok(seconds) = value.parse::<u64>() {
+        return Some(Duration::from_secs(seconds));
+    }
+
+    let at = DateTime::parse_from_rfc2822(value).ok()?;
+    Some((at.to_utc() - now).to_std().unwrap_or(Duration::ZERO))
+}
+
+impl SendError {
+    /// Whether trying again later can succeed.
+    pub fn is_transient(&self) -> bool {
+        match self {
+            SendError::Http(e) => e.is_timeout() || e.is_connect() || e.is_request(),
+            SendError::Rejected { status, .. } => {
+                *status == StatusCode::TOO_MANY_REQUESTS || status.is_server_error()
+            }
+            SendError::Smtp(e) => !e.is_permanent(),
+            SendError::DeadlineExceeded => true,
+            SendError::Message(_)
+            | SendError::Unsupported(_)
+            | SendError::Address(_)
+            | SendError::QuotaExceeded { .. }
+            | SendError::CircuitOpen { .. }
+            | SendError::Database(_) => false,
+        }
+    }
+
+    /// The delay the provider asked for before the next attempt.
+    pub fn retry_after(&self) -> Option<Duration> {
+        match self {
+            SendError::Rejected { retry_after, .. } => *retry_after,
+            _ => None,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
+
+    use chrono::{TimeZone, Utc};
+    use reqwest::header::{HeaderMap, HeaderValue, RETRY_AFTER};
+
+    use super::{RetryPolicy, retry_after};
+
+    fn headers(value: &'static str) -> HeaderMap {
+        HeaderMap::from_iter([(RETRY_AFTER, HeaderValue::from_static(value))])
+    }
+
+    #[test]
+    fn retry_after_takes_seconds_or_a_date() {
+        let now = Utc.with_ymd_and_hms(2026, 1, 18, 9, 0, 0).unwrap();
+
+        assert_eq!(
+            retry_after(&headers("120"), now),
+            Some(Duration::from_secs(120))
+        );
+        assert_eq!(
+            retry_after(&headers("Sun, 18 Jan 2026 09:00:30 GMT"), now),
+            Some(Duration::from_secs(30))
+        );
+        assert_eq!(
+            retry_after(&headers("Sun, 18 Jan 2026 08:00:00 GMT"), now),
+            Some(Duration::ZERO)
+        );
+        assert_eq!(retry_after(&headers("soon"), now), None);
+    }
+
+    #[test]
+    fn backoff_is_jittered_below_an_exponential_ceiling() {
+        let policy = RetryPolicy {
+            max_attempts: 10,
+            base_backoff: Duration::from_millis(100),
+            max_backoff: Duration::from_millis(1_000),
+            deadline: Duration::MAX,
+        };
+
+        for _ in 0..100 {
+            assert!(policy.backoff(1) <= Duration::from_millis(100));
+            assert!(policy.backoff(3) <= Duration::from_millis(400));
+            assert!(policy.backoff(30) <= Duration::from_millis(1_000));
+        }
+    }
+}
@@ -0,0 +1,178 @@
+use std::time::Duration;
+
+use async_trait::async_trait;
+use reqwest::{Client, Url};
+use secrecy::{ExposeSecret, SecretString};
+
+use super::retry::check_status;
+use super::{EmailSender, Message, OutgoingBatch, SendError};
+
+/// Most personalizations SendGrid takes in a single request.
+const MAX_PERSONALIZATIONS: usize = 1000;
+
+/// Sends through SendGrid's `/v3/mail/send` JSON API.
+pub struct SendGrid {
+    base_url: Url,
+    http_client: Client,
+    token: SecretString,
+    timeout: Duration,
+}
+
+impl SendGrid {
+    pub fn new(base_url: Url, token: SecretString, timeout: Duration) -> Self {
+        Self {
+            base_url,
+            timeout,
+            token: SecretString::from(format!("Bearer {}", token.expose_secret())),
+            http_client: Client::new(),
+        }
+    }
+
+    async fn post(&self, body: &request::Body<'_>) -> Result<(), SendError> {
+        let url = self
+            .base_url
+            .join("/v3/mail/send")
+            .expect("Failed to join url");
+
+        let response = self
+            .http_client
+            .post(url)
+            .json(body)
+            .header("Authorization", self.token.expose_secret())
+            .timeout(self.timeout)
+            .send()
+            .await?;
+
+        check_status(response)
+    }
+}
+
+#[async_trait]
+impl EmailSender for SendGrid {
+    async fn send(&self, message: &Message) -> Result<(), SendError> {
+        self.post(&request::Body::new(message)).await
+    }
+
+    fn max_batch_size(&self) -> usize {
+        MAX_PERSONALIZATIONS
+    }
+
+    /// One request for the whole batch, with a personalization per recipient
+    /// that SendGrid fills the substitutions in from.
+    async fn send_batch(&self, batch: &OutgoingBatch<'_>) -> Result<(), SendError> {
+        self.post(&request::Body::batch(batch)).await
+    }
+}
+
+mod request {
+    use std::collections::BTreeMap;
+
+    use serde::Serialize;
+
+    use crate::domain::Email;
+    use crate::email_client::{Headers, Message, OutgoingBatch};
+
+    #[derive(Serialize)]
+    pub struct Body<'a> {
+        personalizations: Vec<Personalization<'a>>,
+        from: &'a Email,
+        #[serde(skip_serializing_if = "Option::is_none")]
+        reply_to: Option<&'a Email>,
+        subject: &'a str,
+        content: Vec<Content<'a>>,
+        #[serde(skip_serializing_if = "<[_]>::is_empty")]
+        categories: &'a [String],
+        #[serde(skip_serializing_if = "Option::is_none")]
+        custom_args: Option<&'a BTreeMap<String, String>>,
+        /// A Unix timestamp.
+        #[serde(skip_serializing_if = "Option::is_none")]
+        send_at: Option<i64>,
+    }
+
+    impl<'a> Body<'a> {
+        /// A single personalization holding every recipient of the message.
+        pub fn new(message: &'a Message) -> Self {
+            Body {
+                personalizations: vec![Personalization {
+                    to: message.to.iter().collect(),
+                    cc: message.cc.iter().collect(),
+                    bcc: message.bcc.iter().collect(),
+                    headers: Some(&message.headers).filter(|h| !h.is_empty()),
+                    substitutions: None,
+                }],
+                from: &message.from,
+                reply_to: message.reply_to.as_ref(),
+                subject: &message.subject,
+                content: vec![
+                    Content::text(&message.raw_content),
+                    Content::html(&message.html_content),
+                ],
+                categories: &message.categories,
+                custom_args: Some(&message.custom_args).filter(|args| !args.is_empty()),
+                send_at: message.send_at.map(|at| at.timestamp()),
+            }
+        }
+
+        /// A personalization per recipient of the batch, with their address,
+        /// headers and substitutions.
+        pub fn batch(batch: &'a OutgoingBatch<'a>) -> Self {
+            Body {
+                personalizations: batch
+                    .recipients
+                    .iter()
+                    .map(|recipient| Personalization {
+                        to: vec![&recipient.to],
+                        cc: vec![],
+                        bcc: vec![],
+                        headers: Some(&recipient.headers).filter(|h| !h.is_empty()),
+                        substitutions: Some(&recipient.substitutions).filter(|s| !s.is_empty()),
+                    })
+                    .collect(),
+                from: batch.from,
+                reply_to: None,
+                subject: batch.subject,
+                content: vec![
+                    Content::text(batch.raw_content),
+                    Content::html(batch.html_content),
+                ],
+                categories: &[],
+                custom_args: None,
+                send_at: None,
+            }
+        }
+    }
+
+    #[derive(Serialize)]
+    struct Personalization<'a> {
+        to: Vec<&'a Email>,
+        #[serde(skip_serializing_if = "Vec::is_empty")]
+        cc: Vec<&'a Email>,
+        #[serde(skip_serializing_if = "Vec::is_empty")]
+        bcc: Vec<&'a Email>,
+        #[serde(skip_serializing_if = "Option::is_none")]
+        headers: Option<&'a Headers>,
+        #[serde(skip_serializing_if = "Option::is_none")]
+        substitutions: Option<&'a BTreeMap<String, String>>,
+    }
+
+    #[derive(Serialize)]
+    #[serde(tag = "type")]
+    enum Content<'a> {
+        #[serde(rename = "text/plain")]
+        Text { value: &'a str },
+        #[serde(rename = "text/html")]
+        Html { value: &'a str },
+    }
+
+    impl<'a> Content<'a> {
+        #[inline]
+        fn text(value: &'a str) -> Self {
+            Self::Text { value }
+        }
+
+        #[inline]
+        fn html(value: &'a str) -> Self {
+            Self::Html { value }
+        }
+    }
+}
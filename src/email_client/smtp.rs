@@ -0,0 +1,191 @@
+use std::time::Duration;
+
+use async_trait::async_trait;
+use lettre::message::header::{HeaderName, HeaderValue};
+use lettre::message::{Mailbox, MultiPart};
+use lettre::transport::smtp::PoolConfig;
+use lettre::transport::smtp::authentication::Credentials;
+use lettre::{AsyncSmtpTransport, AsyncTransport, Tokio1Executor};
+use secrecy::ExposeSecret;
+
+use super::{EmailSender, Message, SendError};
+use crate::config::{SmtpConfig, SmtpTls};
+
+/// Sends through an SMTP relay, reusing pooled connections.
+pub struct Smtp {
+    transport: AsyncSmtpTransport<Tokio1Executor>,
+}
+
+impl Smtp {
+    pub fn new(
+        config: &SmtpConfig,
+        timeout: Duration,
+    ) -> Result<Self, lettre::transport::smtp::Error> {
+        let builder = match config.tls {
+            SmtpTls::None => AsyncSmtpTransport::<Tokio1Executor>::builder_dangerous(&config.host),
+            SmtpTls::StartTls => {
+                AsyncSmtpTransport::<Tokio1Executor>::starttls_relay(&config.host)?
+            }
+            SmtpTls::Implicit => AsyncSmtpTransport::<Tokio1Executor>::relay(&config.host)?,
+        };
+
+        let mut builder = builder
+            .port(config.port)
+            .timeout(Some(timeout))
+            .pool_config(PoolConfig::new().max_size(config.pool_size));
+
+        if let (Some(username), Some(password)) = (&config.username, &config.password) {
+            builder = builder.credentials(Credentials::new(
+                username.clone(),
+                password.expose_secret().to_string(),
+            ));
+        }
+
+        Ok(Self {
+            transport: builder.build(),
+        })
+    }
+}
+
+/// Categories and custom args are left out, SMTP relays have no use for them.
+fn mime_message(message: &Message) -> Result<lettre::Message, SendError> {
+    // The relay has no way to hold the message for later.
+    if message.send_at.is_some() {
+        return Err(SendError::Unsupported("scheduled sending"));
+    }
+
+    let mut builder = lettre::Message::builder()
+        .from(message.from.as_ref().parse::<Mailbox>()?)
+        .subject(&message.subject);
+
+    for to in &message.to {
+        builder = builder.to(to.as_ref().parse::<Mailbox>()?);
+    }
+    for cc in &message.cc {
+        builder = builder.cc(cc.as_ref().parse::<Mailbox>()?);
+    }
+    for bcc in &message.bcc {
+        builder = builder.bcc(bcc.as_ref().parse::<Mailbox>()?);
+    }
+    if let Some(reply_to) = &message.reply_to {
+        builder = builder.reply_to(reply_to.as_ref().parse::<Mailbox>()?);
+    }
+
+    for (name, value) in &message.headers {
+        builder = builder.raw_header(HeaderValue::new(
+            HeaderName::new_from_ascii_str(name),
+            value.clone(),
+        ));
+    }
+
+    let message = builder.multipart(MultiPart::alternative_plain_html(
+        message.raw_content.clone(),
+        message.html_content.clone(),
+    ))?;
+
+    Ok(message)
+}
+
+#[async_trait]
+impl EmailSender for Smtp {
+    async fn send(&self, message: &Message) -> Result<(), SendError> {
+        self.transport.send(mime_message(message)?).await?;
+
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
+
+    use claims::assert_ok;
+    use tokio::io::{AsyncBufReadExt, AsyncWriteExt, BufReader};
+    use tokio::net::TcpListener;
+    use tokio::sync::oneshot;
+
+    use super::Smtp;
+    use crate::config::SmtpConfig;
+    use crate::domain::Email;
+    use crate::email_client::{EmailSender, Message};
+
+    /// Accepts a single message and hands back what followed `DATA`.
+    async fn stub_smtp_server() -> (u16, oneshot::Receiver<String>) {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let port = listener.local_addr().unwrap().port();
+        let (tx, rx) = oneshot::channel();
+
+        tokio::spawn(async move {
+            let (stream, _) = listener.accept().await.unwrap();
+            let (reader, mut writer) = stream.into_split();
+            let mut lines = BufReader::new(reader).lines();
+            let mut tx = Some(tx);
+
+            writer.write_all(b"220 stub ESMTP\r\n").await.unwrap();
+
+            while let Ok(Some(line)) = lines.next_line().await {
+                let reply: &[u8] = match line.to_uppercase().as_str() {
+                    "DATA" => {
+                        writer.write_all(b"354 go ahead\r\n").await.unwrap();
+
+                        let mut data = String::new();
+                        while let Ok(Some(line)) = lines.next_line().await {
+                            if line == "." {
+                                break;
+                            }
+                            data.push_str(&line);
+                            data.push('\n');
+                        }
+
+                        if let Some(tx) = tx.take() {
+                            let _ = tx.send(data);
+                        }
+                        b"250 queued\r\n"
+                    }
+                    "QUIT" => {
+                        writer.write_all(b"221 bye\r\n").await.unwrap();
+                        break;
+                    }
+                    _ => b"250 OK\r\n",
+                };
+
+                writer.write_all(reply).await.unwrap();
+            }
+        });
+
+        (port, rx)
+    }
+
+    #[tokio::test]
+    async fn send_delivers_multipart_alternative_with_custom_headers() {
+        let (port, data) = stub_smtp_server().await;
+        let config = SmtpConfig {
+            port,
+            ..Default::default()
+        };
+        let smtp = Smtp::new(&config, Duration::from_secs(1)).unwrap();
+
+        let email = |address: &str| Email::try_from(address.to_string()).unwrap();
+
+        let message = Message::new(email("sender@lzzzt.cc"), email("main@lzzzt.cc"))
+            .with_subject("Welcome")
+            .with_text("Plain body")
+            .with_html("<p>Html body</p>")
+            .add_cc(email("cc@lzzzt.cc"))
+            .add_bcc(email("bcc@lzzzt.cc"))
+            .with_reply_to(email("reply@lzzzt.cc"))
+            .with_header("List-Unsubscribe", "<http://127.0.0.1/unsubscribe>");
+
+        assert_ok!(smtp.send(&message).await);
+
+        let data = data.await.unwrap();
+        assert!(data.contains("Subject: Welcome"));
+        assert!(data.contains("Cc: cc@lzzzt.cc"));
+        assert!(data.contains("Reply-To: reply@lzzzt.cc"));
+        assert!(!data.contains("bcc@lzzzt.cc"));
+        assert!(data.contains("List-Unsubscribe: <http://127.0.0.1/unsubscribe>"));
+        assert!(data.contains("multipart/alternative"));
+        assert!(data.contains("Plain body"));
+        assert!(data.contains("<p>Html body</p>"));
+    }
+}
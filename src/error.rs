@@ -0,0 +1,317 @@
+use axum::extract::Request;
+use axum::extract::rejection::{FormRejection, JsonRejection, PathRejection, QueryRejection};
+use axum::http::{HeaderValue, StatusCode, header};
+use axum::middleware::Next;
+use axum::response::{IntoResponse, Response};
+use serde::Serialize;
+
+use crate::domain::SubscriberError;
+use crate::email_client::SendError;
+
+const PROBLEM_JSON: &str = "application/problem+json";
+
+/// The error returned by every handler.
+///
+/// It renders as an RFC 7807 `application/problem+json` document carrying a
+/// stable `code`, the failing fields if any, and the id of the request.
+#[derive(Debug, thiserror::Error)]
+pub enum AppError {
+    #[error("The submitted data is not valid.")]
+    Validation(Vec<FieldError>),
+    #[error("{detail}")]
+    BadRequest {
+        status: StatusCode,
+        code: &'static str,
+        detail: String,
+        errors: Vec<FieldError>,
+    },
+    #[error("{0}")]
+    Unauthorized(&'static str),
+    #[error("Valid credentials are required.")]
+    Unauthenticated,
+    #[error("{0}")]
+    NotFound(&'static str),
+    /// A dependency is refusing work for now; retrying later can succeed.
+    #[error("{detail}")]
+    ServiceUnavailable { code: &'static str, detail: String },
+    #[error("Something went wrong while processing the request.")]
+    Unexpected(#[source] Box<dyn std::error::Error + Send + Sync>),
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct FieldError {
+    pub field: String,
+    pub code: &'static str,
+    pub message: String,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct Problem {
+    #[serde(rename = "type")]
+    kind: &'static str,
+    title: &'static str,
+    status: u16,
+    detail: String,
+    code: &'static str,
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    errors: Vec<FieldError>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    request_id: Option<String>,
+}
+
+impl AppError {
+    pub fn status(&self) -> StatusCode {
+        match self {
+            AppError::Validation(_) => StatusCode::UNPROCESSABLE_ENTITY,
+            AppError::BadRequest { status, .. } => *status,
+            AppError::Unauthorized(_) | AppError::Unauthenticated => StatusCode::UNAUTHORIZED,
+            AppError::NotFound(_) => StatusCode::NOT_FOUND,
+            AppError::ServiceUnavailable { .. } => StatusCode::SERVICE_UNAVAILABLE,
+            AppError::Unexpected(_) => StatusCode::INTERNAL_SERVER_ERROR,
+        }
+    }
+
+    pub fn code(&self) -> &'static str {
+        match self {
+            AppError::Validation(_) => "validation_failed",
+            AppError::BadRequest { code, .. } => code,
+            AppError::Unauthorized(_) => "unauthorized",
+            AppError::Unauthenticated => "unauthenticated",
+            AppError::NotFound(_) => "not_found",
+            AppError::ServiceUnavailable { code, .. } => code,
+            AppError::Unexpected(_) => "internal_error",
+        }
+    }
+
+    fn problem(&self) -> Problem {
+        let status = self.status();
+
+        let errors = match self {
+            AppError::Validation(errors) | AppError::BadRequest { errors, .. } => errors.clone(),
+            _ => vec![],
+        };
+
+        Problem {
+            kind: "about:blank",
+            title: status.canonical_reason().unwrap_or("Error"),
+            status: status.as_u16(),
+            detail: self.to_string(),
+            code: self.code(),
+            errors,
+            request_id: None,
+        }
+    }
+}
+
+impl IntoResponse for AppError {
+    fn into_response(self) -> Response {
+        if let AppError::Unexpected(e) = &self {
+            tracing::error!(error = ?e, "Failed to process request");
+        }
+
+        let mut response = self.problem().into_response();
+
+        if let AppError::Unauthenticated = self {
+            response.headers_mut().insert(
+                header::WWW_AUTHENTICATE,
+                HeaderValue::from_static(r#"Basic realm="admin""#),
+            );
+        }
+
+        response
+    }
+}
+
+impl Problem {
+    fn with_request_id(mut self, request_id: String) -> Self {
+        self.request_id = Some(request_id);
+        self
+    }
+}
+
+impl IntoResponse for Problem {
+    fn into_response(self) -> Response {
+        let status = StatusCode::from_u16(self.status).unwrap_or(StatusCode::INTERNAL_SERVER_ERROR);
+        let body = serde_json::to_vec(&self).expect("Problem is always serializable");
+
+        let mut response = (status, [(header::CONTENT_TYPE, PROBLEM_JSON)], body).into_response();
+        response.extensions_mut().insert(self);
+        response
+    }
+}
+
+/// Re-renders problem responses so that their body carries the request id.
+///
+/// Handlers don't see the id, so [`AppError`] stores its [`Problem`] in the
+/// response extensions and this middleware fills it in on the way out.
+pub async fn attach_request_id(request: Request, next: Next) -> Response {
+    let request_id = request
+        .headers()
+        .get("x-request-id")
+        .and_then(|id| id.to_str().ok())
+        .map(String::from);
+
+    let response = next.run(request).await;
+
+    let Some(request_id) = request_id else {
+        return response;
+    };
+
+    let Some(problem) = response.extensions().get::<Problem>().cloned() else {
+        return response;
+    };
+
+    // Keep headers such as `WWW-Authenticate`, only the body changes.
+    let (mut parts, _) = response.into_parts();
+    let (rendered, body) = problem
+        .with_request_id(request_id)
+        .into_response()
+        .into_parts();
+    parts.headers.remove(header::CONTENT_LENGTH);
+    parts.extensions = rendered.extensions;
+
+    Response::from_parts(parts, body)
+}
+
+impl From<SubscriberError> for AppError {
+    fn from(value: SubscriberError) -> Self {
+        AppError::Validation(
+            value
+                .0
+                .into_iter()
+                .map(|e| FieldError {
+                    field: e.field().into(),
+                    code: e.code(),
+                    message: e.to_string(),
+                })
+                .collect(),
+        )
+    }
+}
+
+impl From<FormRejection> for AppError {
+    fn from(value: FormRejection) -> Self {
+        let detail = value.body_text();
+
+        AppError::BadRequest {
+            status: value.status(),
+            code: "invalid_body",
+            errors: missing_field(&detail).into_iter().collect(),
+            detail,
+        }
+    }
+}
+
+impl From<JsonRejection> for AppError {
+    fn from(value: JsonRejection) -> Self {
+        let detail = value.body_text();
+
+        AppError::BadRequest {
+            status: value.status(),
+            code: "invalid_body",
+            errors: missing_field(&detail).into_iter().collect(),
+            detail,
+        }
+    }
+}
+
+impl From<QueryRejection> for AppError {
+    fn from(value: QueryRejection) -> Self {
+        let detail = value.body_text();
+
+        AppError::BadRequest {
+            status: value.status(),
+            code: "invalid_query",
+            errors: missing_field(&detail).into_iter().collect(),
+            detail,
+        }
+    }
+}
+
+impl From<PathRejection> for AppError {
+    fn from(value: PathRejection) -> Self {
+        AppError::BadRequest {
+            status: value.status(),
+            code: "invalid_path",
+            detail: value.body_text(),
+            errors: vec![],
+        }
+    }
+}
+
+impl From<sqlx::Error> for AppError {
+    fn from(value: sqlx::Error) -> Self {
+        AppError::Unexpected(Box::new(value))
+    }
+}
+
+impl From<SendError> for AppError {
+    fn from(value: SendError) -> Self {
+        match value {
+            SendError::QuotaExceeded { .. } => AppError::ServiceUnavailable {
+                code: "email_quota_exceeded",
+                detail: value.to_string(),
+            },
+            SendError::CircuitOpen { .. } => AppError::ServiceUnavailable {
+                code: "email_provider_unavailable",
+                detail: value.to_string(),
+            },
+            value => AppError::Unexpected(Box::new(value)),
+        }
+    }
+}
+
+/// Extracts the field name from serde's "missing field `name`" message.
+fn missing_field(detail: &str) -> Option<FieldError> {
+    let (_, rest) = detail.split_once("missing field `")?;
+    let (field, _) = rest.split_once('`')?;
+
+    Some(FieldError {
+        field: field.into(),
+        code: "missing",
+        message: format!("`{field}` is required."),
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use axum::http::StatusCode;
+    use claims::{assert_none, assert_some};
+
+    use super::{AppError, missing_field};
+    use crate::email_client::SendError;
+
+    #[test]
+    fn missing_field_is_extracted_from_serde_messages() {
+        let detail = "Failed to deserialize form body: missing field `email`";
+
+        let field = assert_some!(missing_field(detail));
+        assert_eq!(field.field, "email");
+        assert_eq!(field.code, "missing");
+    }
+
+    #[test]
+    fn other_messages_have_no_missing_field() {
+        assert_none!(missing_field("Form requests must have `Content-Type`"));
+    }
+
+    #[test]
+    fn unexpected_errors_do_not_leak_their_source() {
+        let error = AppError::Unexpected("connection refused".into());
+        let problem = error.problem();
+
+        assert_eq!(problem.status, StatusCode::INTERNAL_SERVER_ERROR.as_u16());
+        assert!(!problem.detail.contains("connection refused"));
+    }
+
+    #[test]
+    fn an_open_circuit_is_a_service_unavailable_error() {
+        let error = AppError::from(SendError::CircuitOpen {
+            retry_at: chrono::Utc::now(),
+        });
+
+        assert!(matches!(error, AppError::ServiceUnavailable { .. }));
+        assert_eq!(error.status(), StatusCode::SERVICE_UNAVAILABLE);
+        assert_eq!(error.code(), "email_provider_unavailable");
+    }
+}
@@ -0,0 +1,57 @@
+use axum::extract::{FromRequest, Request};
+use axum::http::header::CONTENT_TYPE;
+use axum::{Form, Json};
+use serde::de::DeserializeOwned;
+
+use crate::error::AppError;
+
+/// The encoding a request body was sent in.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum BodyFormat {
+    Form,
+    Json,
+}
+
+/// Extracts `T` from either an `application/json` or an
+/// `application/x-www-form-urlencoded` body, depending on `Content-Type`.
+///
+/// The format is kept so that handlers can answer in kind.
+pub struct FormOrJson<T> {
+    pub data: T,
+    pub format: BodyFormat,
+}
+
+impl<S, T> FromRequest<S> for FormOrJson<T>
+where
+    S: Send + Sync,
+    T: DeserializeOwned,
+{
+    type Rejection = AppError;
+
+    async fn from_request(req: Request, state: &S) -> Result<Self, Self::Rejection> {
+        if is_json(&req) {
+            let Json(data) = Json::<T>::from_request(req, state).await?;
+
+            Ok(Self {
+                data,
+                format: BodyFormat::Json,
+            })
+        } else {
+            let Form(data) = Form::<T>::from_request(req, state).await?;
+
+            Ok(Self {
+                data,
+                format: BodyFormat::Form,
+            })
+        }
+    }
+}
+
+fn is_json(req: &Request) -> bool {
+    req.headers()
+        .get(CONTENT_TYPE)
+        .and_then(|content_type| content_type.to_str().ok())
+        .and_then(|content_type| content_type.split(';').next())
+        .map(|mime| mime.trim().to_ascii_lowercase())
+        .is_some_and(|mime| mime == "application/json" || mime.ends_with("+json"))
+}
@@ -0,0 +1,445 @@
+use std::time::Duration;
+
+use axum::Extension;
+use axum::body::{Body, to_bytes};
+use axum::extract::{Request, State};
+use axum::http::{HeaderMap, HeaderName, HeaderValue, StatusCode};
+use axum::middleware::Next;
+use axum::response::Response;
+use chrono::{TimeDelta, Utc};
+use sha2::{Digest, Sha256};
+use sqlx::PgPool;
+use tokio::time::Instant;
+use tracing::{Instrument, instrument};
+use uuid::Uuid;
+
+use crate::authentication::AdminUser;
+use crate::error::AppError;
+
+const IDEMPOTENCY_KEY: &str = "idempotency-key";
+const IDEMPOTENT_REPLAYED: &str = "idempotent-replayed";
+const MAX_KEY_CHARS: usize = 50;
+/// Same as axum's default body limit.
+const MAX_BODY_BYTES: usize = 2 * 1024 * 1024;
+/// How long a duplicate waits for the first request to finish before it is
+/// told to retry, checking every `IN_PROGRESS_POLL_INTERVAL`.
+const IN_PROGRESS_TIMEOUT: Duration = Duration::from_secs(5);
+const IN_PROGRESS_POLL_INTERVAL: Duration = Duration::from_millis(50);
+/// How long a key stays in progress before a retry can take it over. Longer
+/// than any admin handler runs, sending a test draft and its retries included.
+const IN_PROGRESS_LEASE: Duration = Duration::from_secs(60);
+
+/// How long a stored response can be replayed for.
+#[derive(Debug, Clone, Copy)]
+pub struct IdempotencyKeyTtl(pub Duration);
+
+#[derive(Debug, Clone)]
+pub struct IdempotencyKey(String);
+
+#[derive(Debug, Clone, PartialEq, Eq, thiserror::Error)]
+pub enum IdempotencyKeyError {
+    #[error("The `Idempotency-Key` header must not be empty.")]
+    Empty,
+    #[error("The `Idempotency-Key` header must not be longer than {MAX_KEY_CHARS} characters.")]
+    TooLong,
+}
+
+impl TryFrom<String> for IdempotencyKey {
+    type Error = IdempotencyKeyError;
+
+    fn try_from(value: String) -> Result<Self, Self::Error> {
+        if value.trim().is_empty() {
+            return Err(IdempotencyKeyError::Empty);
+        }
+
+        if value.chars().count() > MAX_KEY_CHARS {
+            return Err(IdempotencyKeyError::TooLong);
+        }
+
+        Ok(Self(value))
+    }
+}
+
+impl AsRef<str> for IdempotencyKey {
+    fn as_ref(&self) -> &str {
+        &self.0
+    }
+}
+
+/// Makes mutating requests safe to retry: the first response for an
+/// `Idempotency-Key` is stored per admin user and replayed for later requests
+/// with the same key, which wait while the first one is still running.
+///
+/// The key is committed as in progress before the handler runs, so that the
+/// handler does not run twice for it. The handler runs to completion and has
+/// its response saved even if the client goes away, and a key left in progress
+/// by a process that stopped is taken over once its `IN_PROGRESS_LEASE` is
+/// over.
+///
+/// Server errors are not stored, so the request can be retried once the
+/// problem is gone. Must run after [`require_admin`](crate::authentication::require_admin).
+pub async fn idempotency(
+    State(pool): State<PgPool>,
+    State(IdempotencyKeyTtl(ttl)): State<IdempotencyKeyTtl>,
+    Extension(admin): Extension<AdminUser>,
+    request: Request,
+    next: Next,
+) -> Result<Response, AppError> {
+    if request.method().is_safe() {
+        return Ok(next.run(request).await);
+    }
+
+    let key = idempotency_key(request.headers())?;
+
+    let (parts, body) = request.into_parts();
+    let body = to_bytes(body, MAX_BODY_BYTES)
+        .await
+        .map_err(|e| AppError::BadRequest {
+            status: StatusCode::BAD_REQUEST,
+            code: "invalid_body",
+            detail: e.to_string(),
+            errors: vec![],
+        })?;
+    let target = parts
+        .uri
+        .path_and_query()
+        .map_or("/", |target| target.as_str());
+    let fingerprint = fingerprint(parts.method.as_str(), target, &body);
+
+    delete_expired_keys(&pool, ttl).await?;
+
+    if !insert_key(&pool, admin.user_id, &key, &fingerprint).await? {
+        return saved_response(&pool, admin.user_id, &key, &fingerprint).await;
+    }
+
+    let request = Request::from_parts(parts, Body::from(body));
+    let handler = {
+        let pool = pool.clone();
+        let key = key.clone();
+        tokio::spawn(
+            async move {
+                let response = next.run(request).await;
+                finish(&pool, admin.user_id, &key, response).await
+            }
+            .in_current_span(),
+        )
+    };
+
+    match handler.await {
+        Ok(response) => response,
+        // The handler panicked.
+        Err(e) => {
+            release_key(&pool, admin.user_id, &key).await?;
+            Err(AppError::Unexpected(Box::new(e)))
+        }
+    }
+}
+
+/// Saves the handler's response for the key, or releases the key after a
+/// server error.
+async fn finish(
+    pool: &PgPool,
+    user_id: Uuid,
+    key: &IdempotencyKey,
+    response: Response,
+) -> Result<Response, AppError> {
+    if response.status().is_server_error() {
+        release_key(pool, user_id, key).await?;
+        return Ok(response);
+    }
+
+    let (parts, body) = response.into_parts();
+    let body = to_bytes(body, usize::MAX)
+        .await
+        .map_err(|e| AppError::Unexpected(Box::new(e)))?;
+
+    save_response(pool, user_id, key, parts.status, &parts.headers, &body).await?;
+
+    Ok(Response::from_parts(parts, Body::from(body)))
+}
+
+fn idempotency_key(headers: &HeaderMap) -> Result<IdempotencyKey, AppError> {
+    let value = headers
+        .get(IDEMPOTENCY_KEY)
+        .ok_or_else(|| AppError::BadRequest {
+            status: StatusCode::BAD_REQUEST,
+            code: "missing_idempotency_key",
+            detail: "The `Idempotency-Key` header is required.".into(),
+            errors: vec![],
+        })?;
+
+    let invalid = |detail: String| AppError::BadRequest {
+        status: StatusCode::BAD_REQUEST,
+        code: "invalid_idempotency_key",
+        detail,
+        errors: vec![],
+    };
+
+    let value = value
+        .to_str()
+        .map_err(|_| invalid("The `Idempotency-Key` header must be visible ASCII.".into()))?;
+
+    IdempotencyKey::try_from(value.to_string()).map_err(|e| invalid(e.to_string()))
+}
+
+/// Identifies what a key was first used for, so that reusing it for another
+/// request is refused instead of replaying an unrelated response. `target` is
+/// the path along with the query string.
+fn fingerprint(method: &str, target: &str, body: &[u8]) -> Vec<u8> {
+    Sha256::new()
+        .chain_update(method)
+        .chain_update([0])
+        .chain_update(target)
+        .chain_update([0])
+        .chain_update(body)
+        .finalize()
+        .to_vec()
+}
+
+#[instrument(skip_all, name = "Deleting expired idempotency keys")]
+async fn delete_expired_keys(pool: &PgPool, ttl: Duration) -> Result<(), sqlx::Error> {
+    let expired_before = Utc::now() - TimeDelta::from_std(ttl).unwrap_or(TimeDelta::MAX);
+
+    sqlx::query!(
+        r#"DELETE FROM idempotency WHERE created_at < $1"#,
+        expired_before,
+    )
+    .execute(pool)
+    .await
+    .inspect_err(|e| tracing::error!("Failed to execute query: {e:?}"))?;
+
+    Ok(())
+}
+
+/// Returns whether the key was new, or left in progress past its lease by the
+/// same request, in which case it is now in progress for this one: its
+/// response columns stay `NULL` until [`save_response`].
+#[instrument(skip_all, name = "Inserting idempotency key")]
+async fn insert_key(
+    pool: &PgPool,
+    user_id: Uuid,
+    key: &IdempotencyKey,
+    fingerprint: &[u8],
+) -> Result<bool, sqlx::Error> {
+    let now = Utc::now();
+
+    let result = sqlx::query!(
+        r#"
+        INSERT INTO idempotency (
+            user_id, idempotency_key, request_fingerprint, created_at, locked_until
+        )
+        VALUES ($1, $2, $3, $4, $5)
+        ON CONFLICT (user_id, idempotency_key) DO UPDATE
+        SET created_at = EXCLUDED.created_at, locked_until = EXCLUDED.locked_until
+        WHERE idempotency.response_status_code IS NULL
+            AND idempotency.locked_until < EXCLUDED.created_at
+            AND idempotency.request_fingerprint = EXCLUDED.request_fingerprint
+        "#,
+        user_id,
+        key.as_ref(),
+        fingerprint,
+        now,
+        now + TimeDelta::from_std(IN_PROGRESS_LEASE).unwrap_or(TimeDelta::MAX),
+    )
+    .execute(pool)
+    .await
+    .inspect_err(|e| tracing::error!("Failed to execute query: {e:?}"))?;
+
+    Ok(result.rows_affected() == 1)
+}
+
+/// Frees a key still in progress, so that the request can be retried.
+#[instrument(skip_all, name = "Releasing idempotency key")]
+async fn release_key(
+    pool: &PgPool,
+    user_id: Uuid,
+    key: &IdempotencyKey,
+) -> Result<(), sqlx::Error> {
+    sqlx::query!(
+        r#"
+        DELETE FROM idempotency
+        WHERE user_id = $1 AND idempotency_key = $2 AND response_status_code IS NULL
+        "#,
+        user_id,
+        key.as_ref(),
+    )
+    .execute(pool)
+    .await
+    .inspect_err(|e| tracing::error!("Failed to execute query: {e:?}"))?;
+
+    Ok(())
+}
+
+#[instrument(skip_all, name = "Saving response for idempotency key")]
+async fn save_response(
+    pool: &PgPool,
+    user_id: Uuid,
+    key: &IdempotencyKey,
+    status: StatusCode,
+    headers: &HeaderMap,
+    body: &[u8],
+) -> Result<(), sqlx::Error> {
+    let (names, values): (Vec<_>, Vec<_>) = headers
+        .iter()
+        .map(|(name, value)| (name.as_str().to_string(), value.as_bytes().to_vec()))
+        .unzip();
+
+    sqlx::query!(
+        r#"
+        UPDATE idempotency
+        SET
+            response_status_code = $3,
+            response_header_names = $4,
+            response_header_values = $5,
+            response_body = $6
+        WHERE user_id = $1 AND idempotency_key = $2
+        "#,
+        user_id,
+        key.as_ref(),
+        status.as_u16() as i16,
+        &names,
+        &values,
+        body,
+    )
+    .execute(pool)
+    .await
+    .inspect_err(|e| tracing::error!("Failed to execute query: {e:?}"))?;
+
+    Ok(())
+}
+
+/// Waits for the first request with the key to finish, without holding a
+/// connection in the meantime, and replays its response.
+#[instrument(skip_all, name = "Replaying saved response")]
+async fn saved_response(
+    pool: &PgPool,
+    user_id: Uuid,
+    key: &IdempotencyKey,
+    fingerprint: &[u8],
+) -> Result<Response, AppError> {
+    let deadline = Instant::now() + IN_PROGRESS_TIMEOUT;
+
+    loop {
+        if let Some(response) = try_saved_response(pool, user_id, key, fingerprint).await? {
+            return Ok(response);
+        }
+
+        if Instant::now() >= deadline {
+            return Err(AppError::BadRequest {
+                status: StatusCode::CONFLICT,
+                code: "idempotency_key_in_progress",
+                detail: "A request with the same `Idempotency-Key` has not finished yet, retry it later.".into(),
+                errors: vec![],
+            });
+        }
+
+        tokio::time::sleep(IN_PROGRESS_POLL_INTERVAL).await;
+    }
+}
+
+/// `None` while the first request is still in progress.
+async fn try_saved_response(
+    pool: &PgPool,
+    user_id: Uuid,
+    key: &IdempotencyKey,
+    fingerprint: &[u8],
+) -> Result<Option<Response>, AppError> {
+    let saved = sqlx::query!(
+        r#"
+        SELECT
+            request_fingerprint,
+            response_status_code,
+            response_header_names,
+            response_header_values,
+            response_body
+        FROM idempotency
+        WHERE user_id = $1 AND idempotency_key = $2
+        "#,
+        user_id,
+        key.as_ref(),
+    )
+    .fetch_optional(pool)
+    .await
+    .inspect_err(|e| tracing::error!("Failed to execute query: {e:?}"))?;
+
+    // The first request failed with a server error and released the key, or
+    // the key expired in the meantime.
+    let Some(saved) = saved else {
+        return Err(AppError::BadRequest {
+            status: StatusCode::CONFLICT,
+            code: "idempotency_key_conflict",
+            detail: "The request could not be matched with its `Idempotency-Key`, retry it.".into(),
+            errors: vec![],
+        });
+    };
+
+    if saved.request_fingerprint != fingerprint {
+        return Err(AppError::BadRequest {
+            status: StatusCode::UNPROCESSABLE_ENTITY,
+            code: "idempotency_key_reused",
+            detail: "The `Idempotency-Key` was already used for a different request.".into(),
+            errors: vec![],
+        });
+    }
+
+    let (Some(status), Some(names), Some(values), Some(body)) = (
+        saved.response_status_code,
+        saved.response_header_names,
+        saved.response_header_values,
+        saved.response_body,
+    ) else {
+        return Ok(None);
+    };
+
+    let mut response = Response::new(Body::from(body));
+    *response.status_mut() =
+        StatusCode::from_u16(status as u16).map_err(|e| AppError::Unexpected(Box::new(e)))?;
+
+    let headers = response.headers_mut();
+    for (name, value) in names.into_iter().zip(values) {
+        let name = HeaderName::try_from(name).map_err(|e| AppError::Unexpected(Box::new(e)))?;
+        let value = HeaderValue::try_from(value).map_err(|e| AppError::Unexpected(Box::new(e)))?;
+        headers.append(name, value);
+    }
+    headers.insert(IDEMPOTENT_REPLAYED, HeaderValue::from_static("true"));
+
+    Ok(Some(response))
+}
+
+#[cfg(test)]
+mod tests {
+    use claims::{assert_err_eq, assert_ok};
+
+    use super::{IdempotencyKey, IdempotencyKeyError, fingerprint};
+
+    #[test]
+    fn empty_keys_are_rejected() {
+        assert_err_eq!(
+            IdempotencyKey::try_from("  ".to_string()),
+            IdempotencyKeyError::Empty
+        );
+    }
+
+    #[test]
+    fn keys_longer_than_50_characters_are_rejected() {
+        assert_ok!(IdempotencyKey::try_from("a".repeat(50)));
+        assert_err_eq!(
+            IdempotencyKey::try_from("a".repeat(51)),
+            IdempotencyKeyError::TooLong
+        );
+    }
+
+    #[test]
+    fn fingerprint_covers_method_target_and_body() {
+        let original = fingerprint("POST", "/admin/newsletters", b"{}");
+
+        assert_eq!(original, fingerprint("POST", "/admin/newsletters", b"{}"));
+        assert_ne!(original, fingerprint("PUT", "/admin/newsletters", b"{}"));
+        assert_ne!(original, fingerprint("POST", "/admin/drafts", b"{}"));
+        assert_ne!(
+            original,
+            fingerprint("POST", "/admin/newsletters?a=b", b"{}")
+        );
+        assert_ne!(original, fingerprint("POST", "/admin/newsletters", b"[]"));
+    }
+}
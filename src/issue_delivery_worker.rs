@@ -0,0 +1,533 @@
+use std::collections::{BTreeMap, HashMap};
+use std::sync::Arc;
+use std::time::Duration;
+
+use chrono::{DateTime, TimeDelta, Utc};
+use sqlx::types::Json;
+use sqlx::{PgExecutor, PgPool, Postgres, Transaction};
+use tracing::{Span, field::display, instrument};
+use uuid::Uuid;
+
+use crate::config::DeliveryConfig;
+use crate::domain::Email;
+use crate::email_client::{EmailClient, SendError};
+use crate::template::{NewsletterTemplate, Recipient};
+
+/// Delivers newsletter issues queued in `issue_delivery_queue`, in batches of
+/// recipients of the same issue.
+///
+/// Several workers, in this process or in other replicas, can share the queue:
+/// a task is claimed with `FOR UPDATE SKIP LOCKED` for as long as it is being
+/// handled.
+#[derive(Clone)]
+pub struct DeliveryWorker {
+    pool: PgPool,
+    email_client: Arc<EmailClient>,
+    config: DeliveryConfig,
+}
+
+#[derive(Debug, PartialEq, Eq)]
+pub enum ExecutionOutcome {
+    TaskCompleted,
+    EmptyQueue,
+}
+
+impl DeliveryWorker {
+    pub fn new(pool: PgPool, email_client: Arc<EmailClient>, config: DeliveryConfig) -> Self {
+        Self {
+            pool,
+            email_client,
+            config,
+        }
+    }
+
+    pub async fn run_until_stopped(self) {
+        let poll_interval = Duration::from_millis(self.config.poll_interval_ms);
+
+        loop {
+            match self.try_execute_task().await {
+                Ok(ExecutionOutcome::TaskCompleted) => {}
+                Ok(ExecutionOutcome::EmptyQueue) => tokio::time::sleep(poll_interval).await,
+                Err(_) => tokio::time::sleep(poll_interval).await,
+            }
+        }
+    }
+
+    /// Claims due tasks of a single issue, up to `batch_size` and to what the
+    /// email backend takes in a single request, and attempts their delivery
+    /// once.
+    ///
+    /// Claiming no more than a request's worth keeps the transaction, and the
+    /// duplicates a crash would cause, down to one request.
+    ///
+    /// Failed attempts are rescheduled with exponential backoff until
+    /// `max_attempts` is reached, at which point the task is dead-lettered.
+    #[instrument(
+        skip_all,
+        name = "Executing delivery tasks",
+        fields(newsletter_issue_id = tracing::field::Empty, n_tasks = tracing::field::Empty),
+        err
+    )]
+    pub async fn try_execute_task(&self) -> Result<ExecutionOutcome, sqlx::Error> {
+        let mut transaction = self.pool.begin().await?;
+
+        let batch_size = self
+            .email_client
+            .max_batch_size()
+            .min(self.config.batch_size.into());
+        let tasks = dequeue_tasks(&mut transaction, batch_size).await?;
+        let Some(newsletter_issue_id) = tasks.first().map(|task| task.newsletter_issue_id) else {
+            return Ok(ExecutionOutcome::EmptyQueue);
+        };
+
+        Span::current()
+            .record("newsletter_issue_id", display(newsletter_issue_id))
+            .record("n_tasks", tasks.len());
+
+        let issue = get_issue(&mut transaction, newsletter_issue_id).await?;
+        // Only issues saved before `validate_content` existed can fail here.
+        let newsletter =
+            match NewsletterTemplate::parse(&issue.title, &issue.text_content, &issue.html_content)
+            {
+                Ok(newsletter) => newsletter,
+                Err(e) => {
+                    tracing::error!(error = %e, "Dead-lettering deliveries of an invalid template");
+                    for task in &tasks {
+                        mark_task_dead(&mut transaction, task, &e.to_string()).await?;
+                    }
+                    transaction.commit().await?;
+                    return Ok(ExecutionOutcome::TaskCompleted);
+                }
+            };
+
+        let mut deliverable = vec![];
+        let mut emails = vec![];
+        for task in tasks {
+            match Email::try_from(task.subscriber_email.clone()) {
+                Ok(email) => {
+                    deliverable.push(task);
+                    emails.push(email);
+                }
+                Err(e) => {
+                    tracing::warn!(
+                        error = %e,
+                        subscriber_email = %task.subscriber_email,
+                        "Dead-lettering a delivery to an invalid email"
+                    );
+                    mark_task_dead(&mut transaction, &task, &e.to_string()).await?;
+                }
+            }
+        }
+
+        // Subscribers can unsubscribe between the issue being published and
+        // their delivery.
+        let mut confirmed = get_confirmed_recipients(&mut *transaction, &emails).await?;
+        let mut recipients = vec![];
+        let mut tasks = vec![];
+        for (task, email) in deliverable.into_iter().zip(emails) {
+            match confirmed.remove(email.as_ref()) {
+                Some(recipient) => {
+                    recipients.push(recipient);
+                    tasks.push(task);
+                }
+                None => mark_task_skipped(&mut transaction, &task).await?,
+            }
+        }
+
+        let results = self
+            .email_client
+            .send_newsletter_batch(&newsletter, recipients)
+            .await;
+
+        // Results come in the order recipients were handed over.
+        let mut tasks = tasks.into_iter();
+        for batch in results {
+            let batch_tasks = tasks.by_ref().take(batch.recipients.len());
+
+            match batch.result {
+                Ok(()) => {
+                    for task in batch_tasks {
+                        mark_task_delivered(&mut transaction, &task).await?;
+                    }
+                }
+                // Not an attempt: nothing was sent, and the quota or the
+                // circuit breaker says when to try again.
+                Err(
+                    e @ (SendError::QuotaExceeded { resets_at: until }
+                    | SendError::CircuitOpen { retry_at: until }),
+                ) => {
+                    tracing::warn!(error = %e, n_recipients = batch.recipients.len(), "Postponing deliveries");
+                    for task in batch_tasks {
+                        postpone_task(&mut transaction, &task, until, &e.to_string()).await?;
+                    }
+                }
+                Err(e) => {
+                    tracing::warn!(error = ?e, n_recipients = batch.recipients.len(), "Batch delivery failed");
+                    for task in batch_tasks {
+                        self.record_failure(&mut transaction, &task, &e.to_string())
+                            .await?;
+                    }
+                }
+            }
+        }
+
+        transaction.commit().await?;
+
+        Ok(ExecutionOutcome::TaskCompleted)
+    }
+
+    /// Reschedules the task, or dead-letters it once it ran out of attempts.
+    async fn record_failure(
+        &self,
+        transaction: &mut Transaction<'_, Postgres>,
+        task: &Task,
+        error: &str,
+    ) -> Result<(), sqlx::Error> {
+        let n_attempts = task.n_attempts as u32 + 1;
+
+        if n_attempts >= self.config.max_attempts {
+            tracing::error!(
+                error,
+                n_attempts,
+                subscriber_email = %task.subscriber_email,
+                "Giving up on a delivery"
+            );
+            mark_task_dead(transaction, task, error).await
+        } else {
+            reschedule_task(transaction, task, backoff(&self.config, n_attempts), error).await
+        }
+    }
+}
+
+/// The delay before the next attempt, after `n_attempts` failed ones.
+fn backoff(config: &DeliveryConfig, n_attempts: u32) -> Duration {
+    let factor = 2u64.saturating_pow(n_attempts.saturating_sub(1));
+    let delay = config.base_backoff_ms.saturating_mul(factor);
+
+    Duration::from_millis(delay.min(config.max_backoff_ms))
+}
+
+/// Queues one delivery per confirmed subscriber and returns how many.
+#[instrument(skip_all, name = "Enqueuing delivery tasks")]
+pub async fn enqueue_delivery_tasks(
+    transaction: &mut Transaction<'_, Postgres>,
+    newsletter_issue_id: Uuid,
+) -> Result<i64, sqlx::Error> {
+    let result = sqlx::query!(
+        r#"
+        INSERT INTO issue_delivery_queue (newsletter_issue_id, subscriber_email)
+        SELECT DISTINCT $1::uuid, email FROM subscriptions WHERE status = 'confirmed'
+        ON CONFLICT DO NOTHING
+        "#,
+        newsletter_issue_id,
+    )
+    .execute(&mut **transaction)
+    .await
+    .inspect_err(|e| tracing::error!("Failed to execute query: {e:?}"))?;
+
+    Ok(result.rows_affected() as i64)
+}
+
+/// What templates know about a subscriber. Addresses that are not on the
+/// list get empty values.
+#[instrument(skip_all, name = "Getting recipient")]
+pub async fn get_recipient(
+    executor: impl PgExecutor<'_>,
+    email: Email,
+) -> Result<Recipient, sqlx::Error> {
+    let mut recipients = get_recipients(executor, vec![email]).await?;
+
+    Ok(recipients.remove(0))
+}
+
+/// [`get_recipient`] for many addresses at once, in the same order.
+#[instrument(skip_all, name = "Getting recipients")]
+pub async fn get_recipients(
+    executor: impl PgExecutor<'_>,
+    emails: Vec<Email>,
+) -> Result<Vec<Recipient>, sqlx::Error> {
+    let addresses: Vec<String> = emails.iter().map(|e| e.as_ref().to_string()).collect();
+
+    let mut subscribers: HashMap<_, _> = sqlx::query!(
+        r#"
+        SELECT DISTINCT ON (email)
+            email, name, attributes AS "attributes: Json<BTreeMap<String, String>>"
+        FROM subscriptions
+        WHERE email = ANY($1)
+        ORDER BY email, status = 'confirmed' DESC, subscribed_at
+        "#,
+        &addresses,
+    )
+    .fetch_all(executor)
+    .await
+    .inspect_err(|e| tracing::error!("Failed to execute query: {e:?}"))?
+    .into_iter()
+    .map(|subscriber| (subscriber.email, (subscriber.name, subscriber.attributes.0)))
+    .collect();
+
+    Ok(emails
+        .into_iter()
+        .map(|email| match subscribers.remove(email.as_ref()) {
+            Some((name, attributes)) => Recipient {
+                email,
+                name,
+                attributes,
+            },
+            None => Recipient::unknown(email),
+        })
+        .collect())
+}
+
+/// The confirmed subscribers among `emails`, by address.
+#[instrument(skip_all, name = "Getting confirmed recipients")]
+async fn get_confirmed_recipients(
+    executor: impl PgExecutor<'_>,
+    emails: &[Email],
+) -> Result<HashMap<String, Recipient>, sqlx::Error> {
+    let addresses: Vec<&str> = emails.iter().map(AsRef::as_ref).collect();
+
+    let mut subscribers: HashMap<_, _> = sqlx::query!(
+        r#"
+        SELECT DISTINCT ON (email)
+            email, name, attributes AS "attributes: Json<BTreeMap<String, String>>"
+        FROM subscriptions
+        WHERE email = ANY($1) AND status = 'confirmed'
+        ORDER BY email, subscribed_at
+        "#,
+        &addresses as &[&str],
+    )
+    .fetch_all(executor)
+    .await
+    .inspect_err(|e| tracing::error!("Failed to execute query: {e:?}"))?
+    .into_iter()
+    .map(|subscriber| (subscriber.email, (subscriber.name, subscriber.attributes.0)))
+    .collect();
+
+    Ok(emails
+        .iter()
+        .filter_map(|email| {
+            let (name, attributes) = subscribers.remove(email.as_ref())?;
+
+            Some((
+                email.as_ref().to_string(),
+                Recipient {
+                    email: email.clone(),
+                    name,
+                    attributes,
+                },
+            ))
+        })
+        .collect())
+}
+
+struct Task {
+    newsletter_issue_id: Uuid,
+    subscriber_email: String,
+    n_attempts: i32,
+}
+
+/// Locks the oldest due task along with other due tasks of the same issue, so
+/// that they can go out together.
+#[instrument(skip_all, name = "Dequeuing delivery tasks")]
+async fn dequeue_tasks(
+    transaction: &mut Transaction<'_, Postgres>,
+    batch_size: usize,
+) -> Result<Vec<Task>, sqlx::Error> {
+    sqlx::query_as!(
+        Task,
+        r#"
+        SELECT newsletter_issue_id, subscriber_email, n_attempts
+        FROM issue_delivery_queue
+        WHERE status = 'pending' AND execute_after <= now() AND newsletter_issue_id = (
+            SELECT newsletter_issue_id
+            FROM issue_delivery_queue
+            WHERE status = 'pending' AND execute_after <= now()
+            ORDER BY execute_after
+            FOR UPDATE SKIP LOCKED
+            LIMIT 1
+        )
+        ORDER BY execute_after
+        FOR UPDATE SKIP LOCKED
+        LIMIT $1
+        "#,
+        batch_size.max(1) as i64,
+    )
+    .fetch_all(&mut **transaction)
+    .await
+    .inspect_err(|e| tracing::error!("Failed to execute query: {e:?}"))
+}
+
+#[instrument(skip_all, name = "Marking delivery as delivered")]
+async fn mark_task_delivered(
+    transaction: &mut Transaction<'_, Postgres>,
+    task: &Task,
+) -> Result<(), sqlx::Error> {
+    sqlx::query!(
+        r#"
+        UPDATE issue_delivery_queue
+        SET status = 'delivered', n_attempts = n_attempts + 1, last_error = NULL
+        WHERE newsletter_issue_id = $1 AND subscriber_email = $2
+        "#,
+        task.newsletter_issue_id,
+        task.subscriber_email,
+    )
+    .execute(&mut **transaction)
+    .await
+    .inspect_err(|e| tracing::error!("Failed to execute query: {e:?}"))?;
+
+    Ok(())
+}
+
+/// Not an attempt, nothing was sent.
+#[instrument(skip_all, name = "Skipping delivery")]
+async fn mark_task_skipped(
+    transaction: &mut Transaction<'_, Postgres>,
+    task: &Task,
+) -> Result<(), sqlx::Error> {
+    sqlx::query!(
+        r#"
+        UPDATE issue_delivery_queue
+        SET status = 'skipped', last_error = 'The subscriber is no longer confirmed.'
+        WHERE newsletter_issue_id = $1 AND subscriber_email = $2
+        "#,
+        task.newsletter_issue_id,
+        task.subscriber_email,
+    )
+    .execute(&mut **transaction)
+    .await
+    .inspect_err(|e| tracing::error!("Failed to execute query: {e:?}"))?;
+
+    Ok(())
+}
+
+#[instrument(skip_all, name = "Rescheduling delivery")]
+async fn reschedule_task(
+    transaction: &mut Transaction<'_, Postgres>,
+    task: &Task,
+    delay: Duration,
+    error: &str,
+) -> Result<(), sqlx::Error> {
+    let execute_after = Utc::now() + TimeDelta::from_std(delay).unwrap_or(TimeDelta::MAX);
+
+    sqlx::query!(
+        r#"
+        UPDATE issue_delivery_queue
+        SET n_attempts = n_attempts + 1, execute_after = $3, last_error = $4
+        WHERE newsletter_issue_id = $1 AND subscriber_email = $2
+        "#,
+        task.newsletter_issue_id,
+        task.subscriber_email,
+        execute_after,
+        error,
+    )
+    .execute(&mut **transaction)
+    .await
+    .inspect_err(|e| tracing::error!("Failed to execute query: {e:?}"))?;
+
+    Ok(())
+}
+
+#[instrument(skip_all, name = "Postponing delivery")]
+async fn postpone_task(
+    transaction: &mut Transaction<'_, Postgres>,
+    task: &Task,
+    execute_after: DateTime<Utc>,
+    error: &str,
+) -> Result<(), sqlx::Error> {
+    sqlx::query!(
+        r#"
+        UPDATE issue_delivery_queue
+        SET execute_after = $3, last_error = $4
+        WHERE newsletter_issue_id = $1 AND subscriber_email = $2
+        "#,
+        task.newsletter_issue_id,
+        task.subscriber_email,
+        execute_after,
+        error,
+    )
+    .execute(&mut **transaction)
+    .await
+    .inspect_err(|e| tracing::error!("Failed to execute query: {e:?}"))?;
+
+    Ok(())
+}
+
+#[instrument(skip_all, name = "Dead-lettering delivery")]
+async fn mark_task_dead(
+    transaction: &mut Transaction<'_, Postgres>,
+    task: &Task,
+    error: &str,
+) -> Result<(), sqlx::Error> {
+    sqlx::query!(
+        r#"
+        UPDATE issue_delivery_queue
+        SET status = 'dead', n_attempts = n_attempts + 1, last_error = $3
+        WHERE newsletter_issue_id = $1 AND subscriber_email = $2
+        "#,
+        task.newsletter_issue_id,
+        task.subscriber_email,
+        error,
+    )
+    .execute(&mut **transaction)
+    .await
+    .inspect_err(|e| tracing::error!("Failed to execute query: {e:?}"))?;
+
+    Ok(())
+}
+
+struct NewsletterIssue {
+    title: String,
+    text_content: String,
+    html_content: String,
+}
+
+#[instrument(skip_all, name = "Getting newsletter issue")]
+async fn get_issue(
+    transaction: &mut Transaction<'_, Postgres>,
+    newsletter_issue_id: Uuid,
+) -> Result<NewsletterIssue, sqlx::Error> {
+    sqlx::query_as!(
+        NewsletterIssue,
+        r#"
+        SELECT title, text_content, html_content
+        FROM newsletter_issues
+        WHERE newsletter_issue_id = $1
+        "#,
+        newsletter_issue_id,
+    )
+    .fetch_one(&mut **transaction)
+    .await
+    .inspect_err(|e| tracing::error!("Failed to execute query: {e:?}"))
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
+
+    use super::backoff;
+    use crate::config::DeliveryConfig;
+
+    #[test]
+    fn backoff_doubles_after_every_failure() {
+        let config = DeliveryConfig {
+            base_backoff_ms: 1_000,
+            ..Default::default()
+        };
+
+        assert_eq!(backoff(&config, 1), Duration::from_secs(1));
+        assert_eq!(backoff(&config, 2), Duration::from_secs(2));
+        assert_eq!(backoff(&config, 4), Duration::from_secs(8));
+    }
+
+    #[test]
+    fn backoff_is_capped() {
+        let config = DeliveryConfig {
+            base_backoff_ms: 1_000,
+            max_backoff_ms: 5_000,
+            ..Default::default()
+        };
+
+        assert_eq!(backoff(&config, 10), Duration::from_secs(5));
+        assert_eq!(backoff(&config, 200), Duration::from_secs(5));
+    }
+}
@@ -0,0 +1,73 @@
+use std::time::Duration;
+
+use sqlx::{PgPool, Postgres, Transaction};
+use tracing::instrument;
+use uuid::Uuid;
+
+use crate::issue_delivery_worker::enqueue_delivery_tasks;
+
+/// Moves scheduled newsletter issues into `issue_delivery_queue` once their
+/// `scheduled_at` has passed.
+///
+/// Due issues are claimed with `FOR UPDATE SKIP LOCKED`, so schedulers in
+/// other replicas never publish the same issue twice, and a cancellation
+/// racing with the scheduler either wins or sees the issue as published.
+#[derive(Clone)]
+pub struct IssueScheduler {
+    pool: PgPool,
+    poll_interval: Duration,
+}
+
+impl IssueScheduler {
+    pub fn new(pool: PgPool, poll_interval: Duration) -> Self {
+        Self {
+            pool,
+            poll_interval,
+        }
+    }
+
+    pub async fn run_until_stopped(self) {
+        loop {
+            let _ = self.publish_due_issues().await;
+            tokio::time::sleep(self.poll_interval).await;
+        }
+    }
+
+    /// Publishes every issue that is due and returns their ids.
+    #[instrument(skip_all, name = "Publishing due newsletter issues", err)]
+    pub async fn publish_due_issues(&self) -> Result<Vec<Uuid>, sqlx::Error> {
+        let mut transaction = self.pool.begin().await?;
+
+        let issue_ids = mark_due_issues_published(&mut transaction).await?;
+        for issue_id in &issue_ids {
+            let queued = enqueue_delivery_tasks(&mut transaction, *issue_id).await?;
+            tracing::info!(%issue_id, queued, "Published a scheduled issue");
+        }
+
+        transaction.commit().await?;
+
+        Ok(issue_ids)
+    }
+}
+
+#[instrument(skip_all, name = "Marking due issues as published")]
+async fn mark_due_issues_published(
+    transaction: &mut Transaction<'_, Postgres>,
+) -> Result<Vec<Uuid>, sqlx::Error> {
+    sqlx::query_scalar!(
+        r#"
+        UPDATE newsletter_issues
+        SET status = 'published', published_at = now()
+        WHERE newsletter_issue_id IN (
+            SELECT newsletter_issue_id
+            FROM newsletter_issues
+            WHERE status = 'scheduled' AND scheduled_at <= now()
+            FOR UPDATE SKIP LOCKED
+        )
+        RETURNING newsletter_issue_id
+        "#,
+    )
+    .fetch_all(&mut **transaction)
+    .await
+    .inspect_err(|e| tracing::error!("Failed to execute query: {e:?}"))
+}
@@ -1,9 +1,19 @@
 mod app;
 
+pub mod authentication;
 pub mod config;
 pub mod domain;
 pub mod email_client;
+pub mod error;
+pub mod extract;
+pub mod idempotency;
+pub mod issue_delivery_worker;
+pub mod issue_scheduler;
+pub mod markdown;
 pub mod routes;
+pub mod state;
 pub mod telemetry;
+pub mod template;
+pub mod unsubscribe;
 
 pub use app::App;
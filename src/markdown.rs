@@ -0,0 +1,399 @@
+use std::sync::LazyLock;
+
+use pulldown_cmark::{Event, HeadingLevel, Options, Parser, Tag, TagEnd};
+
+/// Inline styles added to the rendered HTML, since many email clients ignore
+/// `<style>` blocks.
+const STYLES: &[(&str, &str)] = &[
+    ("p", "margin: 0 0 16px; line-height: 1.5;"),
+    (
+        "h1",
+        "margin: 24px 0 16px; font-size: 24px; line-height: 1.25;",
+    ),
+    (
+        "h2",
+        "margin: 24px 0 16px; font-size: 20px; line-height: 1.25;",
+    ),
+    (
+        "h3",
+        "margin: 24px 0 16px; font-size: 16px; line-height: 1.25;",
+    ),
+    ("a", "color: #1a73e8;"),
+    ("ul", "margin: 0 0 16px; padding-left: 24px;"),
+    ("ol", "margin: 0 0 16px; padding-left: 24px;"),
+    (
+        "blockquote",
+        "margin: 0 0 16px; padding-left: 12px; border-left: 3px solid #ddd; color: #555;",
+    ),
+    (
+        "code",
+        "font-family: monospace; background: #f4f4f4; padding: 1px 4px;",
+    ),
+    (
+        "pre",
+        "margin: 0 0 16px; padding: 12px; background: #f4f4f4; overflow-x: auto;",
+    ),
+    ("img", "max-width: 100%;"),
+    (
+        "hr",
+        "border: 0; border-top: 1px solid #ddd; margin: 24px 0;",
+    ),
+];
+
+static SANITIZER: LazyLock<ammonia::Builder<'static>> = LazyLock::new(|| {
+    let mut builder = ammonia::Builder::default();
+    for (tag, style) in STYLES {
+        builder.set_tag_attribute_value(*tag, "style", *style);
+    }
+    builder
+});
+
+/// The two bodies of an email written in Markdown.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct RenderedMarkdown {
+    pub text_content: String,
+    pub html_content: String,
+}
+
+/// Renders the HTML and plain-text versions of an email from the same
+/// Markdown source.
+pub fn render(markdown: &str) -> RenderedMarkdown {
+    RenderedMarkdown {
+        text_content: to_text(markdown),
+        html_content: to_html(markdown),
+    }
+}
+
+fn options() -> Options {
+    Options::ENABLE_STRIKETHROUGH
+}
+
+/// Sanitized HTML with inline styles. Raw HTML in the source goes through the
+/// same sanitizer, so scripts, event handlers and the like are dropped.
+pub fn to_html(markdown: &str) -> String {
+    let mut html = String::new();
+    pulldown_cmark::html::push_html(&mut html, Parser::new_ext(markdown, options()));
+
+    SANITIZER.clean(&html).to_string()
+}
+
+/// Plain text meant to be read as is: headings are underlined, lists keep
+/// their markers, and links become numbered references listed at the end.
+pub fn to_text(markdown: &str) -> String {
+    let mut writer = TextWriter::default();
+    for event in Parser::new_ext(markdown, options()) {
+        writer.event(event);
+    }
+
+    writer.finish()
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+enum Separator {
+    Line,
+    Blank,
+}
+
+#[derive(Default)]
+struct TextWriter {
+    out: String,
+    /// Written at the start of every line, for quotes and list items.
+    prefixes: Vec<&'static str>,
+    /// The separator to write before the next text, and how many prefixes
+    /// were open when it was asked for.
+    pending: Option<(Separator, usize)>,
+    /// Set right after a list marker, so that the item's first block stays
+    /// on the marker's line.
+    after_marker: bool,
+    /// The next number of each open list, `None` for bullet lists.
+    lists: Vec<Option<u64>>,
+    /// Open links and images, with where their text starts.
+    links: Vec<(String, usize)>,
+    references: Vec<String>,
+    heading_start: Option<usize>,
+    code_block: Option<String>,
+}
+
+impl TextWriter {
+    fn event(&mut self, event: Event<'_>) {
+        match event {
+            Event::Start(tag) => self.start(tag),
+            Event::End(tag) => self.end(tag),
+            Event::Text(text) => match &mut self.code_block {
+                Some(code) => code.push_str(&text),
+                None => self.write(&text),
+            },
+            Event::Code(code) => self.write(&code),
+            Event::SoftBreak | Event::HardBreak => self.separate(Separator::Line),
+            Event::Rule => {
+                self.separate(Separator::Blank);
+                self.write("----------");
+            }
+            Event::TaskListMarker(checked) => self.write(if checked { "[x] " } else { "[ ] " }),
+            _ => {}
+        }
+    }
+
+    fn start(&mut self, tag: Tag<'_>) {
+        match tag {
+            Tag::Paragraph => self.separate(Separator::Blank),
+            Tag::Heading { .. } => {
+                self.separate(Separator::Blank);
+                self.flush();
+                self.heading_start = Some(self.out.len());
+            }
+            Tag::BlockQuote(_) => {
+                self.separate(Separator::Blank);
+                self.prefixes.push("> ");
+            }
+            Tag::CodeBlock(_) => {
+                self.separate(Separator::Blank);
+                self.prefixes.push("    ");
+                self.code_block = Some(String::new());
+            }
+            Tag::List(start) => {
+                // Nested lists follow their parent item on the next line.
+                let separator = match self.lists.is_empty() {
+                    true => Separator::Blank,
+                    false => Separator::Line,
+                };
+                self.after_marker = false;
+                self.separate(separator);
+                self.lists.push(start);
+            }
+            Tag::Item => {
+                self.separate(Separator::Line);
+                let marker = match self.lists.last_mut() {
+                    Some(Some(n)) => {
+                        let marker = format!("{n}. ");
+                        *n += 1;
+                        marker
+                    }
+                    _ => "- ".to_string(),
+                };
+                self.write(&marker);
+                // Following lines line up with the item's first one.
+                self.prefixes.push(match marker.len() {
+                    2 => "  ",
+                    3 => "   ",
+                    _ => "    ",
+                });
+                self.after_marker = true;
+            }
+            Tag::Link { dest_url, .. } | Tag::Image { dest_url, .. } => {
+                self.flush();
+                self.links.push((dest_url.to_string(), self.out.len()));
+            }
+            _ => {}
+        }
+    }
+
+    fn end(&mut self, tag: TagEnd) {
+        match tag {
+            TagEnd::Heading(level) => {
+                let Some(start) = self.heading_start.take() else {
+                    return;
+                };
+                let width = self.out[start..].chars().count();
+                let underline = match level {
+                    HeadingLevel::H1 => "=",
+                    _ => "-",
+                };
+                self.separate(Separator::Line);
+                self.write(&underline.repeat(width));
+            }
+            TagEnd::BlockQuote(_) => {
+                self.pop_prefix();
+                self.separate(Separator::Blank);
+            }
+            TagEnd::CodeBlock => {
+                if let Some(code) = self.code_block.take() {
+                    self.write(code.trim_end_matches('\n'));
+                }
+                self.pop_prefix();
+            }
+            TagEnd::List(_) => {
+                self.lists.pop();
+                self.separate(Separator::Blank);
+            }
+            TagEnd::Item => {
+                self.pop_prefix();
+                self.after_marker = false;
+            }
+            TagEnd::Link | TagEnd::Image => {
+                let Some((url, start)) = self.links.pop() else {
+                    return;
+                };
+                // Autolinks already show their address.
+                let text = &self.out[start..];
+                if text == url || format!("mailto:{text}") == url {
+                    return;
+                }
+                let n = match self.references.iter().position(|r| *r == url) {
+                    Some(i) => i + 1,
+                    None => {
+                        self.references.push(url);
+                        self.references.len()
+                    }
+                };
+                self.write(&format!(" [{n}]"));
+            }
+            _ => {}
+        }
+    }
+
+    /// Asks for a line break or a blank line before whatever comes next.
+    fn separate(&mut self, separator: Separator) {
+        if self.after_marker {
+            return;
+        }
+        self.pending = match self.pending {
+            Some((pending, depth)) => {
+                Some((pending.max(separator), depth.min(self.prefixes.len())))
+            }
+            None => Some((separator, self.prefixes.len())),
+        };
+    }
+
+    fn pop_prefix(&mut self) {
+        self.prefixes.pop();
+        if let Some((_, depth)) = &mut self.pending {
+            *depth = (*depth).min(self.prefixes.len());
+        }
+    }
+
+    fn flush(&mut self) {
+        let Some((separator, depth)) = self.pending.take() else {
+            return;
+        };
+
+        let prefix = self.prefixes.concat();
+        if self.out.is_empty() {
+            self.out.push_str(&prefix);
+            return;
+        }
+
+        if separator == Separator::Blank {
+            self.out.push('\n');
+            self.out
+                .push_str(self.prefixes[..depth].concat().trim_end());
+        }
+        self.out.push('\n');
+        self.out.push_str(&prefix);
+    }
+
+    fn write(&mut self, text: &str) {
+        self.flush();
+        self.after_marker = false;
+
+        let prefix = self.prefixes.concat();
+        for (i, line) in text.split('\n').enumerate() {
+            if i > 0 {
+                self.out.push('\n');
+                self.out.push_str(&prefix);
+            }
+            self.out.push_str(line);
+        }
+    }
+
+    fn finish(mut self) -> String {
+        let mut out = self
+            .out
+            .lines()
+            .map(str::trim_end)
+            .collect::<Vec<_>>()
+            .join("\n");
+
+        if !self.references.is_empty() {
+            out.push_str("\n\n");
+            for (i, url) in self.references.drain(..).enumerate() {
+                out.push_str(&format!("[{}] {url}\n", i + 1));
+            }
+            out.truncate(out.trim_end().len());
+        }
+
+        out
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{to_html, to_text};
+
+    #[test]
+    fn links_become_numbered_references() {
+        let text = to_text(
+            "Read [the post](https://lzzzt.cc/post) and [the docs](https://lzzzt.cc/docs), \
+             then [the post](https://lzzzt.cc/post) again.",
+        );
+
+        assert_eq!(
+            text,
+            "Read the post [1] and the docs [2], then the post [1] again.\n\
+             \n\
+             [1] https://lzzzt.cc/post\n\
+             [2] https://lzzzt.cc/docs"
+        );
+    }
+
+    #[test]
+    fn autolinks_are_not_referenced_twice() {
+        assert_eq!(
+            to_text("Visit <https://lzzzt.cc>."),
+            "Visit https://lzzzt.cc."
+        );
+    }
+
+    #[test]
+    fn blocks_are_laid_out_as_plain_text() {
+        let text = to_text(
+            "# Title\n\
+             \n\
+             Some *emphasis*.\n\
+             \n\
+             - one\n\
+             - two\n  \
+               1. nested\n\
+             \n\
+             > quoted\n\
+             > lines\n\
+             \n\
+             ```\n\
+             let x = 1;\n\
+             ```",
+        );
+
+        assert_eq!(
+            text,
+            "Title\n\
+             =====\n\
+             \n\
+             Some emphasis.\n\
+             \n\
+             - one\n\
+             - two\n  \
+               1. nested\n\
+             \n\
+             > quoted\n\
+             > lines\n\
+             \n    \
+             let x = 1;"
+        );
+    }
+
+    #[test]
+    fn html_is_sanitized() {
+        let html = to_html("Hi <script>alert(1)</script><a href=\"x\" onclick=\"y\">there</a>");
+
+        assert!(!html.contains("<script"));
+        assert!(!html.contains("onclick"));
+    }
+
+    #[test]
+    fn html_styles_are_inlined() {
+        let html = to_html("Some [link](https://lzzzt.cc).");
+
+        assert!(html.starts_with("<p style=\"margin: 0 0 16px;"));
+        assert!(html.contains("<a href=\"https://lzzzt.cc\" style=\"color: #1a73e8;\""));
+    }
+}
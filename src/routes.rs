@@ -1,5 +1,19 @@
+mod admin_drafts;
+mod admin_newsletters;
+mod archive;
+mod dev_mailbox;
 mod health_check;
+mod metrics;
 mod subscriptions;
+mod subscriptions_confirm;
+mod subscriptions_unsubscribe;
 
+pub use admin_drafts::*;
+pub use admin_newsletters::*;
+pub use archive::*;
+pub use dev_mailbox::*;
 pub use health_check::*;
+pub use metrics::*;
 pub use subscriptions::*;
+pub use subscriptions_confirm::*;
+pub use subscriptions_unsubscribe::*;
@@ -0,0 +1,309 @@
+use std::sync::Arc;
+
+use axum::extract::rejection::{PathRejection, QueryRejection};
+use axum::extract::{Path, Query, State};
+use axum::http::StatusCode;
+use axum::{Extension, Json};
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use sqlx::PgPool;
+use tracing::instrument;
+use uuid::Uuid;
+
+use super::admin_newsletters::{ContentData, validate_content};
+use crate::authentication::AdminUser;
+use crate::domain::Email;
+use crate::email_client::{EmailClient, Headers, Message};
+use crate::error::{AppError, FieldError};
+use crate::extract::FormOrJson;
+use crate::issue_delivery_worker::get_recipient;
+use crate::state::TestRecipients;
+use crate::template::NewsletterTemplate;
+
+#[derive(Deserialize)]
+pub struct DraftData {
+    pub title: String,
+    #[serde(flatten)]
+    pub content: ContentData,
+}
+
+#[derive(Debug, Serialize)]
+pub struct Draft {
+    pub draft_id: Uuid,
+    pub title: String,
+    /// Set when the draft is written in Markdown, which the other two
+    /// versions are rendered from.
+    pub markdown_content: Option<String>,
+    pub text_content: String,
+    pub html_content: String,
+    pub created_at: DateTime<Utc>,
+    pub updated_at: DateTime<Utc>,
+}
+
+const DRAFT_NOT_FOUND: &str = "The newsletter draft does not exist.";
+
+#[instrument(skip_all, name = "Creating a newsletter draft", fields(user_id = %admin.user_id))]
+pub async fn create_draft(
+    State(pool): State<PgPool>,
+    Extension(admin): Extension<AdminUser>,
+    FormOrJson { data, .. }: FormOrJson<DraftData>,
+) -> Result<(StatusCode, Json<Draft>), AppError> {
+    let content = validate_content(&data.title, data.content)?;
+
+    let now = Utc::now();
+    let draft = sqlx::query_as!(
+        Draft,
+        r#"
+        INSERT INTO newsletter_drafts (
+            draft_id, title, markdown_content, text_content, html_content,
+            created_by, created_at, updated_at
+        )
+        VALUES ($1, $2, $3, $4, $5, $6, $7, $7)
+        RETURNING draft_id, title, markdown_content, text_content, html_content, created_at, updated_at
+        "#,
+        Uuid::new_v4(),
+        data.title,
+        content.markdown_content,
+        content.text_content,
+        content.html_content,
+        admin.user_id,
+        now,
+    )
+    .fetch_one(&pool)
+    .await
+    .inspect_err(|e| tracing::error!("Failed to execute query: {e:?}"))?;
+
+    Ok((StatusCode::CREATED, Json(draft)))
+}
+
+/// Every draft, most recently edited first.
+#[instrument(skip_all, name = "Listing newsletter drafts")]
+pub async fn list_drafts(State(pool): State<PgPool>) -> Result<Json<Vec<Draft>>, AppError> {
+    let drafts = sqlx::query_as!(
+        Draft,
+        r#"
+        SELECT draft_id, title, markdown_content, text_content, html_content, created_at, updated_at
+        FROM newsletter_drafts
+        ORDER BY updated_at DESC
+        "#,
+    )
+    .fetch_all(&pool)
+    .await
+    .inspect_err(|e| tracing::error!("Failed to execute query: {e:?}"))?;
+
+    Ok(Json(drafts))
+}
+
+#[instrument(skip_all, name = "Getting a newsletter draft")]
+pub async fn get_draft(
+    State(pool): State<PgPool>,
+    draft_id: Result<Path<Uuid>, PathRejection>,
+) -> Result<Json<Draft>, AppError> {
+    let Path(draft_id) = draft_id?;
+
+    let draft = fetch_draft(&pool, draft_id)
+        .await?
+        .ok_or(AppError::NotFound(DRAFT_NOT_FOUND))?;
+
+    Ok(Json(draft))
+}
+
+#[instrument(skip_all, name = "Updating a newsletter draft")]
+pub async fn update_draft(
+    State(pool): State<PgPool>,
+    draft_id: Result<Path<Uuid>, PathRejection>,
+    FormOrJson { data, .. }: FormOrJson<DraftData>,
+) -> Result<Json<Draft>, AppError> {
+    let Path(draft_id) = draft_id?;
+    let content = validate_content(&data.title, data.content)?;
+
+    let draft = sqlx::query_as!(
+        Draft,
+        r#"
+        UPDATE newsletter_drafts
+        SET
+            title = $2,
+            markdown_content = $3,
+            text_content = $4,
+            html_content = $5,
+            updated_at = $6
+        WHERE draft_id = $1
+        RETURNING draft_id, title, markdown_content, text_content, html_content, created_at, updated_at
+        "#,
+        draft_id,
+        data.title,
+        content.markdown_content,
+        content.text_content,
+        content.html_content,
+        Utc::now(),
+    )
+    .fetch_optional(&pool)
+    .await
+    .inspect_err(|e| tracing::error!("Failed to execute query: {e:?}"))?
+    .ok_or(AppError::NotFound(DRAFT_NOT_FOUND))?;
+
+    Ok(Json(draft))
+}
+
+#[instrument(skip_all, name = "Deleting a newsletter draft")]
+pub async fn delete_draft(
+    State(pool): State<PgPool>,
+    draft_id: Result<Path<Uuid>, PathRejection>,
+) -> Result<StatusCode, AppError> {
+    let Path(draft_id) = draft_id?;
+
+    let result = sqlx::query!(
+        r#"DELETE FROM newsletter_drafts WHERE draft_id = $1"#,
+        draft_id,
+    )
+    .execute(&pool)
+    .await
+    .inspect_err(|e| tracing::error!("Failed to execute query: {e:?}"))?;
+
+    if result.rows_affected() == 0 {
+        return Err(AppError::NotFound(DRAFT_NOT_FOUND));
+    }
+
+    Ok(StatusCode::NO_CONTENT)
+}
+
+#[derive(Deserialize)]
+pub struct PreviewParameters {
+    /// The recipient to render for, the sender address by default.
+    pub to: Option<String>,
+}
+
+/// A draft as a recipient would get it.
+#[derive(Debug, Serialize)]
+pub struct Preview {
+    pub from: String,
+    pub to: String,
+    pub subject: String,
+    pub text_content: String,
+    pub html_content: String,
+    pub headers: Headers,
+}
+
+impl From<Message> for Preview {
+    fn from(value: Message) -> Self {
+        let to: Vec<&str> = value.to.iter().map(AsRef::as_ref).collect();
+
+        Self {
+            from: value.from.as_ref().into(),
+            to: to.join(", "),
+            subject: value.subject,
+            text_content: value.raw_content,
+            html_content: value.html_content,
+            headers: value.headers,
+        }
+    }
+}
+
+/// Renders the draft through [`EmailClient::render_newsletter`], the same way
+/// delivery workers render issues, filled in for `to` if they subscribed.
+#[instrument(skip_all, name = "Previewing a newsletter draft")]
+pub async fn preview_draft(
+    State(pool): State<PgPool>,
+    State(email_client): State<Arc<EmailClient>>,
+    draft_id: Result<Path<Uuid>, PathRejection>,
+    parameters: Result<Query<PreviewParameters>, QueryRejection>,
+) -> Result<Json<Preview>, AppError> {
+    let Path(draft_id) = draft_id?;
+    let Query(parameters) = parameters?;
+
+    let to = match parameters.to {
+        Some(to) => parse_recipient(to)?,
+        None => email_client.sender().clone(),
+    };
+
+    let draft = fetch_draft(&pool, draft_id)
+        .await?
+        .ok_or(AppError::NotFound(DRAFT_NOT_FOUND))?;
+
+    let newsletter = draft_template(&draft)?;
+    let recipient = get_recipient(&pool, to).await?;
+    let email = email_client.render_newsletter(&newsletter, recipient);
+
+    Ok(Json(email.into()))
+}
+
+#[derive(Deserialize)]
+pub struct TestRecipient {
+    pub to: String,
+}
+
+/// Sends the draft to a single address, right away and without touching the
+/// delivery queue. Only the sender and the configured test recipients can be
+/// sent to.
+#[instrument(skip_all, name = "Sending a test of a newsletter draft", fields(to = %data.to))]
+pub async fn send_test_draft(
+    State(pool): State<PgPool>,
+    State(email_client): State<Arc<EmailClient>>,
+    State(test_recipients): State<TestRecipients>,
+    draft_id: Result<Path<Uuid>, PathRejection>,
+    FormOrJson { data, .. }: FormOrJson<TestRecipient>,
+) -> Result<StatusCode, AppError> {
+    let Path(draft_id) = draft_id?;
+    let to = parse_recipient(data.to)?;
+
+    if !test_recipients.allows(&to) {
+        return Err(AppError::Validation(vec![FieldError {
+            field: "to".into(),
+            code: "not_a_test_recipient",
+            message:
+                "Test emails can only be sent to the sender or the configured test recipients."
+                    .into(),
+        }]));
+    }
+
+    let draft = fetch_draft(&pool, draft_id)
+        .await?
+        .ok_or(AppError::NotFound(DRAFT_NOT_FOUND))?;
+
+    let newsletter = draft_template(&draft)?;
+    let recipient = get_recipient(&pool, to).await?;
+    email_client
+        .send_newsletter(&newsletter, recipient)
+        .await
+        .inspect_err(|e| tracing::error!("Failed to send test email: {e:?}"))?;
+
+    Ok(StatusCode::NO_CONTENT)
+}
+
+/// Only drafts saved before `validate_content` existed can fail here.
+fn draft_template(draft: &Draft) -> Result<NewsletterTemplate, AppError> {
+    NewsletterTemplate::parse(&draft.title, &draft.text_content, &draft.html_content).map_err(|e| {
+        AppError::BadRequest {
+            status: StatusCode::UNPROCESSABLE_ENTITY,
+            code: "invalid_template",
+            detail: e.to_string(),
+            errors: vec![],
+        }
+    })
+}
+
+fn parse_recipient(to: String) -> Result<Email, AppError> {
+    Email::try_from(to).map_err(|e| {
+        AppError::Validation(vec![FieldError {
+            field: "to".into(),
+            code: e.code(),
+            message: e.to_string(),
+        }])
+    })
+}
+
+#[instrument(skip_all, name = "Fetching a newsletter draft")]
+async fn fetch_draft(pool: &PgPool, draft_id: Uuid) -> Result<Option<Draft>, sqlx::Error> {
+    sqlx::query_as!(
+        Draft,
+        r#"
+        SELECT draft_id, title, markdown_content, text_content, html_content, created_at, updated_at
+        FROM newsletter_drafts
+        WHERE draft_id = $1
+        "#,
+        draft_id,
+    )
+    .fetch_optional(pool)
+    .await
+    .inspect_err(|e| tracing::error!("Failed to execute query: {e:?}"))
+}
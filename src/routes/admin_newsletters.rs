@@ -0,0 +1,479 @@
+use axum::extract::rejection::PathRejection;
+use axum::extract::{Path, State};
+use axum::http::StatusCode;
+use axum::{Extension, Json};
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use sqlx::{Connection, PgPool, Postgres, Transaction};
+use tracing::instrument;
+use uuid::Uuid;
+
+use crate::authentication::AdminUser;
+use crate::domain::IssueSlug;
+use crate::error::{AppError, FieldError};
+use crate::extract::FormOrJson;
+use crate::issue_delivery_worker::enqueue_delivery_tasks;
+use crate::markdown;
+use crate::template::Template;
+
+#[derive(Deserialize)]
+pub struct NewsletterData {
+    pub title: String,
+    #[serde(flatten)]
+    pub content: ContentData,
+    /// Publishes the issue at that time instead of right away.
+    #[serde(default)]
+    pub scheduled_at: Option<DateTime<Utc>>,
+}
+
+/// Returned once an issue has been queued, or scheduled to be; the delivery
+/// workers take it from there.
+#[derive(Debug, Serialize)]
+pub struct PublishSummary {
+    pub issue_id: Uuid,
+    pub status: &'static str,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub scheduled_at: Option<DateTime<Utc>>,
+    /// Confirmed subscribers the issue was queued for.
+    pub queued: i64,
+}
+
+#[instrument(
+    skip_all,
+    name = "Publishing a newsletter issue",
+    fields(user_id = %admin.user_id, issue_id = tracing::field::Empty)
+)]
+pub async fn publish_newsletter(
+    State(pool): State<PgPool>,
+    Extension(admin): Extension<AdminUser>,
+    FormOrJson { data, .. }: FormOrJson<NewsletterData>,
+) -> Result<(StatusCode, Json<PublishSummary>), AppError> {
+    let content = validate_content(&data.title, data.content)?;
+
+    // A time in the past means "now", so that a late request is not lost.
+    let scheduled_at = data.scheduled_at.filter(|at| *at > Utc::now());
+
+    let mut transaction = pool.begin().await?;
+
+    let issue_id = insert_newsletter_issue(
+        &mut transaction,
+        &data.title,
+        &content,
+        admin.user_id,
+        scheduled_at,
+    )
+    .await?;
+    tracing::Span::current().record("issue_id", tracing::field::display(issue_id));
+
+    let summary = match scheduled_at {
+        Some(scheduled_at) => PublishSummary {
+            issue_id,
+            status: "scheduled",
+            scheduled_at: Some(scheduled_at),
+            queued: 0,
+        },
+        None => PublishSummary {
+            issue_id,
+            status: "published",
+            scheduled_at: None,
+            queued: enqueue_delivery_tasks(&mut transaction, issue_id).await?,
+        },
+    };
+
+    transaction.commit().await?;
+
+    Ok((StatusCode::ACCEPTED, Json(summary)))
+}
+
+#[derive(Deserialize)]
+pub struct ScheduleData {
+    pub scheduled_at: DateTime<Utc>,
+}
+
+#[derive(Debug, Serialize)]
+pub struct IssueSchedule {
+    pub issue_id: Uuid,
+    pub status: String,
+    pub scheduled_at: Option<DateTime<Utc>>,
+}
+
+#[instrument(skip_all, name = "Rescheduling a newsletter issue")]
+pub async fn reschedule_newsletter_issue(
+    State(pool): State<PgPool>,
+    issue_id: Result<Path<Uuid>, PathRejection>,
+    FormOrJson { data, .. }: FormOrJson<ScheduleData>,
+) -> Result<Json<IssueSchedule>, AppError> {
+    let Path(issue_id) = issue_id?;
+
+    let schedule = sqlx::query_as!(
+        IssueSchedule,
+        r#"
+        UPDATE newsletter_issues
+        SET scheduled_at = $2
+        WHERE newsletter_issue_id = $1 AND status = 'scheduled'
+        RETURNING newsletter_issue_id AS issue_id, status, scheduled_at
+        "#,
+        issue_id,
+        data.scheduled_at,
+    )
+    .fetch_optional(&pool)
+    .await
+    .inspect_err(|e| tracing::error!("Failed to execute query: {e:?}"))?;
+
+    match schedule {
+        Some(schedule) => Ok(Json(schedule)),
+        None => Err(not_scheduled(&pool, issue_id).await),
+    }
+}
+
+#[instrument(skip_all, name = "Cancelling a newsletter issue")]
+pub async fn cancel_newsletter_issue(
+    State(pool): State<PgPool>,
+    issue_id: Result<Path<Uuid>, PathRejection>,
+) -> Result<Json<IssueSchedule>, AppError> {
+    let Path(issue_id) = issue_id?;
+
+    let schedule = sqlx::query_as!(
+        IssueSchedule,
+        r#"
+        UPDATE newsletter_issues
+        SET status = 'cancelled'
+        WHERE newsletter_issue_id = $1 AND status = 'scheduled'
+        RETURNING newsletter_issue_id AS issue_id, status, scheduled_at
+        "#,
+        issue_id,
+    )
+    .fetch_optional(&pool)
+    .await
+    .inspect_err(|e| tracing::error!("Failed to execute query: {e:?}"))?;
+
+    match schedule {
+        Some(schedule) => Ok(Json(schedule)),
+        None => Err(not_scheduled(&pool, issue_id).await),
+    }
+}
+
+/// Explains why an issue could not be changed: it is either unknown, or it
+/// already left the `scheduled` state. The scheduler holds a lock on issues
+/// it is publishing, so a concurrent update sees the outcome.
+async fn not_scheduled(pool: &PgPool, issue_id: Uuid) -> AppError {
+    let status = sqlx::query_scalar!(
+        r#"SELECT status FROM newsletter_issues WHERE newsletter_issue_id = $1"#,
+        issue_id,
+    )
+    .fetch_optional(pool)
+    .await
+    .inspect_err(|e| tracing::error!("Failed to execute query: {e:?}"));
+
+    match status {
+        Ok(Some(status)) => AppError::BadRequest {
+            status: StatusCode::CONFLICT,
+            code: "issue_not_scheduled",
+            detail: format!("Only scheduled issues can be changed, this one is {status}."),
+            errors: vec![],
+        },
+        Ok(None) => AppError::NotFound("The newsletter issue does not exist."),
+        Err(e) => e.into(),
+    }
+}
+
+/// Where the delivery of an issue stands, as recorded in
+/// `issue_delivery_queue`.
+#[derive(Debug, Serialize)]
+pub struct IssueReport {
+    pub issue_id: Uuid,
+    pub title: String,
+    pub status: String,
+    pub scheduled_at: Option<DateTime<Utc>>,
+    pub published_at: Option<DateTime<Utc>>,
+    pub pending: i64,
+    pub delivered: i64,
+    /// Deliveries to subscribers who were no longer confirmed by the time
+    /// their turn came.
+    pub skipped: i64,
+    pub dead: i64,
+    /// The deliveries that ran out of attempts, with the last error seen.
+    pub dead_letters: Vec<DeadLetter>,
+}
+
+#[derive(Debug, Serialize)]
+pub struct DeadLetter {
+    pub subscriber_email: String,
+    pub n_attempts: i32,
+    pub last_error: Option<String>,
+}
+
+#[instrument(skip_all, name = "Inspecting a newsletter issue")]
+pub async fn get_newsletter_issue(
+    State(pool): State<PgPool>,
+    issue_id: Result<Path<Uuid>, PathRejection>,
+) -> Result<Json<IssueReport>, AppError> {
+    let Path(issue_id) = issue_id?;
+
+    let issue = sqlx::query!(
+        r#"
+        SELECT
+            i.title,
+            i.status,
+            i.scheduled_at,
+            i.published_at,
+            COUNT(q.*) FILTER (WHERE q.status = 'pending') AS "pending!",
+            COUNT(q.*) FILTER (WHERE q.status = 'delivered') AS "delivered!",
+            COUNT(q.*) FILTER (WHERE q.status = 'skipped') AS "skipped!",
+            COUNT(q.*) FILTER (WHERE q.status = 'dead') AS "dead!"
+        FROM newsletter_issues i
+        LEFT JOIN issue_delivery_queue q USING (newsletter_issue_id)
+        WHERE i.newsletter_issue_id = $1
+        GROUP BY i.newsletter_issue_id
+        "#,
+        issue_id,
+    )
+    .fetch_optional(&pool)
+    .await
+    .inspect_err(|e| tracing::error!("Failed to execute query: {e:?}"))?
+    .ok_or(AppError::NotFound("The newsletter issue does not exist."))?;
+
+    let dead_letters = sqlx::query_as!(
+        DeadLetter,
+        r#"
+        SELECT subscriber_email, n_attempts, last_error
+        FROM issue_delivery_queue
+        WHERE newsletter_issue_id = $1 AND status = 'dead'
+        ORDER BY subscriber_email
+        "#,
+        issue_id,
+    )
+    .fetch_all(&pool)
+    .await
+    .inspect_err(|e| tracing::error!("Failed to execute query: {e:?}"))?;
+
+    Ok(Json(IssueReport {
+        issue_id,
+        title: issue.title,
+        status: issue.status,
+        scheduled_at: issue.scheduled_at,
+        published_at: issue.published_at,
+        pending: issue.pending,
+        delivered: issue.delivered,
+        skipped: issue.skipped,
+        dead: issue.dead,
+        dead_letters,
+    }))
+}
+
+/// The body of an issue or draft: either Markdown, which both versions are
+/// rendered from, or the text and HTML versions themselves.
+#[derive(Deserialize)]
+pub struct ContentData {
+    pub markdown_content: Option<String>,
+    pub text_content: Option<String>,
+    pub html_content: Option<String>,
+}
+
+/// A validated [`ContentData`], with both versions filled in.
+pub(crate) struct Content {
+    pub markdown_content: Option<String>,
+    pub text_content: String,
+    pub html_content: String,
+}
+
+/// Issues and drafts need a title and every part of their content, and their
+/// templates are checked here so that deliveries cannot fail on them later.
+///
+/// Rows saved before templates were checked are not migrated, so code that
+/// parses stored content still handles templates that fail to parse.
+pub(crate) fn validate_content(title: &str, content: ContentData) -> Result<Content, AppError> {
+    let mut errors = vec![];
+    let mut require = |field: &str, value: Option<&str>| {
+        let error = match value {
+            None => FieldError {
+                field: field.into(),
+                code: "missing",
+                message: format!("`{field}` is required."),
+            },
+            Some(value) if value.trim().is_empty() => FieldError {
+                field: field.into(),
+                code: "empty",
+                message: format!("`{field}` must not be empty."),
+            },
+            Some(value) => match Template::parse(value) {
+                Ok(template) => return Some(template),
+                Err(e) => FieldError {
+                    field: field.into(),
+                    code: "invalid_template",
+                    message: e.to_string(),
+                },
+            },
+        };
+        errors.push(error);
+        None
+    };
+
+    require("title", Some(title));
+
+    let content = match content {
+        ContentData {
+            markdown_content: Some(markdown),
+            text_content: None,
+            html_content: None,
+        } => {
+            // Placeholders are swapped for markers while rendering, so that
+            // they also work as link destinations.
+            let (text_content, html_content) = match require("markdown_content", Some(&markdown)) {
+                Some(template) => {
+                    let rendered = markdown::render(&template.to_markers());
+                    (
+                        template.from_markers(&rendered.text_content),
+                        template.from_markers(&rendered.html_content),
+                    )
+                }
+                None => Default::default(),
+            };
+
+            Content {
+                markdown_content: Some(markdown),
+                text_content,
+                html_content,
+            }
+        }
+        ContentData {
+            markdown_content: Some(_),
+            ..
+        } => {
+            errors.push(FieldError {
+                field: "markdown_content".into(),
+                code: "conflict",
+                message: "`markdown_content` cannot be combined with `text_content` or \
+                          `html_content`."
+                    .into(),
+            });
+            return Err(AppError::Validation(errors));
+        }
+        ContentData {
+            markdown_content: None,
+            text_content,
+            html_content,
+        } => {
+            require("text_content", text_content.as_deref());
+            require("html_content", html_content.as_deref());
+
+            Content {
+                markdown_content: None,
+                text_content: text_content.unwrap_or_default(),
+                html_content: html_content.unwrap_or_default(),
+            }
+        }
+    };
+
+    if errors.is_empty() {
+        Ok(content)
+    } else {
+        Err(AppError::Validation(errors))
+    }
+}
+
+/// Saves the issue as published, or as scheduled if `scheduled_at` is set.
+#[instrument(skip_all, name = "Saving newsletter issue in the database")]
+async fn insert_newsletter_issue(
+    transaction: &mut Transaction<'_, Postgres>,
+    title: &str,
+    content: &Content,
+    published_by: Uuid,
+    scheduled_at: Option<DateTime<Utc>>,
+) -> Result<Uuid, sqlx::Error> {
+    let issue_id = Uuid::new_v4();
+    let (status, published_at) = match scheduled_at {
+        Some(_) => ("scheduled", None),
+        None => ("published", Some(Utc::now())),
+    };
+    // Another title can end up with the same slug concurrently, which the
+    // lock in `unique_slug` does not cover: the slug is picked again then.
+    let mut attempt = 1;
+    loop {
+        let slug = unique_slug(transaction, title).await?;
+        // Rolled back alone on a conflict, rather than the whole transaction.
+        let mut savepoint = transaction.begin().await?;
+
+        let result = sqlx::query!(
+            r#"
+            INSERT INTO newsletter_issues (
+                newsletter_issue_id, title, text_content, html_content,
+                published_by, status, scheduled_at, published_at, slug
+            )
+            VALUES ($1, $2, $3, $4, $5, $6, $7, $8, $9)
+            "#,
+            issue_id,
+            title,
+            content.text_content,
+            content.html_content,
+            published_by,
+            status,
+            scheduled_at,
+            published_at,
+            slug.as_ref(),
+        )
+        .execute(&mut *savepoint)
+        .await;
+
+        match result {
+            Ok(_) => {
+                savepoint.commit().await?;
+                break;
+            }
+            Err(e) if attempt < SLUG_ATTEMPTS && is_slug_conflict(&e) => {
+                savepoint.rollback().await?;
+                attempt += 1;
+            }
+            Err(e) => {
+                tracing::error!("Failed to execute query: {e:?}");
+                return Err(e);
+            }
+        }
+    }
+
+    Ok(issue_id)
+}
+
+/// How many slugs are tried for an issue before giving up.
+const SLUG_ATTEMPTS: u32 = 3;
+
+fn is_slug_conflict(e: &sqlx::Error) -> bool {
+    e.as_database_error()
+        .is_some_and(|e| e.constraint() == Some("newsletter_issues_slug_key"))
+}
+
+/// The archive slug for an issue titled `title`, numbered after the issues
+/// that already have it. Placeholders are left out of it.
+async fn unique_slug(
+    transaction: &mut Transaction<'_, Postgres>,
+    title: &str,
+) -> Result<IssueSlug, sqlx::Error> {
+    // Titles went through `validate_content` before, so this always parses.
+    let title = Template::parse(title)
+        .map(|template| template.without_variables())
+        .unwrap_or_default();
+    let slug = IssueSlug::from_title(&title);
+
+    // Held until the transaction commits, so that issues published at the
+    // same time with the same title see each other's slug instead of both
+    // picking the same one.
+    sqlx::query!("SELECT pg_advisory_xact_lock(hashtext($1))", slug.as_ref())
+        .execute(&mut **transaction)
+        .await
+        .inspect_err(|e| tracing::error!("Failed to execute query: {e:?}"))?;
+
+    let taken = sqlx::query_scalar!(
+        r#"SELECT slug FROM newsletter_issues WHERE slug = $1 OR slug LIKE $1 || '-%'"#,
+        slug.as_ref(),
+    )
+    .fetch_all(&mut **transaction)
+    .await
+    .inspect_err(|e| tracing::error!("Failed to execute query: {e:?}"))?;
+
+    let mut n = 1;
+    while taken.iter().any(|t| t == slug.nth(n).as_ref()) {
+        n += 1;
+    }
+
+    Ok(slug.nth(n))
+}
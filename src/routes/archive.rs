@@ -0,0 +1,352 @@
+use std::fmt::Write;
+use std::num::NonZeroU32;
+
+use axum::extract::rejection::{PathRejection, QueryRejection};
+use axum::extract::{Path, Query, State};
+use axum::http::{HeaderMap, HeaderValue, StatusCode, header};
+use axum::response::{IntoResponse, Response};
+use chrono::{DateTime, SecondsFormat, Utc};
+use serde::Deserialize;
+use sqlx::PgPool;
+use tracing::instrument;
+use uuid::Uuid;
+
+use crate::error::AppError;
+use crate::state::ApplicationBaseUrl;
+use crate::template::{Template, escape_html};
+
+const ARCHIVE_TITLE: &str = "Newsletter archive";
+const PAGE_SIZE: i64 = 20;
+/// Issues listed in `/feed.xml` and `/atom.xml`, most recent first.
+const FEED_SIZE: i64 = 20;
+/// The archive only changes when an issue goes out, so readers and proxies
+/// can keep it for a while. `Last-Modified` lets them check for new issues
+/// cheaply once it expires.
+const CACHE_CONTROL: &str = "public, max-age=300";
+
+const HTML: &str = "text/html; charset=utf-8";
+const RSS: &str = "application/rss+xml; charset=utf-8";
+const ATOM: &str = "application/atom+xml; charset=utf-8";
+
+const ISSUE_NOT_FOUND: &str = "The newsletter issue does not exist.";
+const PAGE_NOT_FOUND: &str = "The archive page does not exist.";
+
+#[derive(Deserialize)]
+pub struct ArchiveParameters {
+    /// Counted from 1, the most recent issues.
+    pub page: Option<NonZeroU32>,
+}
+
+struct IssueSummary {
+    slug: String,
+    title: String,
+    published_at: DateTime<Utc>,
+    /// When the most recent issue went out, which every page depends on.
+    last_published_at: DateTime<Utc>,
+}
+
+struct ArchivedIssue {
+    issue_id: Uuid,
+    slug: String,
+    title: String,
+    html_content: String,
+    published_at: DateTime<Utc>,
+}
+
+/// Lists the issues that went out, most recent first.
+#[instrument(skip_all, name = "Listing archived issues")]
+pub async fn archive(
+    State(pool): State<PgPool>,
+    headers: HeaderMap,
+    parameters: Result<Query<ArchiveParameters>, QueryRejection>,
+) -> Result<Response, AppError> {
+    let Query(parameters) = parameters?;
+    let page = parameters.page.map_or(1, NonZeroU32::get);
+
+    // One more than a page, to know whether there is a next one.
+    let mut issues = sqlx::query_as!(
+        IssueSummary,
+        r#"
+        SELECT
+            slug,
+            title,
+            published_at AS "published_at!",
+            MAX(published_at) OVER () AS "last_published_at!"
+        FROM newsletter_issues
+        WHERE status = 'published'
+        ORDER BY published_at DESC, newsletter_issue_id
+        LIMIT $1 OFFSET $2
+        "#,
+        PAGE_SIZE + 1,
+        (i64::from(page) - 1) * PAGE_SIZE,
+    )
+    .fetch_all(&pool)
+    .await
+    .inspect_err(|e| tracing::error!("Failed to execute query: {e:?}"))?;
+
+    if issues.is_empty() && page > 1 {
+        return Err(AppError::NotFound(PAGE_NOT_FOUND));
+    }
+
+    let has_next = issues.len() as i64 > PAGE_SIZE;
+    issues.truncate(PAGE_SIZE as usize);
+
+    let mut body = format!("<h1>{ARCHIVE_TITLE}</h1>");
+
+    if issues.is_empty() {
+        body.push_str("<p>No issues have been sent yet.</p>");
+    } else {
+        body.push_str("<ul>");
+        for issue in &issues {
+            write!(
+                body,
+                r#"<li><a href="/archive/{slug}">{title}</a> <time datetime="{date}">{day}</time></li>"#,
+                slug = issue.slug,
+                title = public_text(&issue.title),
+                date = rfc3339(issue.published_at),
+                day = issue.published_at.format("%B %-d, %Y"),
+            )
+            .unwrap();
+        }
+        body.push_str("</ul>");
+    }
+
+    body.push_str("<nav>");
+    if page > 1 {
+        write!(
+            body,
+            r#"<a rel="prev" href="/archive?page={}">Newer issues</a> "#,
+            page - 1
+        )
+        .unwrap();
+    }
+    if has_next {
+        write!(
+            body,
+            r#"<a rel="next" href="/archive?page={}">Older issues</a>"#,
+            page + 1
+        )
+        .unwrap();
+    }
+    body.push_str("</nav>");
+
+    let last_modified = issues.first().map(|issue| issue.last_published_at);
+
+    Ok(cached(
+        &headers,
+        last_modified,
+        HTML,
+        page_html(ARCHIVE_TITLE, &body),
+    ))
+}
+
+/// A single issue as it went out, without anything personal to its
+/// recipients.
+#[instrument(skip_all, name = "Showing an archived issue")]
+pub async fn archived_issue(
+    State(pool): State<PgPool>,
+    headers: HeaderMap,
+    slug: Result<Path<String>, PathRejection>,
+) -> Result<Response, AppError> {
+    let Path(slug) = slug?;
+
+    let issue = sqlx::query_as!(
+        ArchivedIssue,
+        r#"
+        SELECT
+            newsletter_issue_id AS issue_id,
+            slug,
+            title,
+            html_content,
+            published_at AS "published_at!"
+        FROM newsletter_issues
+        WHERE slug = $1 AND status = 'published'
+        "#,
+        slug,
+    )
+    .fetch_optional(&pool)
+    .await
+    .inspect_err(|e| tracing::error!("Failed to execute query: {e:?}"))?
+    .ok_or(AppError::NotFound(ISSUE_NOT_FOUND))?;
+
+    let title = public_text(&issue.title);
+    let body = format!(
+        r#"<p><a href="/archive">{ARCHIVE_TITLE}</a></p><article><h1>{title}</h1><p><time datetime="{date}">{day}</time></p>{content}</article>"#,
+        date = rfc3339(issue.published_at),
+        day = issue.published_at.format("%B %-d, %Y"),
+        content = public_html(&issue.html_content),
+    );
+
+    Ok(cached(
+        &headers,
+        Some(issue.published_at),
+        HTML,
+        page_html(&title, &body),
+    ))
+}
+
+/// The most recent issues as an RSS 2.0 feed.
+#[instrument(skip_all, name = "Serving the RSS feed")]
+pub async fn rss_feed(
+    State(pool): State<PgPool>,
+    State(base_url): State<ApplicationBaseUrl>,
+    headers: HeaderMap,
+) -> Result<Response, AppError> {
+    let issues = feed_issues(&pool).await?;
+    let base_url = base_url.0.trim_end_matches('/');
+    let last_modified = issues.first().map(|issue| issue.published_at);
+
+    let mut items = String::new();
+    for issue in &issues {
+        let link = format!("{base_url}/archive/{}", issue.slug);
+        write!(
+            items,
+            r#"<item><title>{title}</title><link>{link}</link><guid isPermaLink="true">{link}</guid><pubDate>{date}</pubDate><description>{content}</description></item>"#,
+            title = public_text(&issue.title),
+            date = issue.published_at.to_rfc2822(),
+            content = xml_text(&public_html(&issue.html_content)),
+        )
+        .unwrap();
+    }
+
+    let last_build_date = last_modified
+        .map(|at| format!("<lastBuildDate>{}</lastBuildDate>", at.to_rfc2822()))
+        .unwrap_or_default();
+    let feed = format!(
+        r#"<?xml version="1.0" encoding="utf-8"?><rss version="2.0"><channel><title>{ARCHIVE_TITLE}</title><link>{base_url}/archive</link><description>Every issue of the newsletter.</description>{last_build_date}{items}</channel></rss>"#,
+    );
+
+    Ok(cached(&headers, last_modified, RSS, feed))
+}
+
+/// The most recent issues as an Atom feed.
+#[instrument(skip_all, name = "Serving the Atom feed")]
+pub async fn atom_feed(
+    State(pool): State<PgPool>,
+    State(base_url): State<ApplicationBaseUrl>,
+    headers: HeaderMap,
+) -> Result<Response, AppError> {
+    let issues = feed_issues(&pool).await?;
+    let base_url = base_url.0.trim_end_matches('/');
+    let last_modified = issues.first().map(|issue| issue.published_at);
+
+    let mut entries = String::new();
+    for issue in &issues {
+        write!(
+            entries,
+            r#"<entry><title>{title}</title><id>urn:uuid:{id}</id><link rel="alternate" href="{base_url}/archive/{slug}"/><published>{date}</published><updated>{date}</updated><content type="html">{content}</content></entry>"#,
+            title = public_text(&issue.title),
+            id = issue.issue_id,
+            slug = issue.slug,
+            date = rfc3339(issue.published_at),
+            content = xml_text(&public_html(&issue.html_content)),
+        )
+        .unwrap();
+    }
+
+    // Atom requires `updated` even when there is nothing in the feed yet.
+    let updated = rfc3339(last_modified.unwrap_or(DateTime::UNIX_EPOCH));
+    let feed = format!(
+        r#"<?xml version="1.0" encoding="utf-8"?><feed xmlns="http://www.w3.org/2005/Atom"><title>{ARCHIVE_TITLE}</title><id>{base_url}/archive</id><link rel="self" href="{base_url}/atom.xml"/><link rel="alternate" href="{base_url}/archive"/><author><name>{ARCHIVE_TITLE}</name></author><updated>{updated}</updated>{entries}</feed>"#,
+    );
+
+    Ok(cached(&headers, last_modified, ATOM, feed))
+}
+
+#[instrument(skip_all, name = "Fetching issues for feeds")]
+async fn feed_issues(pool: &PgPool) -> Result<Vec<ArchivedIssue>, sqlx::Error> {
+    sqlx::query_as!(
+        ArchivedIssue,
+        r#"
+        SELECT
+            newsletter_issue_id AS issue_id,
+            slug,
+            title,
+            html_content,
+            published_at AS "published_at!"
+        FROM newsletter_issues
+        WHERE status = 'published'
+        ORDER BY published_at DESC, newsletter_issue_id
+        LIMIT $1
+        "#,
+        FEED_SIZE,
+    )
+    .fetch_all(pool)
+    .await
+    .inspect_err(|e| tracing::error!("Failed to execute query: {e:?}"))
+}
+
+/// Adds the cache headers to `body`, or answers `304 Not Modified` when the
+/// client's copy, per `If-Modified-Since`, is still current.
+fn cached(
+    request_headers: &HeaderMap,
+    last_modified: Option<DateTime<Utc>>,
+    content_type: &'static str,
+    body: String,
+) -> Response {
+    // HTTP dates have no fractions of a second.
+    let not_modified = request_headers
+        .get(header::IF_MODIFIED_SINCE)
+        .and_then(|value| value.to_str().ok())
+        .and_then(|value| DateTime::parse_from_rfc2822(value).ok())
+        .zip(last_modified)
+        .is_some_and(|(since, last_modified)| last_modified.timestamp() <= since.timestamp());
+
+    let mut response = match not_modified {
+        true => StatusCode::NOT_MODIFIED.into_response(),
+        false => ([(header::CONTENT_TYPE, content_type)], body).into_response(),
+    };
+
+    let headers = response.headers_mut();
+    headers.insert(
+        header::CACHE_CONTROL,
+        HeaderValue::from_static(CACHE_CONTROL),
+    );
+    if let Some(last_modified) = last_modified {
+        let value = last_modified
+            .format("%a, %d %b %Y %H:%M:%S GMT")
+            .to_string();
+        if let Ok(value) = HeaderValue::try_from(value) {
+            headers.insert(header::LAST_MODIFIED, value);
+        }
+    }
+
+    response
+}
+
+/// Escaped text with the placeholders left out, since there is no recipient
+/// to fill them in for.
+fn public_text(source: &str) -> String {
+    let mut out = String::new();
+    escape_html(&without_variables(source), &mut out);
+    out
+}
+
+/// Issue HTML with the placeholders left out, sanitized since the archive is
+/// served from our own origin.
+fn public_html(source: &str) -> String {
+    ammonia::clean(&without_variables(source))
+}
+
+/// Issues that fail to parse, see `validate_content`, are shown as they are.
+fn without_variables(source: &str) -> String {
+    Template::parse(source)
+        .map(|template| template.without_variables())
+        .unwrap_or_else(|_| source.to_string())
+}
+
+fn xml_text(text: &str) -> String {
+    let mut out = String::new();
+    escape_html(text, &mut out);
+    out
+}
+
+fn rfc3339(at: DateTime<Utc>) -> String {
+    at.to_rfc3339_opts(SecondsFormat::Secs, true)
+}
+
+fn page_html(title: &str, body: &str) -> String {
+    format!(
+        r#"<!doctype html><html><head><meta charset="utf-8"><title>{title}</title><link rel="alternate" type="application/rss+xml" title="{ARCHIVE_TITLE}" href="/feed.xml"><link rel="alternate" type="application/atom+xml" title="{ARCHIVE_TITLE}" href="/atom.xml"></head><body>{body}</body></html>"#
+    )
+}
@@ -0,0 +1,106 @@
+use std::fmt::Write;
+
+use axum::extract::{Path, State};
+use axum::http::StatusCode;
+use axum::response::Html;
+use tracing::instrument;
+
+use crate::email_client::{CaughtEmail, MailCatcher};
+use crate::error::AppError;
+use crate::template::escape_html;
+
+const MESSAGE_NOT_FOUND: &str = "The caught email does not exist.";
+
+/// Lists the caught emails along with the links they contain, so the
+/// confirmation and unsubscribe flows can be clicked through locally.
+#[instrument(skip_all, name = "Listing caught emails")]
+pub async fn list_mailbox(State(mail_catcher): State<MailCatcher>) -> Html<String> {
+    let messages = mail_catcher.messages();
+
+    let mut body = String::from("<h1>Mailbox</h1>");
+
+    if messages.is_empty() {
+        body.push_str("<p>No emails have been sent yet.</p>");
+    }
+
+    for message in &messages {
+        write!(
+            body,
+            r#"<section><h2><a href="/dev/mailbox/{id}">{subject}</a></h2><p>From {from} to {to} at {caught_at}</p><ul>"#,
+            id = message.id,
+            subject = escape(&message.subject),
+            from = escape(&message.from),
+            to = escape(&message.to),
+            caught_at = message.caught_at.to_rfc3339(),
+        )
+        .unwrap();
+
+        for link in links(message) {
+            let link = escape(link);
+            write!(body, r#"<li><a href="{link}">{link}</a></li>"#).unwrap();
+        }
+
+        body.push_str("</ul></section>");
+    }
+
+    Html(page("Mailbox", &body))
+}
+
+/// Renders a caught email's HTML body as the recipient would see it, followed
+/// by its plain text alternative and headers.
+#[instrument(skip_all, name = "Showing a caught email")]
+pub async fn show_mailbox_message(
+    State(mail_catcher): State<MailCatcher>,
+    Path(id): Path<u64>,
+) -> Result<Html<String>, AppError> {
+    let message = mail_catcher
+        .message(id)
+        .ok_or(AppError::NotFound(MESSAGE_NOT_FOUND))?;
+
+    let mut body = format!(
+        r#"<p><a href="/dev/mailbox">Back to mailbox</a></p><h1>{subject}</h1><p>From {from} to {to}</p><hr>{html}<hr><pre>{raw}</pre>"#,
+        subject = escape(&message.subject),
+        from = escape(&message.from),
+        to = escape(&message.to),
+        html = message.html_content,
+        raw = escape(&message.raw_content),
+    );
+
+    if !message.headers.is_empty() {
+        body.push_str("<h2>Headers</h2><dl>");
+        for (name, value) in &message.headers {
+            write!(body, "<dt>{name}</dt><dd>{}</dd>", escape(value)).unwrap();
+        }
+        body.push_str("</dl>");
+    }
+
+    Ok(Html(page(&escape(&message.subject), &body)))
+}
+
+#[instrument(skip_all, name = "Clearing caught emails")]
+pub async fn clear_mailbox(State(mail_catcher): State<MailCatcher>) -> StatusCode {
+    mail_catcher.clear();
+    StatusCode::NO_CONTENT
+}
+
+fn page(title: &str, body: &str) -> String {
+    format!(
+        r#"<!doctype html><html><head><meta charset="utf-8"><title>{title}</title></head><body>{body}</body></html>"#
+    )
+}
+
+/// The URLs found in the plain text body, which carries the same links as the
+/// HTML one without any markup around them.
+fn links(message: &CaughtEmail) -> impl Iterator<Item = &str> {
+    message
+        .raw_content
+        .split_whitespace()
+        .filter(|word| word.starts_with("http://") || word.starts_with("https://"))
+        .map(|word| word.trim_end_matches(['.', ',', ')', '>']))
+}
+
+fn escape(text: &str) -> String {
+    let mut escaped = String::with_capacity(text.len());
+    escape_html(text, &mut escaped);
+    escaped
+}
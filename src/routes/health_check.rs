@@ -1,7 +1,39 @@
+use std::sync::Arc;
+
+use axum::Json;
+use axum::extract::State;
 use axum::http::StatusCode;
+use serde::Serialize;
 use tracing::instrument;
 
+use crate::email_client::{CircuitState, CircuitStatus, EmailClient};
+
 #[instrument]
 pub async fn health_check() -> StatusCode {
     StatusCode::OK
 }
+
+#[derive(Serialize)]
+pub struct HealthDetails {
+    /// `degraded` while emails cannot go out; the app itself still serves
+    /// requests, so the status code stays `200 OK`.
+    status: &'static str,
+    email_circuit: Option<CircuitStatus>,
+}
+
+/// Like `/health_check`, with the state of the dependencies that can fail on
+/// their own.
+#[instrument(skip_all, name = "Checking health in detail")]
+pub async fn health_check_details(
+    State(email_client): State<Arc<EmailClient>>,
+) -> Json<HealthDetails> {
+    let email_circuit = email_client.circuit_status();
+    let degraded = email_circuit
+        .as_ref()
+        .is_some_and(|status| status.state != CircuitState::Closed);
+
+    Json(HealthDetails {
+        status: if degraded { "degraded" } else { "ok" },
+        email_circuit,
+    })
+}
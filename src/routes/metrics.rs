@@ -0,0 +1,54 @@
+use std::fmt::Write;
+use std::sync::Arc;
+
+use axum::extract::State;
+use axum::http::header;
+use axum::response::IntoResponse;
+use tracing::instrument;
+
+use crate::email_client::{CircuitState, EmailClient};
+
+const CONTENT_TYPE: &str = "text/plain; version=0.0.4; charset=utf-8";
+
+/// Prometheus text exposition of the email circuit breaker.
+#[instrument(skip_all, name = "Serving metrics")]
+pub async fn metrics(State(email_client): State<Arc<EmailClient>>) -> impl IntoResponse {
+    let mut body = String::new();
+
+    if let Some(status) = email_client.circuit_status() {
+        body.push_str("# HELP email_circuit_state Whether the email circuit breaker is in the state, 1, or not, 0.\n# TYPE email_circuit_state gauge\n");
+        for (state, name) in [
+            (CircuitState::Closed, "closed"),
+            (CircuitState::Open, "open"),
+            (CircuitState::HalfOpen, "half_open"),
+        ] {
+            writeln!(
+                body,
+                r#"email_circuit_state{{state="{name}"}} {}"#,
+                u8::from(status.state == state)
+            )
+            .unwrap();
+        }
+
+        writeln!(
+            body,
+            "# HELP email_circuit_consecutive_failures Transient send failures in a row.\n# TYPE email_circuit_consecutive_failures gauge\nemail_circuit_consecutive_failures {}",
+            status.consecutive_failures
+        )
+        .unwrap();
+        writeln!(
+            body,
+            "# HELP email_circuit_opened_total Times the email circuit breaker opened.\n# TYPE email_circuit_opened_total counter\nemail_circuit_opened_total {}",
+            status.opened_total
+        )
+        .unwrap();
+        writeln!(
+            body,
+            "# HELP email_circuit_rejected_total Sends turned away while the circuit was open.\n# TYPE email_circuit_rejected_total counter\nemail_circuit_rejected_total {}",
+            status.rejected_total
+        )
+        .unwrap();
+    }
+
+    ([(header::CONTENT_TYPE, CONTENT_TYPE)], body)
+}
@@ -1,50 +1,228 @@
-use axum::Form;
+use std::collections::BTreeMap;
+use std::sync::Arc;
+
+use axum::Json;
 use axum::extract::State;
 use axum::http::StatusCode;
-use serde::Deserialize;
-use sqlx::PgPool;
+use axum::response::{IntoResponse, Response};
+use rand::Rng;
+use rand::distr::Alphanumeric;
+use serde::{Deserialize, Serialize};
+use sqlx::{PgPool, Postgres, Transaction};
+use tracing::field::Empty;
 use tracing::instrument;
+use uuid::Uuid;
 
-use crate::domain::Subscriber;
+use crate::domain::{Canonicalization, Subscriber, SubscriberError};
+use crate::email_client::{EmailClient, SendError};
+use crate::error::AppError;
+use crate::extract::{BodyFormat, FormOrJson};
+use crate::state::ApplicationBaseUrl;
 
 #[derive(Deserialize)]
 pub struct FormData {
     pub name: String,
     pub email: String,
+    /// Only JSON bodies can carry attributes.
+    #[serde(default)]
+    pub attributes: BTreeMap<String, String>,
+}
+
+/// The body JSON clients get back. It reads the same whether or not the
+/// address was already on the list.
+#[derive(Serialize)]
+struct SubscribeResponse {
+    message: &'static str,
 }
 
+const SUBSCRIBE_MESSAGE: &str = "Check your inbox to confirm your subscription.";
+
 #[instrument(
     skip_all,
     name = "Adding a new subscriber"
-    fields(subsriber_name = %data.name, subscriber_email = %data.email)
+    fields(subscriber_name = Empty, subscriber_email = Empty)
 )]
-pub async fn subscribe(State(pool): State<PgPool>, Form(data): Form<FormData>) -> StatusCode {
-    let subscriber = match data.try_into() {
-        Ok(data) => data,
-        Err(_) => return StatusCode::UNPROCESSABLE_ENTITY,
+pub async fn subscribe(
+    State(pool): State<PgPool>,
+    State(email_client): State<Arc<EmailClient>>,
+    State(base_url): State<ApplicationBaseUrl>,
+    State(canonicalization): State<Canonicalization>,
+    FormOrJson { data, format }: FormOrJson<FormData>,
+) -> Result<Response, AppError> {
+    tracing::Span::current()
+        .record("subscriber_name", &data.name)
+        .record("subscriber_email", &data.email);
+
+    let subscriber = Subscriber::try_from(data)?;
+    let email_canonical = subscriber
+        .email
+        .canonical(canonicalization)
+        .map_err(|e| SubscriberError(vec![e.into()]))?;
+
+    let mut transaction = pool.begin().await?;
+
+    // Already confirmed addresses are sent an email too, so that the
+    // endpoint answers the same, and fails the same, whether or not the
+    // address is on the list.
+    let Some(subscriber_id) =
+        insert_subscriber(&mut transaction, &subscriber, &email_canonical).await?
+    else {
+        transaction.commit().await?;
+        send_already_subscribed_email(&email_client, subscriber).await?;
+        return Ok(subscribed(format));
     };
 
-    match insert_subscriber(&pool, &subscriber).await {
-        Ok(_) => StatusCode::OK,
-        Err(_) => StatusCode::INTERNAL_SERVER_ERROR,
+    let subscription_token = generate_subscription_token();
+    store_token(&mut transaction, subscriber_id, &subscription_token).await?;
+
+    transaction.commit().await?;
+
+    send_confirmation_email(&email_client, subscriber, &base_url.0, &subscription_token).await?;
+
+    Ok(subscribed(format))
+}
+
+fn subscribed(format: BodyFormat) -> Response {
+    match format {
+        BodyFormat::Form => StatusCode::OK.into_response(),
+        BodyFormat::Json => Json(SubscribeResponse {
+            message: SUBSCRIBE_MESSAGE,
+        })
+        .into_response(),
     }
 }
 
+/// Inserts the subscriber as pending, or locks the existing row with the same
+/// email address or, failing that, the same canonical form. The two differ
+/// for rows saved under other canonicalization settings.
+///
+/// Returns the id of a subscriber that should be sent a confirmation email,
+/// or `None` if the subscriber has already confirmed. Subscribers who left
+/// the list are moved back to pending, with the attributes sent this time,
+/// so they can opt in again.
 #[instrument(skip_all, name = "Saving new subscriber into the database")]
-pub async fn insert_subscriber(pool: &PgPool, data: &Subscriber) -> Result<(), sqlx::Error> {
+pub async fn insert_subscriber(
+    transaction: &mut Transaction<'_, Postgres>,
+    data: &Subscriber,
+    email_canonical: &str,
+) -> Result<Option<Uuid>, sqlx::Error> {
+    // `ON CONFLICT` waits for a concurrent insert of the same email to
+    // commit, so the `FOR UPDATE` below always finds a row to lock. Both
+    // `email` and `email_canonical` are unique.
     sqlx::query!(
         r#"
-        INSERT INTO subscriptions (id, email, name, subscribed_at)
-        VALUES ($1, $2, $3, $4)
+        INSERT INTO subscriptions (
+            id, email, email_canonical, name, attributes, subscribed_at, status
+        )
+        VALUES ($1, $2, $3, $4, $5, $6, 'pending')
+        ON CONFLICT DO NOTHING
         "#,
-        uuid::Uuid::new_v4(),
+        Uuid::new_v4(),
         data.email.as_ref(),
+        email_canonical,
         data.name.as_ref(),
+        sqlx::types::Json(data.attributes.as_ref()) as _,
         chrono::Utc::now(),
     )
-    .execute(pool)
+    .execute(&mut **transaction)
+    .await
+    .inspect_err(|e| tracing::error!("Failed to execute query: {e:?}"))?;
+
+    let existing = sqlx::query!(
+        r#"
+        SELECT id, status FROM subscriptions
+        WHERE email = $1 OR email_canonical = $2
+        ORDER BY email = $1 DESC
+        LIMIT 1
+        FOR UPDATE
+        "#,
+        data.email.as_ref(),
+        email_canonical,
+    )
+    .fetch_one(&mut **transaction)
+    .await
+    .inspect_err(|e| tracing::error!("Failed to execute query: {e:?}"))?;
+
+    match existing.status.as_str() {
+        "confirmed" => Ok(None),
+        "pending" => Ok(Some(existing.id)),
+        _ => {
+            sqlx::query!(
+                r#"UPDATE subscriptions SET status = 'pending', attributes = $2 WHERE id = $1"#,
+                existing.id,
+                sqlx::types::Json(data.attributes.as_ref()) as _,
+            )
+            .execute(&mut **transaction)
+            .await
+            .inspect_err(|e| tracing::error!("Failed to execute query: {e:?}"))?;
+
+            Ok(Some(existing.id))
+        }
+    }
+}
+
+#[instrument(skip_all, name = "Storing subscription token in the database")]
+pub async fn store_token(
+    transaction: &mut Transaction<'_, Postgres>,
+    subscriber_id: Uuid,
+    subscription_token: &str,
+) -> Result<(), sqlx::Error> {
+    sqlx::query!(
+        r#"
+        INSERT INTO subscription_tokens (subscription_token, subscriber_id)
+        VALUES ($1, $2)
+        "#,
+        subscription_token,
+        subscriber_id,
+    )
+    .execute(&mut **transaction)
     .await
     .inspect_err(|e| tracing::error!("Failed to execute query: {e:?}"))?;
 
     Ok(())
 }
+
+#[instrument(skip_all, name = "Sending a confirmation email to the new subscriber")]
+pub async fn send_confirmation_email(
+    email_client: &EmailClient,
+    subscriber: Subscriber,
+    base_url: &str,
+    subscription_token: &str,
+) -> Result<(), SendError> {
+    let confirmation_link = format!("{base_url}/subscriptions/confirm?token={subscription_token}");
+
+    let raw_content = format!(
+        "Welcome to our newsletter!\nVisit {confirmation_link} to confirm your subscription."
+    );
+    let html_content = format!(
+        "Welcome to our newsletter!<br />\
+        Click <a href=\"{confirmation_link}\">here</a> to confirm your subscription."
+    );
+
+    email_client
+        .send_email(subscriber.email, "Welcome!", raw_content, html_content)
+        .await
+        .inspect_err(|e| tracing::error!("Failed to send confirmation email: {e:?}"))
+}
+
+/// Sent instead of a confirmation email to subscribers who signed up again.
+#[instrument(skip_all, name = "Sending a notice to an already confirmed subscriber")]
+pub async fn send_already_subscribed_email(
+    email_client: &EmailClient,
+    subscriber: Subscriber,
+) -> Result<(), SendError> {
+    let content = "You are already subscribed to our newsletter, there is nothing else to do.";
+
+    email_client
+        .send_email(subscriber.email, "Welcome back!", content, content)
+        .await
+        .inspect_err(|e| tracing::error!("Failed to send already subscribed email: {e:?}"))
+}
+
+fn generate_subscription_token() -> String {
+    rand::rng()
+        .sample_iter(&Alphanumeric)
+        .take(25)
+        .map(char::from)
+        .collect()
+}
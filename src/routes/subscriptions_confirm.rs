@@ -0,0 +1,80 @@
+use axum::extract::rejection::QueryRejection;
+use axum::extract::{Query, State};
+use axum::http::StatusCode;
+use serde::Deserialize;
+use sqlx::{PgPool, Postgres, Transaction};
+use tracing::instrument;
+use uuid::Uuid;
+
+use crate::error::AppError;
+
+#[derive(Deserialize)]
+pub struct ConfirmParameters {
+    pub token: String,
+}
+
+/// Tokens only work once, so that an old confirmation link cannot bring back
+/// a subscriber who has left the list since.
+#[instrument(skip_all, name = "Confirming a pending subscriber")]
+pub async fn confirm(
+    State(pool): State<PgPool>,
+    parameters: Result<Query<ConfirmParameters>, QueryRejection>,
+) -> Result<StatusCode, AppError> {
+    let Query(parameters) = parameters?;
+
+    let mut transaction = pool.begin().await?;
+
+    let subscriber_id = take_subscriber_id_from_token(&mut transaction, &parameters.token)
+        .await?
+        .ok_or(AppError::Unauthorized(
+            "The confirmation token is not valid.",
+        ))?;
+
+    confirm_subscriber(&mut transaction, subscriber_id).await?;
+
+    transaction.commit().await?;
+
+    Ok(StatusCode::OK)
+}
+
+/// Only pending subscribers are confirmed, subscribers who left the list have
+/// to sign up again.
+#[instrument(skip_all, name = "Marking subscriber as confirmed")]
+pub async fn confirm_subscriber(
+    transaction: &mut Transaction<'_, Postgres>,
+    subscriber_id: Uuid,
+) -> Result<(), sqlx::Error> {
+    sqlx::query!(
+        r#"UPDATE subscriptions SET status = 'confirmed' WHERE id = $1 AND status = 'pending'"#,
+        subscriber_id,
+    )
+    .execute(&mut **transaction)
+    .await
+    .inspect_err(|e| tracing::error!("Failed to execute query: {e:?}"))?;
+
+    Ok(())
+}
+
+/// Deletes the token, along with the subscriber's other tokens, and returns
+/// the subscriber it was issued for.
+#[instrument(skip_all, name = "Taking subscriber id from token")]
+pub async fn take_subscriber_id_from_token(
+    transaction: &mut Transaction<'_, Postgres>,
+    subscription_token: &str,
+) -> Result<Option<Uuid>, sqlx::Error> {
+    let result = sqlx::query!(
+        r#"
+        DELETE FROM subscription_tokens
+        WHERE subscriber_id = (
+            SELECT subscriber_id FROM subscription_tokens WHERE subscription_token = $1
+        )
+        RETURNING subscriber_id
+        "#,
+        subscription_token,
+    )
+    .fetch_all(&mut **transaction)
+    .await
+    .inspect_err(|e| tracing::error!("Failed to execute query: {e:?}"))?;
+
+    Ok(result.first().map(|r| r.subscriber_id))
+}
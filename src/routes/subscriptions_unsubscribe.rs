@@ -0,0 +1,93 @@
+use axum::extract::rejection::QueryRejection;
+use axum::extract::{Query, State};
+use axum::http::StatusCode;
+use axum::response::Html;
+use serde::Deserialize;
+use sqlx::PgPool;
+use tracing::instrument;
+
+use crate::domain::{Canonicalization, Email};
+use crate::error::AppError;
+use crate::template::escape_html;
+use crate::unsubscribe::UnsubscribeLinks;
+
+#[derive(Deserialize)]
+pub struct UnsubscribeParameters {
+    pub token: String,
+}
+
+/// The page behind the link in the email body. Following it changes nothing,
+/// since link scanners and prefetching mail clients follow links too: the
+/// page asks for confirmation with a form posting the same token.
+#[instrument(skip_all, name = "Showing the unsubscribe page")]
+pub async fn unsubscribe_page(
+    State(links): State<UnsubscribeLinks>,
+    parameters: Result<Query<UnsubscribeParameters>, QueryRejection>,
+) -> Result<Html<String>, AppError> {
+    let Query(parameters) = parameters?;
+
+    let email = links
+        .verify(&parameters.token)
+        .ok_or(AppError::Unauthorized(
+            "The unsubscribe token is not valid.",
+        ))?;
+
+    let mut escaped_email = String::new();
+    escape_html(&email, &mut escaped_email);
+    let mut escaped_token = String::new();
+    escape_html(&parameters.token, &mut escaped_token);
+
+    Ok(Html(format!(
+        r#"<!doctype html><html><head><meta charset="utf-8"><title>Unsubscribe</title></head><body><h1>Unsubscribe</h1><p>Stop sending the newsletter to {escaped_email}?</p><form method="post" action="/subscriptions/unsubscribe?token={escaped_token}"><button type="submit">Unsubscribe</button></form></body></html>"#
+    )))
+}
+
+/// Serves both the form on the unsubscribe page and RFC 8058 one-click
+/// unsubscribe (`List-Unsubscribe=One-Click`), whose body carries nothing we
+/// need since the token is part of the URL.
+#[instrument(skip_all, name = "Unsubscribing a subscriber")]
+pub async fn unsubscribe(
+    State(pool): State<PgPool>,
+    State(links): State<UnsubscribeLinks>,
+    State(canonicalization): State<Canonicalization>,
+    parameters: Result<Query<UnsubscribeParameters>, QueryRejection>,
+) -> Result<StatusCode, AppError> {
+    let Query(parameters) = parameters?;
+
+    let email = links
+        .verify(&parameters.token)
+        .ok_or(AppError::Unauthorized(
+            "The unsubscribe token is not valid.",
+        ))?;
+
+    // The token was issued for an address we sent to, so it always parses.
+    let email = Email::try_from(email).map_err(|e| AppError::Unexpected(Box::new(e)))?;
+    // Rows stored under other canonicalization settings are only found by
+    // the address itself, whose canonical form may not even exist anymore.
+    let email_canonical = email.canonical(canonicalization).ok();
+
+    mark_subscriber_as_unsubscribed(&pool, &email, email_canonical.as_deref()).await?;
+
+    Ok(StatusCode::OK)
+}
+
+#[instrument(skip_all, name = "Marking subscriber as unsubscribed")]
+pub async fn mark_subscriber_as_unsubscribed(
+    pool: &PgPool,
+    email: &Email,
+    email_canonical: Option<&str>,
+) -> Result<(), sqlx::Error> {
+    sqlx::query!(
+        r#"
+        UPDATE subscriptions SET status = 'unsubscribed'
+        WHERE email = $1 OR email_canonical = $2
+        "#,
+        email.as_ref(),
+        email_canonical,
+    )
+    .execute(pool)
+    .await
+    .inspect_err(|e| tracing::error!("Failed to execute query: {e:?}"))?;
+
+    Ok(())
+}
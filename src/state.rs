@@ -0,0 +1,82 @@
+use std::sync::Arc;
+
+use axum::extract::FromRef;
+use sqlx::PgPool;
+
+use crate::domain::{Canonicalization, Email};
+use crate::email_client::EmailClient;
+use crate::idempotency::IdempotencyKeyTtl;
+use crate::unsubscribe::UnsubscribeLinks;
+
+/// Shared state handed to every route.
+///
+/// Handlers should extract only the parts they need, e.g. `State<PgPool>`,
+/// which is made possible by the [`FromRef`] implementations below.
+#[derive(Clone)]
+pub struct AppState {
+    pub conn_pool: PgPool,
+    pub email_client: Arc<EmailClient>,
+    pub base_url: ApplicationBaseUrl,
+    pub unsubscribe_links: UnsubscribeLinks,
+    pub email_canonicalization: Canonicalization,
+    pub idempotency_key_ttl: IdempotencyKeyTtl,
+    pub test_recipients: TestRecipients,
+}
+
+#[derive(Clone)]
+pub struct ApplicationBaseUrl(pub String);
+
+/// The only addresses drafts can be sent to as a test, so that the endpoint
+/// cannot be used to mail anyone from the newsletter's address.
+#[derive(Clone)]
+pub struct TestRecipients(pub Arc<[Email]>);
+
+impl TestRecipients {
+    pub fn allows(&self, email: &Email) -> bool {
+        self.0
+            .iter()
+            .any(|allowed| allowed.as_ref().eq_ignore_ascii_case(email.as_ref()))
+    }
+}
+
+impl FromRef<AppState> for PgPool {
+    fn from_ref(state: &AppState) -> Self {
+        state.conn_pool.clone()
+    }
+}
+
+impl FromRef<AppState> for Arc<EmailClient> {
+    fn from_ref(state: &AppState) -> Self {
+        state.email_client.clone()
+    }
+}
+
+impl FromRef<AppState> for ApplicationBaseUrl {
+    fn from_ref(state: &AppState) -> Self {
+        state.base_url.clone()
+    }
+}
+
+impl FromRef<AppState> for UnsubscribeLinks {
+    fn from_ref(state: &AppState) -> Self {
+        state.unsubscribe_links.clone()
+    }
+}
+
+impl FromRef<AppState> for Canonicalization {
+    fn from_ref(state: &AppState) -> Self {
+        state.email_canonicalization
+    }
+}
+
+impl FromRef<AppState> for TestRecipients {
+    fn from_ref(state: &AppState) -> Self {
+        state.test_recipients.clone()
+    }
+}
+
+impl FromRef<AppState> for IdempotencyKeyTtl {
+    fn from_ref(state: &AppState) -> Self {
+        state.idempotency_key_ttl
+    }
+}
@@ -1,11 +1,14 @@
 use axum::Router;
 use axum::http::{Request, header::HeaderName};
-use tower_http::request_id::{MakeRequestUuid, SetRequestIdLayer};
+use axum::middleware::from_fn;
+use tower_http::request_id::{MakeRequestUuid, PropagateRequestIdLayer, SetRequestIdLayer};
 use tower_http::trace::TraceLayer;
 use tracing::{Subscriber, error, info_span, subscriber::set_global_default};
 use tracing_bunyan_formatter::{BunyanFormattingLayer, JsonStorageLayer};
 use tracing_subscriber::{EnvFilter, Registry, fmt::MakeWriter, layer::SubscriberExt};
 
+use crate::error::attach_request_id;
+
 pub fn create_subscriber<S>(
     name: impl Into<String>,
     level: impl Into<String>,
@@ -52,7 +55,9 @@ pub fn with_request_id(router: Router) -> Router {
                     }
                 }
             }),
-        );
+        )
+        .layer(PropagateRequestIdLayer::new(x_request_id))
+        .layer(from_fn(attach_request_id));
 
     router.layer(service)
 }
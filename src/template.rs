@@ -0,0 +1,455 @@
+use std::collections::BTreeMap;
+use std::fmt;
+use std::str::FromStr;
+
+use crate::domain::{Email, is_valid_attribute_key};
+
+/// Stands in for a placeholder while Markdown is rendered. It is made of
+/// characters Markdown, URL encoding and the sanitizer all leave alone.
+const MARKER_START: &str = "TEMPLATEVARIABLE";
+const MARKER_END: &str = "END";
+
+/// Text with `{{ variable }}` placeholders, filled in for every recipient.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Template(Vec<Segment>);
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+enum Segment {
+    Literal(String),
+    Variable(Variable),
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Variable {
+    Name,
+    Email,
+    UnsubscribeUrl,
+    Attribute(String),
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, thiserror::Error)]
+pub enum TemplateError {
+    #[error("The `{{{{` at character {0} is never closed with `}}}}`.")]
+    Unclosed(usize),
+    #[error(
+        "`{0}` is not a template variable, use `name`, `email`, `unsubscribe_url` or \
+         `attributes.<name>`."
+    )]
+    UnknownVariable(String),
+}
+
+/// How values are escaped for the part of the email they go into.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Escape {
+    Html,
+    /// Control characters are dropped, so that values cannot add lines to a
+    /// subject.
+    Text,
+}
+
+/// Everything placeholders can refer to.
+#[derive(Debug, Clone)]
+pub struct Recipient {
+    pub email: Email,
+    pub name: String,
+    pub attributes: BTreeMap<String, String>,
+}
+
+impl Recipient {
+    /// Someone who is not on the list, such as the address of a test send.
+    pub fn unknown(email: Email) -> Self {
+        Self {
+            email,
+            name: String::new(),
+            attributes: BTreeMap::new(),
+        }
+    }
+}
+
+impl FromStr for Variable {
+    type Err = TemplateError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "name" => Ok(Variable::Name),
+            "email" => Ok(Variable::Email),
+            "unsubscribe_url" => Ok(Variable::UnsubscribeUrl),
+            _ => match s.strip_prefix("attributes.") {
+                Some(key) if is_valid_attribute_key(key) => Ok(Variable::Attribute(key.into())),
+                _ => Err(TemplateError::UnknownVariable(s.into())),
+            },
+        }
+    }
+}
+
+impl Variable {
+    fn render(
+        &self,
+        recipient: &Recipient,
+        unsubscribe_url: &str,
+        escape: Escape,
+        out: &mut String,
+    ) {
+        let value = match self {
+            Variable::Name => recipient.name.as_str(),
+            Variable::Email => recipient.email.as_ref(),
+            Variable::UnsubscribeUrl => unsubscribe_url,
+            Variable::Attribute(key) => recipient
+                .attributes
+                .get(key)
+                .map(String::as_str)
+                .unwrap_or_default(),
+        };
+
+        match escape {
+            Escape::Html => escape_html(value, out),
+            Escape::Text => out.extend(value.chars().filter(|c| !c.is_control())),
+        }
+    }
+
+    /// Stands for the variable in [`Template::to_keys`]. Values are escaped
+    /// differently for HTML, so they get a key of their own.
+    fn key(&self, escape: Escape) -> String {
+        match escape {
+            Escape::Text => self.to_string(),
+            Escape::Html => format!("{{{{ {} | html }}}}", self.name()),
+        }
+    }
+
+    fn name(&self) -> String {
+        match self {
+            Variable::Name => "name".into(),
+            Variable::Email => "email".into(),
+            Variable::UnsubscribeUrl => "unsubscribe_url".into(),
+            Variable::Attribute(key) => format!("attributes.{key}"),
+        }
+    }
+}
+
+impl fmt::Display for Variable {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{{{{ {} }}}}", self.name())
+    }
+}
+
+impl Template {
+    pub fn parse(source: &str) -> Result<Self, TemplateError> {
+        let mut segments = vec![];
+        let mut rest = source;
+
+        while let Some(start) = rest.find("{{") {
+            if start > 0 {
+                segments.push(Segment::Literal(rest[..start].into()));
+            }
+
+            let inner = &rest[start + 2..];
+            let Some(end) = inner.find("}}") else {
+                let offset = source.len() - rest.len() + start;
+                return Err(TemplateError::Unclosed(source[..offset].chars().count()));
+            };
+
+            segments.push(Segment::Variable(inner[..end].trim().parse()?));
+            rest = &inner[end + 2..];
+        }
+
+        if !rest.is_empty() {
+            segments.push(Segment::Literal(rest.into()));
+        }
+
+        Ok(Self(segments))
+    }
+
+    pub fn render(&self, recipient: &Recipient, unsubscribe_url: &str, escape: Escape) -> String {
+        let mut out = String::new();
+
+        for segment in &self.0 {
+            match segment {
+                Segment::Literal(literal) => out.push_str(literal),
+                Segment::Variable(variable) => {
+                    variable.render(recipient, unsubscribe_url, escape, &mut out)
+                }
+            }
+        }
+
+        out
+    }
+
+    /// The source with every placeholder replaced by its substitution key,
+    /// for backends that fill in emails for many recipients themselves.
+    ///
+    /// Keys look like placeholders, which literal text never contains, so
+    /// they cannot be mistaken for anything else.
+    pub fn to_keys(&self, escape: Escape) -> String {
+        let mut out = String::new();
+
+        for segment in &self.0 {
+            match segment {
+                Segment::Literal(literal) => out.push_str(literal),
+                Segment::Variable(variable) => out.push_str(&variable.key(escape)),
+            }
+        }
+
+        out
+    }
+
+    /// The value of every key in [`Template::to_keys`] for `recipient`.
+    pub fn substitutions(
+        &self,
+        recipient: &Recipient,
+        unsubscribe_url: &str,
+        escape: Escape,
+        substitutions: &mut BTreeMap<String, String>,
+    ) {
+        for segment in &self.0 {
+            if let Segment::Variable(variable) = segment {
+                let mut value = String::new();
+                variable.render(recipient, unsubscribe_url, escape, &mut value);
+                substitutions.insert(variable.key(escape), value);
+            }
+        }
+    }
+
+    /// The text around the placeholders, for pages that have no recipient
+    /// to fill them in for, such as the public archive.
+    pub fn without_variables(&self) -> String {
+        self.0
+            .iter()
+            .filter_map(|segment| match segment {
+                Segment::Literal(literal) => Some(literal.as_str()),
+                Segment::Variable(_) => None,
+            })
+            .collect()
+    }
+
+    /// The source with every placeholder replaced by a marker, for content
+    /// that goes through another renderer first.
+    pub fn to_markers(&self) -> String {
+        let mut out = String::new();
+        let mut n = 0;
+
+        for segment in &self.0 {
+            match segment {
+                Segment::Literal(literal) => out.push_str(literal),
+                Segment::Variable(_) => {
+                    out.push_str(&format!("{MARKER_START}{n}{MARKER_END}"));
+                    n += 1;
+                }
+            }
+        }
+
+        out
+    }
+
+    /// Puts the placeholders back into the output of [`Template::to_markers`].
+    pub fn from_markers(&self, rendered: &str) -> String {
+        let variables = self.0.iter().filter_map(|segment| match segment {
+            Segment::Variable(variable) => Some(variable),
+            Segment::Literal(_) => None,
+        });
+
+        // Highest numbers first, so that `1` does not match the start of `10`.
+        let mut out = rendered.to_string();
+        for (n, variable) in variables.enumerate().collect::<Vec<_>>().into_iter().rev() {
+            out = out.replace(
+                &format!("{MARKER_START}{n}{MARKER_END}"),
+                &variable.to_string(),
+            );
+        }
+
+        out
+    }
+}
+
+/// Appends `value` to `out`, escaping the characters that are special in
+/// HTML and XML.
+pub fn escape_html(value: &str, out: &mut String) {
+    for c in value.chars() {
+        match c {
+            '&' => out.push_str("&amp;"),
+            '<' => out.push_str("&lt;"),
+            '>' => out.push_str("&gt;"),
+            '"' => out.push_str("&quot;"),
+            '\'' => out.push_str("&#39;"),
+            c => out.push(c),
+        }
+    }
+}
+
+/// The subject and both bodies of an issue, parsed once and rendered for
+/// every recipient.
+#[derive(Debug, Clone)]
+pub struct NewsletterTemplate {
+    pub subject: Template,
+    pub text_content: Template,
+    pub html_content: Template,
+}
+
+/// A [`NewsletterTemplate`] filled in for one recipient.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Personalized {
+    pub subject: String,
+    pub text_content: String,
+    pub html_content: String,
+}
+
+impl NewsletterTemplate {
+    pub fn parse(
+        subject: &str,
+        text_content: &str,
+        html_content: &str,
+    ) -> Result<Self, TemplateError> {
+        Ok(Self {
+            subject: Template::parse(subject)?,
+            text_content: Template::parse(text_content)?,
+            html_content: Template::parse(html_content)?,
+        })
+    }
+
+    /// The newsletter with substitution keys in place of its placeholders,
+    /// as [`NewsletterTemplate::substitutions`] fills them in.
+    pub fn to_keys(&self) -> Personalized {
+        Personalized {
+            subject: self.subject.to_keys(Escape::Text),
+            text_content: self.text_content.to_keys(Escape::Text),
+            html_content: self.html_content.to_keys(Escape::Html),
+        }
+    }
+
+    pub fn substitutions(
+        &self,
+        recipient: &Recipient,
+        unsubscribe_url: &str,
+    ) -> BTreeMap<String, String> {
+        let mut substitutions = BTreeMap::new();
+        self.subject
+            .substitutions(recipient, unsubscribe_url, Escape::Text, &mut substitutions);
+        self.text_content.substitutions(
+            recipient,
+            unsubscribe_url,
+            Escape::Text,
+            &mut substitutions,
+        );
+        self.html_content.substitutions(
+            recipient,
+            unsubscribe_url,
+            Escape::Html,
+            &mut substitutions,
+        );
+        substitutions
+    }
+
+    pub fn render(&self, recipient: &Recipient, unsubscribe_url: &str) -> Personalized {
+        Personalized {
+            subject: self
+                .subject
+                .render(recipient, unsubscribe_url, Escape::Text),
+            text_content: self
+                .text_content
+                .render(recipient, unsubscribe_url, Escape::Text),
+            html_content: self
+                .html_content
+                .render(recipient, unsubscribe_url, Escape::Html),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use claims::{assert_err_eq, assert_ok};
+
+    use super::{Escape, NewsletterTemplate, Recipient, Template, TemplateError};
+    use crate::domain::Email;
+
+    fn recipient() -> Recipient {
+        Recipient {
+            email: Email::try_from("main@lzzzt.cc".to_string()).unwrap(),
+            name: "<Lzzzt>".into(),
+            attributes: [("company".to_string(), "A & B".to_string())].into(),
+        }
+    }
+
+    #[test]
+    fn variables_are_filled_in() {
+        let template =
+            Template::parse("Hi {{name}} of {{ attributes.company }}, {{ attributes.city }}!")
+                .unwrap();
+
+        assert_eq!(
+            template.render(&recipient(), "", Escape::Text),
+            "Hi <Lzzzt> of A & B, !"
+        );
+        assert_eq!(
+            template.render(&recipient(), "", Escape::Html),
+            "Hi &lt;Lzzzt&gt; of A &amp; B, !"
+        );
+    }
+
+    #[test]
+    fn text_values_cannot_break_lines() {
+        let mut recipient = recipient();
+        recipient.name = "Lzzzt\r\nBcc: someone".into();
+
+        let template = Template::parse("Hi {{ name }}").unwrap();
+
+        assert_eq!(
+            template.render(&recipient, "", Escape::Text),
+            "Hi LzzztBcc: someone"
+        );
+    }
+
+    #[test]
+    fn unknown_variables_are_rejected() {
+        assert_err_eq!(
+            Template::parse("Hi {{ nmae }}"),
+            TemplateError::UnknownVariable("nmae".into())
+        );
+        assert_err_eq!(
+            Template::parse("{{ attributes.Bad }}"),
+            TemplateError::UnknownVariable("attributes.Bad".into())
+        );
+        assert_ok!(Template::parse("Hi {{ attributes.first_name }}"));
+    }
+
+    #[test]
+    fn unclosed_placeholders_are_rejected() {
+        assert_err_eq!(Template::parse("Ça va {{ name"), TemplateError::Unclosed(6));
+    }
+
+    #[test]
+    fn substitutions_fill_in_keys_like_render() {
+        let newsletter = NewsletterTemplate::parse(
+            "Hi {{ name }}",
+            "{{ name }}, leave at {{ unsubscribe_url }}",
+            "<p>{{ name }} of {{ attributes.company }}</p>",
+        )
+        .unwrap();
+
+        let keyed = newsletter.to_keys();
+        assert_eq!(
+            keyed.html_content,
+            "<p>{{ name | html }} of {{ attributes.company | html }}</p>"
+        );
+
+        let substitutions = newsletter.substitutions(&recipient(), "https://lzzzt.cc/u");
+        let fill_in = |keyed: &str| {
+            substitutions
+                .iter()
+                .fold(keyed.to_string(), |out, (key, value)| {
+                    out.replace(key, value)
+                })
+        };
+        let rendered = newsletter.render(&recipient(), "https://lzzzt.cc/u");
+
+        assert_eq!(fill_in(&keyed.subject), rendered.subject);
+        assert_eq!(fill_in(&keyed.text_content), rendered.text_content);
+        assert_eq!(fill_in(&keyed.html_content), rendered.html_content);
+    }
+
+    #[test]
+    fn markers_round_trip() {
+        let source = "{{ name }} ".repeat(11) + "{{ unsubscribe_url }}";
+        let template = Template::parse(&source).unwrap();
+
+        assert_eq!(template.from_markers(&template.to_markers()), source);
+    }
+}
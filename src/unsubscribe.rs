@@ -0,0 +1,122 @@
+use base64::Engine;
+use base64::engine::general_purpose::URL_SAFE_NO_PAD;
+use hmac::{Hmac, Mac};
+use secrecy::{ExposeSecret, SecretString};
+use sha2::Sha256;
+
+use crate::domain::Email;
+
+type HmacSha256 = Hmac<Sha256>;
+
+/// Builds and verifies the signed, per-subscriber links used to leave the list.
+///
+/// A token is `base64(email).base64(hmac(email))`, so it can be checked
+/// without a database round trip and cannot be forged for other addresses.
+#[derive(Clone)]
+pub struct UnsubscribeLinks {
+    base_url: String,
+    secret: SecretString,
+}
+
+impl UnsubscribeLinks {
+    pub fn new(base_url: impl Into<String>, secret: SecretString) -> Self {
+        Self {
+            base_url: base_url.into(),
+            secret,
+        }
+    }
+
+    pub fn url_for(&self, email: &Email) -> String {
+        format!(
+            "{}/subscriptions/unsubscribe?token={}",
+            self.base_url,
+            self.token_for(email)
+        )
+    }
+
+    pub fn token_for(&self, email: &Email) -> String {
+        let signature = self.mac(email.as_ref()).finalize().into_bytes();
+
+        format!(
+            "{}.{}",
+            URL_SAFE_NO_PAD.encode(email.as_ref()),
+            URL_SAFE_NO_PAD.encode(signature)
+        )
+    }
+
+    /// Returns the email address the token was issued for, if the signature is valid.
+    pub fn verify(&self, token: &str) -> Option<String> {
+        let (email, signature) = token.split_once('.')?;
+
+        let email = String::from_utf8(URL_SAFE_NO_PAD.decode(email).ok()?).ok()?;
+        let signature = URL_SAFE_NO_PAD.decode(signature).ok()?;
+
+        self.mac(&email).verify_slice(&signature).ok()?;
+
+        Some(email)
+    }
+
+    fn mac(&self, email: &str) -> HmacSha256 {
+        let mut mac = HmacSha256::new_from_slice(self.secret.expose_secret().as_bytes())
+            .expect("HMAC can take key of any size");
+        mac.update(email.as_bytes());
+        mac
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use claims::{assert_none, assert_some_eq};
+    use secrecy::SecretString;
+
+    use super::UnsubscribeLinks;
+    use crate::domain::Email;
+
+    fn links(secret: &str) -> UnsubscribeLinks {
+        UnsubscribeLinks::new("http://127.0.0.1", SecretString::from(secret))
+    }
+
+    fn email() -> Email {
+        Email::try_from("main@lzzzt.cc".to_string()).unwrap()
+    }
+
+    #[test]
+    fn a_token_is_verified_back_to_its_email() {
+        let links = links("secret");
+        let token = links.token_for(&email());
+
+        assert_some_eq!(links.verify(&token), "main@lzzzt.cc");
+    }
+
+    #[test]
+    fn a_token_signed_with_another_secret_is_rejected() {
+        let token = links("another-secret").token_for(&email());
+
+        assert_none!(links("secret").verify(&token));
+    }
+
+    #[test]
+    fn a_token_with_a_swapped_email_is_rejected() {
+        let links = links("secret");
+        let token = links.token_for(&email());
+        let (_, signature) = token.split_once('.').unwrap();
+
+        let forged = format!("{}.{signature}", base64_email("other@lzzzt.cc"));
+
+        assert_none!(links.verify(&forged));
+    }
+
+    #[test]
+    fn malformed_tokens_are_rejected() {
+        let links = links("secret");
+
+        for token in ["", ".", "no-dot", "!!!.!!!"] {
+            assert_none!(links.verify(token));
+        }
+    }
+
+    fn base64_email(email: &str) -> String {
+        use base64::Engine;
+        base64::engine::general_purpose::URL_SAFE_NO_PAD.encode(email)
+    }
+}
@@ -0,0 +1,342 @@
+use reqwest::Method;
+use wiremock::matchers::{any, method, path};
+use wiremock::{Mock, ResponseTemplate};
+
+use crate::TestApp;
+
+fn draft() -> serde_json::Value {
+    serde_json::json!({
+        "title": "Draft title",
+        "text_content": "Draft body as plain text",
+        "html_content": "<p>Draft body as HTML</p>",
+    })
+}
+
+async fn create_draft(app: &TestApp) -> serde_json::Value {
+    let response = app
+        .admin_request(Method::POST, "/admin/drafts")
+        .json(&draft())
+        .send()
+        .await
+        .expect("Failed to send request.");
+    assert_eq!(201, response.status().as_u16());
+
+    response.json().await.unwrap()
+}
+
+/// An app that lets drafts be sent as a test to `me@lzzzt.cc`.
+async fn test_app() -> TestApp {
+    TestApp::with_config(|c| {
+        c.app_config.test_recipients = vec!["me@lzzzt.cc".to_string().try_into().unwrap()];
+    })
+    .await
+}
+
+#[tokio::test]
+async fn drafts_can_be_created_read_and_listed() {
+    let app = TestApp::new().await;
+
+    let created = create_draft(&app).await;
+    let draft_id = created["draft_id"].as_str().unwrap();
+    assert_eq!(created["title"], "Draft title");
+
+    let response = app
+        .admin_request(Method::GET, &format!("/admin/drafts/{draft_id}"))
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(200, response.status().as_u16());
+    let fetched: serde_json::Value = response.json().await.unwrap();
+    assert_eq!(fetched, created);
+
+    let listed: serde_json::Value = app
+        .admin_request(Method::GET, "/admin/drafts")
+        .send()
+        .await
+        .unwrap()
+        .json()
+        .await
+        .unwrap();
+    assert_eq!(listed, serde_json::json!([created]));
+}
+
+#[tokio::test]
+async fn drafts_can_be_updated() {
+    let app = TestApp::new().await;
+    let created = create_draft(&app).await;
+    let draft_id = created["draft_id"].as_str().unwrap();
+
+    let mut changes = draft();
+    changes["title"] = "Better title".into();
+    let response = app
+        .admin_request(Method::PUT, &format!("/admin/drafts/{draft_id}"))
+        .json(&changes)
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(200, response.status().as_u16());
+
+    let updated: serde_json::Value = response.json().await.unwrap();
+    assert_eq!(updated["title"], "Better title");
+    assert_eq!(updated["created_at"], created["created_at"]);
+    assert_ne!(updated["updated_at"], created["updated_at"]);
+}
+
+#[tokio::test]
+async fn drafts_can_be_deleted() {
+    let app = TestApp::new().await;
+    let created = create_draft(&app).await;
+    let path = format!("/admin/drafts/{}", created["draft_id"].as_str().unwrap());
+
+    let response = app
+        .admin_request(Method::DELETE, &path)
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(204, response.status().as_u16());
+
+    let response = app.admin_request(Method::GET, &path).send().await.unwrap();
+    assert_eq!(404, response.status().as_u16());
+    let response = app
+        .admin_request(Method::DELETE, &path)
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(404, response.status().as_u16());
+}
+
+#[tokio::test]
+async fn markdown_drafts_keep_their_source() {
+    let app = TestApp::new().await;
+
+    let response = app
+        .admin_request(Method::POST, "/admin/drafts")
+        .json(&serde_json::json!({
+            "title": "Draft title",
+            "markdown_content": "Hello *there*",
+        }))
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(201, response.status().as_u16());
+
+    let created: serde_json::Value = response.json().await.unwrap();
+    assert_eq!(created["markdown_content"], "Hello *there*");
+    assert_eq!(created["text_content"], "Hello there");
+    assert!(
+        created["html_content"]
+            .as_str()
+            .unwrap()
+            .contains("<em>there</em>")
+    );
+
+    // Switching back to hand-written versions drops the source.
+    let draft_id = created["draft_id"].as_str().unwrap();
+    let updated: serde_json::Value = app
+        .admin_request(Method::PUT, &format!("/admin/drafts/{draft_id}"))
+        .json(&draft())
+        .send()
+        .await
+        .unwrap()
+        .json()
+        .await
+        .unwrap();
+    assert!(updated["markdown_content"].is_null());
+}
+
+#[tokio::test]
+async fn drafts_returns_422_for_empty_fields() {
+    let app = TestApp::new().await;
+
+    let mut body = draft();
+    body["text_content"] = "".into();
+    let response = app
+        .admin_request(Method::POST, "/admin/drafts")
+        .json(&body)
+        .send()
+        .await
+        .unwrap();
+
+    assert_eq!(422, response.status().as_u16());
+    let problem: serde_json::Value = response.json().await.unwrap();
+    assert_eq!(problem["errors"][0]["field"], "text_content");
+}
+
+#[tokio::test]
+async fn unknown_drafts_are_not_found() {
+    let app = TestApp::new().await;
+    let path = format!("/admin/drafts/{}", uuid::Uuid::new_v4());
+
+    let response = app
+        .admin_request(Method::PUT, &path)
+        .json(&draft())
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(404, response.status().as_u16());
+
+    let response = app
+        .admin_request(Method::GET, &format!("{path}/preview"))
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(404, response.status().as_u16());
+}
+
+#[tokio::test]
+async fn preview_matches_the_email_sent_as_a_test() {
+    let app = test_app().await;
+    let created = create_draft(&app).await;
+    let draft_id = created["draft_id"].as_str().unwrap();
+
+    Mock::given(path("/v3/mail/send"))
+        .and(method("POST"))
+        .respond_with(ResponseTemplate::new(200))
+        .expect(1)
+        .mount(&app.email_server)
+        .await;
+
+    let preview: serde_json::Value = app
+        .admin_request(
+            Method::GET,
+            &format!("/admin/drafts/{draft_id}/preview?to=me%40lzzzt.cc"),
+        )
+        .send()
+        .await
+        .unwrap()
+        .json()
+        .await
+        .unwrap();
+    assert_eq!(preview["to"], "me@lzzzt.cc");
+    assert_eq!(preview["subject"], "Draft title");
+
+    let response = app
+        .admin_request(Method::POST, &format!("/admin/drafts/{draft_id}/test"))
+        .json(&serde_json::json!({ "to": "me@lzzzt.cc" }))
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(204, response.status().as_u16());
+
+    let request = &app.email_server.received_requests().await.unwrap()[0];
+    let sent: serde_json::Value = serde_json::from_slice(&request.body).unwrap();
+    let personalization = &sent["personalizations"][0];
+    assert_eq!(personalization["to"][0]["email"], preview["to"]);
+    assert_eq!(personalization["headers"], preview["headers"]);
+    assert_eq!(sent["from"]["email"], preview["from"]);
+    assert_eq!(sent["subject"], preview["subject"]);
+    assert_eq!(sent["content"][0]["value"], preview["text_content"]);
+    assert_eq!(sent["content"][1]["value"], preview["html_content"]);
+}
+
+#[tokio::test]
+async fn test_emails_are_not_queued() {
+    let app = test_app().await;
+    let created = create_draft(&app).await;
+    let draft_id = created["draft_id"].as_str().unwrap();
+
+    Mock::given(path("/v3/mail/send"))
+        .respond_with(ResponseTemplate::new(200))
+        .expect(1)
+        .mount(&app.email_server)
+        .await;
+
+    app.admin_request(Method::POST, &format!("/admin/drafts/{draft_id}/test"))
+        .json(&serde_json::json!({ "to": "me@lzzzt.cc" }))
+        .send()
+        .await
+        .unwrap()
+        .error_for_status()
+        .unwrap();
+
+    let queued = sqlx::query_scalar!(r#"SELECT COUNT(*) AS "count!" FROM issue_delivery_queue"#)
+        .fetch_one(&app.conn_pool)
+        .await
+        .unwrap();
+    assert_eq!(queued, 0);
+}
+
+#[tokio::test]
+async fn test_emails_are_only_sent_to_test_recipients() {
+    let app = test_app().await;
+    let created = create_draft(&app).await;
+    let draft_id = created["draft_id"].as_str().unwrap();
+
+    Mock::given(any())
+        .respond_with(ResponseTemplate::new(200))
+        .expect(0)
+        .mount(&app.email_server)
+        .await;
+
+    let response = app
+        .admin_request(Method::POST, &format!("/admin/drafts/{draft_id}/test"))
+        .json(&serde_json::json!({ "to": "someone@else.example" }))
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(422, response.status().as_u16());
+
+    let problem: serde_json::Value = response.json().await.unwrap();
+    assert_eq!(problem["errors"][0]["field"], "to");
+    assert_eq!(problem["errors"][0]["code"], "not_a_test_recipient");
+}
+
+#[tokio::test]
+async fn test_emails_need_a_valid_recipient() {
+    let app = TestApp::new().await;
+    let created = create_draft(&app).await;
+    let draft_id = created["draft_id"].as_str().unwrap();
+
+    let response = app
+        .admin_request(Method::POST, &format!("/admin/drafts/{draft_id}/test"))
+        .json(&serde_json::json!({ "to": "not-an-email" }))
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(422, response.status().as_u16());
+
+    let problem: serde_json::Value = response.json().await.unwrap();
+    assert_eq!(problem["errors"][0]["field"], "to");
+}
+
+#[tokio::test]
+async fn drafts_require_authentication() {
+    let app = TestApp::new().await;
+
+    let response = reqwest::get(format!("{}/admin/drafts", app.address))
+        .await
+        .unwrap();
+
+    assert_eq!(401, response.status().as_u16());
+}
+
+#[tokio::test]
+async fn drafts_with_invalid_templates_are_rejected() {
+    let app = TestApp::new().await;
+
+    let response = app
+        .admin_request(Method::POST, "/admin/drafts")
+        .json(&serde_json::json!({
+            "title": "Hi {{ name",
+            "markdown_content": "Hi {{ nmae }}",
+        }))
+        .send()
+        .await
+        .unwrap();
+
+    assert_eq!(422, response.status().as_u16());
+    let problem: serde_json::Value = response.json().await.unwrap();
+    let errors: Vec<_> = problem["errors"]
+        .as_array()
+        .unwrap()
+        .iter()
+        .map(|e| (e["field"].as_str().unwrap(), e["code"].as_str().unwrap()))
+        .collect();
+    assert_eq!(
+        errors,
+        [
+            ("title", "invalid_template"),
+            ("markdown_content", "invalid_template")
+        ]
+    );
+}
@@ -0,0 +1,558 @@
+use wiremock::matchers::{any, method, path};
+use wiremock::{Mock, ResponseTemplate};
+use zero2prod::config::EmailProvider;
+
+use crate::{ConfirmationLinks, TestApp};
+
+async fn create_unconfirmed_subscriber(app: &TestApp, email: &str) -> ConfirmationLinks {
+    let _mock_guard = Mock::given(path("/v3/mail/send"))
+        .and(method("POST"))
+        .respond_with(ResponseTemplate::new(200))
+        .named("Create unconfirmed subscriber")
+        .expect(1)
+        .mount_as_scoped(&app.email_server)
+        .await;
+
+    app.post_subscriptions(format!("name=lzzzt&email={}", crate::percent_encode(email)))
+        .await
+        .error_for_status()
+        .unwrap();
+
+    let email_request = app
+        .email_server
+        .received_requests()
+        .await
+        .unwrap()
+        .pop()
+        .unwrap();
+
+    app.get_confirmation_links(&email_request)
+}
+
+pub async fn create_confirmed_subscriber(app: &TestApp, email: &str) {
+    let confirmation_links = create_unconfirmed_subscriber(app, email).await;
+
+    reqwest::get(confirmation_links.html)
+        .await
+        .unwrap()
+        .error_for_status()
+        .unwrap();
+}
+
+pub fn newsletter() -> serde_json::Value {
+    serde_json::json!({
+        "title": "Newsletter title",
+        "text_content": "Newsletter body as plain text",
+        "html_content": "<p>Newsletter body as HTML</p>",
+    })
+}
+
+pub async fn get_issue_report(app: &TestApp, issue_id: &str) -> reqwest::Response {
+    reqwest::Client::new()
+        .get(format!("{}/admin/newsletters/{issue_id}", app.address))
+        .basic_auth(&app.test_user.username, Some(&app.test_user.password))
+        .send()
+        .await
+        .expect("Failed to send request.")
+}
+
+async fn publish(app: &TestApp) -> serde_json::Value {
+    let response = app.post_newsletters(&newsletter()).await;
+    assert_eq!(202, response.status().as_u16());
+
+    response.json().await.unwrap()
+}
+
+#[tokio::test]
+async fn newsletters_are_not_delivered_to_unconfirmed_subscribers() {
+    let app = TestApp::new().await;
+    create_unconfirmed_subscriber(&app, "main@lzzzt.cc").await;
+
+    Mock::given(any())
+        .respond_with(ResponseTemplate::new(200))
+        .expect(0)
+        .mount(&app.email_server)
+        .await;
+
+    let summary = publish(&app).await;
+    app.dispatch_all_pending_emails().await;
+
+    assert_eq!(summary["queued"], 0);
+}
+
+#[tokio::test]
+async fn newsletters_are_delivered_to_confirmed_subscribers() {
+    let app = TestApp::new().await;
+    create_confirmed_subscriber(&app, "main@lzzzt.cc").await;
+    create_confirmed_subscriber(&app, "second@lzzzt.cc").await;
+
+    // Both go out in a single request, one personalization each.
+    Mock::given(path("/v3/mail/send"))
+        .and(method("POST"))
+        .respond_with(ResponseTemplate::new(200))
+        .expect(1)
+        .mount(&app.email_server)
+        .await;
+
+    let summary = publish(&app).await;
+    assert_eq!(summary["queued"], 2);
+
+    app.dispatch_all_pending_emails().await;
+
+    let request = app
+        .email_server
+        .received_requests()
+        .await
+        .unwrap()
+        .pop()
+        .unwrap();
+    let body: serde_json::Value = serde_json::from_slice(&request.body).unwrap();
+    let mut recipients: Vec<_> = body["personalizations"]
+        .as_array()
+        .unwrap()
+        .iter()
+        .map(|p| p["to"][0]["email"].as_str().unwrap())
+        .collect();
+    recipients.sort();
+    assert_eq!(recipients, ["main@lzzzt.cc", "second@lzzzt.cc"]);
+
+    let issue_id = summary["issue_id"].as_str().unwrap();
+    let report: serde_json::Value = get_issue_report(&app, issue_id).await.json().await.unwrap();
+    assert_eq!(report["title"], "Newsletter title");
+    assert_eq!(report["pending"], 0);
+    assert_eq!(report["delivered"], 2);
+    assert_eq!(report["dead"], 0);
+}
+
+#[tokio::test]
+async fn publishing_only_queues_the_issue() {
+    let app = TestApp::new().await;
+    create_confirmed_subscriber(&app, "main@lzzzt.cc").await;
+
+    Mock::given(any())
+        .respond_with(ResponseTemplate::new(200))
+        .expect(0)
+        .mount(&app.email_server)
+        .await;
+
+    let summary = publish(&app).await;
+
+    let issue_id = summary["issue_id"].as_str().unwrap();
+    let report: serde_json::Value = get_issue_report(&app, issue_id).await.json().await.unwrap();
+    assert_eq!(report["pending"], 1);
+    assert_eq!(report["delivered"], 0);
+}
+
+#[tokio::test]
+async fn deliveries_to_subscribers_who_left_since_publishing_are_skipped() {
+    let app = TestApp::new().await;
+    create_confirmed_subscriber(&app, "main@lzzzt.cc").await;
+
+    Mock::given(any())
+        .respond_with(ResponseTemplate::new(200))
+        .expect(0)
+        .mount(&app.email_server)
+        .await;
+
+    let summary = publish(&app).await;
+    sqlx::query!("UPDATE subscriptions SET status = 'unsubscribed'")
+        .execute(&app.conn_pool)
+        .await
+        .unwrap();
+    app.dispatch_all_pending_emails().await;
+
+    let issue_id = summary["issue_id"].as_str().unwrap();
+    let report: serde_json::Value = get_issue_report(&app, issue_id).await.json().await.unwrap();
+    assert_eq!(report["pending"], 0);
+    assert_eq!(report["delivered"], 0);
+    assert_eq!(report["skipped"], 1);
+}
+
+#[tokio::test]
+async fn workers_claim_no_more_tasks_than_a_single_request_carries() {
+    // Postmark has no batch API, so each request carries a single recipient.
+    let app =
+        TestApp::with_config(|c| c.email_client_config.provider = EmailProvider::Postmark).await;
+
+    Mock::given(path("/email"))
+        .respond_with(ResponseTemplate::new(200))
+        .mount(&app.email_server)
+        .await;
+    for email in ["main@lzzzt.cc", "second@lzzzt.cc"] {
+        app.post_subscriptions(format!("name=lzzzt&email={}", crate::percent_encode(email)))
+            .await
+            .error_for_status()
+            .unwrap();
+    }
+    sqlx::query!("UPDATE subscriptions SET status = 'confirmed'")
+        .execute(&app.conn_pool)
+        .await
+        .unwrap();
+
+    let summary = publish(&app).await;
+    app.delivery_worker.try_execute_task().await.unwrap();
+
+    let issue_id = summary["issue_id"].as_str().unwrap();
+    let report: serde_json::Value = get_issue_report(&app, issue_id).await.json().await.unwrap();
+    assert_eq!(report["pending"], 1);
+    assert_eq!(report["delivered"], 1);
+}
+
+#[tokio::test]
+async fn failed_deliveries_are_retried_later() {
+    // One recipient per request, so that only one of them fails.
+    let app = TestApp::with_config(|c| c.delivery_config.batch_size = 1).await;
+    create_confirmed_subscriber(&app, "main@lzzzt.cc").await;
+    create_confirmed_subscriber(&app, "second@lzzzt.cc").await;
+
+    Mock::given(path("/v3/mail/send"))
+        .respond_with(ResponseTemplate::new(500))
+        .up_to_n_times(1)
+        .mount(&app.email_server)
+        .await;
+    Mock::given(path("/v3/mail/send"))
+        .respond_with(ResponseTemplate::new(200))
+        .mount(&app.email_server)
+        .await;
+
+    let summary = publish(&app).await;
+    app.dispatch_all_pending_emails().await;
+
+    let retry = sqlx::query!(
+        "SELECT n_attempts, execute_after > now() AS \"backing_off!\", last_error \
+         FROM issue_delivery_queue WHERE status = 'pending'"
+    )
+    .fetch_one(&app.conn_pool)
+    .await
+    .expect("Failed to fetch the pending delivery.");
+    assert_eq!(retry.n_attempts, 1);
+    assert!(retry.backing_off);
+    assert!(retry.last_error.is_some());
+
+    let issue_id = summary["issue_id"].as_str().unwrap();
+    let report: serde_json::Value = get_issue_report(&app, issue_id).await.json().await.unwrap();
+    assert_eq!(report["pending"], 1);
+    assert_eq!(report["delivered"], 1);
+}
+
+#[tokio::test]
+async fn deliveries_are_dead_lettered_after_the_last_attempt() {
+    let app = TestApp::with_config(|c| {
+        c.delivery_config.max_attempts = 3;
+        c.delivery_config.base_backoff_ms = 0;
+    })
+    .await;
+    create_confirmed_subscriber(&app, "main@lzzzt.cc").await;
+
+    Mock::given(path("/v3/mail/send"))
+        .respond_with(ResponseTemplate::new(500))
+        .expect(3)
+        .mount(&app.email_server)
+        .await;
+
+    let summary = publish(&app).await;
+    app.dispatch_all_pending_emails().await;
+
+    let issue_id = summary["issue_id"].as_str().unwrap();
+    let report: serde_json::Value = get_issue_report(&app, issue_id).await.json().await.unwrap();
+    assert_eq!(report["pending"], 0);
+    assert_eq!(report["dead"], 1);
+    assert_eq!(
+        report["dead_letters"][0]["subscriber_email"],
+        "main@lzzzt.cc"
+    );
+    assert_eq!(report["dead_letters"][0]["n_attempts"], 3);
+    assert!(report["dead_letters"][0]["last_error"].is_string());
+}
+
+#[tokio::test]
+async fn unknown_issues_are_not_found() {
+    let app = TestApp::new().await;
+
+    let response = get_issue_report(&app, &uuid::Uuid::new_v4().to_string()).await;
+    assert_eq!(404, response.status().as_u16());
+
+    let response = get_issue_report(&app, "not-a-uuid").await;
+    assert_eq!(400, response.status().as_u16());
+}
+
+#[tokio::test]
+async fn newsletters_returns_422_for_empty_fields() {
+    let app = TestApp::new().await;
+
+    let response = app
+        .post_newsletters(&serde_json::json!({
+            "title": "",
+            "text_content": "Newsletter body as plain text",
+            "html_content": " ",
+        }))
+        .await;
+
+    assert_eq!(422, response.status().as_u16());
+    let problem: serde_json::Value = response.json().await.unwrap();
+    let fields: Vec<_> = problem["errors"]
+        .as_array()
+        .unwrap()
+        .iter()
+        .map(|e| e["field"].as_str().unwrap())
+        .collect();
+    assert_eq!(fields, ["title", "html_content"]);
+}
+
+#[tokio::test]
+async fn newsletters_returns_422_for_missing_fields() {
+    let app = TestApp::new().await;
+
+    let response = app
+        .post_newsletters(&serde_json::json!({ "title": "Newsletter title" }))
+        .await;
+
+    assert_eq!(422, response.status().as_u16());
+}
+
+#[tokio::test]
+async fn requests_missing_authorization_are_rejected() {
+    let app = TestApp::new().await;
+
+    let response = reqwest::Client::new()
+        .post(format!("{}/admin/newsletters", app.address))
+        .json(&newsletter())
+        .send()
+        .await
+        .expect("Failed to send request.");
+
+    assert_eq!(401, response.status().as_u16());
+    assert_eq!(
+        r#"Basic realm="admin""#,
+        response.headers()["WWW-Authenticate"]
+    );
+    let problem: serde_json::Value = response.json().await.unwrap();
+    assert_eq!(problem["code"], "unauthenticated");
+    assert!(problem["request_id"].is_string());
+}
+
+#[tokio::test]
+async fn unknown_users_are_rejected() {
+    let app = TestApp::new().await;
+
+    let response = reqwest::Client::new()
+        .post(format!("{}/admin/newsletters", app.address))
+        .basic_auth(uuid::Uuid::new_v4().to_string(), Some("password"))
+        .json(&newsletter())
+        .send()
+        .await
+        .expect("Failed to send request.");
+
+    assert_eq!(401, response.status().as_u16());
+}
+
+#[tokio::test]
+async fn invalid_passwords_are_rejected() {
+    let app = TestApp::new().await;
+
+    let response = reqwest::Client::new()
+        .post(format!("{}/admin/newsletters", app.address))
+        .basic_auth(&app.test_user.username, Some("wrong password"))
+        .json(&newsletter())
+        .send()
+        .await
+        .expect("Failed to send request.");
+
+    assert_eq!(401, response.status().as_u16());
+}
+
+#[tokio::test]
+async fn markdown_newsletters_are_delivered_as_html_and_text() {
+    let app = TestApp::new().await;
+    create_confirmed_subscriber(&app, "main@lzzzt.cc").await;
+
+    Mock::given(path("/v3/mail/send"))
+        .and(method("POST"))
+        .respond_with(ResponseTemplate::new(200))
+        .expect(1)
+        .mount(&app.email_server)
+        .await;
+
+    let response = app
+        .post_newsletters(&serde_json::json!({
+            "title": "Newsletter title",
+            "markdown_content": "Read **[the post](https://lzzzt.cc/post)**.",
+        }))
+        .await;
+    assert_eq!(202, response.status().as_u16());
+    app.dispatch_all_pending_emails().await;
+
+    let request = app
+        .email_server
+        .received_requests()
+        .await
+        .unwrap()
+        .pop()
+        .unwrap();
+    let body: serde_json::Value = serde_json::from_slice(&request.body).unwrap();
+    assert_eq!(
+        body["content"][0]["value"],
+        "Read the post [1].\n\n[1] https://lzzzt.cc/post"
+    );
+    let html = body["content"][1]["value"].as_str().unwrap();
+    assert!(html.contains("<strong><a href=\"https://lzzzt.cc/post\""));
+}
+
+#[tokio::test]
+async fn markdown_cannot_be_combined_with_other_versions() {
+    let app = TestApp::new().await;
+
+    let mut body = newsletter();
+    body["markdown_content"] = "# Title".into();
+    let response = app.post_newsletters(&body).await;
+
+    assert_eq!(422, response.status().as_u16());
+    let problem: serde_json::Value = response.json().await.unwrap();
+    assert_eq!(problem["errors"][0]["field"], "markdown_content");
+    assert_eq!(problem["errors"][0]["code"], "conflict");
+}
+
+#[tokio::test]
+async fn newsletters_are_personalized_for_every_subscriber() {
+    let app = TestApp::new().await;
+
+    Mock::given(path("/v3/mail/send"))
+        .respond_with(ResponseTemplate::new(200))
+        .mount(&app.email_server)
+        .await;
+
+    app.post_subscriptions_json(&serde_json::json!({
+        "name": "Lzzzt's",
+        "email": "main@lzzzt.cc",
+        "attributes": { "company": "<A & B>" },
+    }))
+    .await
+    .error_for_status()
+    .unwrap();
+    let confirmation = app
+        .email_server
+        .received_requests()
+        .await
+        .unwrap()
+        .pop()
+        .unwrap();
+    reqwest::get(app.get_confirmation_links(&confirmation).html)
+        .await
+        .unwrap()
+        .error_for_status()
+        .unwrap();
+
+    let response = app
+        .post_newsletters(&serde_json::json!({
+            "title": "News for {{ name }}",
+            "markdown_content": "Hi {{ name }} from {{ attributes.company }}! \
+                                 [Leave]({{unsubscribe_url}})",
+        }))
+        .await;
+    assert_eq!(202, response.status().as_u16());
+    app.dispatch_all_pending_emails().await;
+
+    let request = app
+        .email_server
+        .received_requests()
+        .await
+        .unwrap()
+        .pop()
+        .unwrap();
+    let body: serde_json::Value = serde_json::from_slice(&request.body).unwrap();
+    let unsubscribe_url = body["personalizations"][0]["headers"]["List-Unsubscribe"]
+        .as_str()
+        .unwrap()
+        .trim_matches(['<', '>']);
+
+    let (subject, text, html) = personalized(&body, 0);
+    assert_eq!(subject, "News for Lzzzt's");
+    assert!(text.starts_with("Hi Lzzzt's from <A & B>! Leave [1]"));
+    assert!(text.contains(unsubscribe_url));
+    assert!(html.contains("Hi Lzzzt&#39;s from &lt;A &amp; B&gt;!"));
+    assert!(html.contains(&format!("href=\"{unsubscribe_url}\"")));
+}
+
+#[tokio::test]
+async fn batched_newsletters_are_filled_in_for_each_subscriber() {
+    let app = TestApp::new().await;
+    create_confirmed_subscriber(&app, "main@lzzzt.cc").await;
+    create_confirmed_subscriber(&app, "second@lzzzt.cc").await;
+
+    Mock::given(path("/v3/mail/send"))
+        .respond_with(ResponseTemplate::new(200))
+        .expect(1)
+        .mount(&app.email_server)
+        .await;
+
+    let response = app
+        .post_newsletters(&serde_json::json!({
+            "title": "News for {{ email }}",
+            "text_content": "Sent to {{ email }}",
+            "html_content": "<p>Sent to {{ email }}</p>",
+        }))
+        .await;
+    assert_eq!(202, response.status().as_u16());
+    app.dispatch_all_pending_emails().await;
+
+    let request = app
+        .email_server
+        .received_requests()
+        .await
+        .unwrap()
+        .pop()
+        .unwrap();
+    let body: serde_json::Value = serde_json::from_slice(&request.body).unwrap();
+
+    for i in 0..2 {
+        let personalization = &body["personalizations"][i];
+        let email = personalization["to"][0]["email"].as_str().unwrap();
+        let (subject, text, html) = personalized(&body, i);
+
+        assert_eq!(subject, format!("News for {email}"));
+        assert_eq!(text, format!("Sent to {email}"));
+        assert_eq!(html, format!("<p>Sent to {email}</p>"));
+        assert!(
+            personalization["headers"]["List-Unsubscribe"]
+                .as_str()
+                .unwrap()
+                .contains("token=")
+        );
+    }
+}
+
+/// The subject and both bodies of a SendGrid request, as the recipient of its
+/// `i`th personalization gets them.
+fn personalized(body: &serde_json::Value, i: usize) -> (String, String, String) {
+    let substitutions = body["personalizations"][i]["substitutions"]
+        .as_object()
+        .cloned()
+        .unwrap_or_default();
+    let fill_in = |value: &serde_json::Value| {
+        substitutions
+            .iter()
+            .fold(value.as_str().unwrap().to_string(), |out, (key, value)| {
+                out.replace(key, value.as_str().unwrap())
+            })
+    };
+
+    (
+        fill_in(&body["subject"]),
+        fill_in(&body["content"][0]["value"]),
+        fill_in(&body["content"][1]["value"]),
+    )
+}
+
+#[tokio::test]
+async fn newsletters_with_unknown_variables_are_rejected() {
+    let app = TestApp::new().await;
+
+    let mut body = newsletter();
+    body["html_content"] = "<p>Hi {{ nmae }}</p>".into();
+    let response = app.post_newsletters(&body).await;
+
+    assert_eq!(422, response.status().as_u16());
+    let problem: serde_json::Value = response.json().await.unwrap();
+    assert_eq!(problem["errors"][0]["field"], "html_content");
+    assert_eq!(problem["errors"][0]["code"], "invalid_template");
+}
@@ -0,0 +1,216 @@
+use chrono::{TimeDelta, Utc};
+
+use crate::TestApp;
+use crate::admin_newsletters::newsletter;
+
+async fn publish(app: &TestApp, title: &str, html_content: &str) {
+    let mut body = newsletter();
+    body["title"] = title.into();
+    body["html_content"] = html_content.into();
+
+    let response = app.post_newsletters(&body).await;
+    assert_eq!(202, response.status().as_u16());
+}
+
+async fn get(app: &TestApp, path: &str) -> reqwest::Response {
+    reqwest::get(format!("{}{path}", app.address))
+        .await
+        .expect("Failed to send request.")
+}
+
+#[tokio::test]
+async fn published_issues_are_listed_and_shown() {
+    let app = TestApp::new().await;
+    publish(&app, "Hello, {{ name }}!", "<p>Hi {{ name }}</p>").await;
+
+    let response = get(&app, "/archive").await;
+    assert_eq!(200, response.status().as_u16());
+    assert_eq!(
+        response.headers()["content-type"],
+        "text/html; charset=utf-8"
+    );
+    let body = response.text().await.unwrap();
+    assert!(body.contains(r#"<a href="/archive/hello">Hello, !</a>"#));
+
+    let response = get(&app, "/archive/hello").await;
+    assert_eq!(200, response.status().as_u16());
+    let body = response.text().await.unwrap();
+    assert!(body.contains("<p>Hi </p>"));
+    assert!(!body.contains("{{"));
+}
+
+#[tokio::test]
+async fn repeated_titles_get_numbered_slugs() {
+    let app = TestApp::new().await;
+    publish(&app, "Weekly", "<p>One</p>").await;
+    publish(&app, "Weekly", "<p>Two</p>").await;
+
+    let body = get(&app, "/archive/weekly-2").await.text().await.unwrap();
+    assert!(body.contains("<p>Two</p>"));
+}
+
+#[tokio::test]
+async fn concurrently_published_titles_get_distinct_slugs() {
+    let app = TestApp::new().await;
+
+    tokio::join!(
+        publish(&app, "Weekly", "<p>One</p>"),
+        publish(&app, "Weekly", "<p>Two</p>"),
+        publish(&app, "Weekly", "<p>Three</p>"),
+    );
+
+    for slug in ["weekly", "weekly-2", "weekly-3"] {
+        let response = get(&app, &format!("/archive/{slug}")).await;
+        assert_eq!(200, response.status().as_u16());
+    }
+}
+
+#[tokio::test]
+async fn slugs_taken_while_publishing_are_picked_again() {
+    let app = TestApp::new().await;
+    publish(&app, "Weekly", "<p>One</p>").await;
+
+    // Another title getting `weekly-2`, not committed yet when the issue
+    // below picks its slug.
+    let mut transaction = app.conn_pool.begin().await.unwrap();
+    sqlx::query!(
+        r#"
+        INSERT INTO newsletter_issues (
+            newsletter_issue_id, title, text_content, html_content,
+            published_by, status, published_at, slug
+        )
+        SELECT $1, 'Weekly 2', '', '', user_id, 'published', now(), 'weekly-2'
+        FROM users WHERE username = $2
+        "#,
+        uuid::Uuid::new_v4(),
+        app.test_user.username,
+    )
+    .execute(&mut *transaction)
+    .await
+    .unwrap();
+
+    let commit = async {
+        tokio::time::sleep(std::time::Duration::from_millis(200)).await;
+        transaction.commit().await.unwrap();
+    };
+    tokio::join!(publish(&app, "Weekly", "<p>Two</p>"), commit);
+
+    let body = get(&app, "/archive/weekly-3").await.text().await.unwrap();
+    assert!(body.contains("<p>Two</p>"));
+}
+
+#[tokio::test]
+async fn scheduled_issues_are_not_archived_yet() {
+    let app = TestApp::new().await;
+    let mut body = newsletter();
+    body["title"] = "Coming soon".into();
+    body["scheduled_at"] = serde_json::json!(Utc::now() + TimeDelta::hours(1));
+    assert_eq!(202, app.post_newsletters(&body).await.status().as_u16());
+
+    let response = get(&app, "/archive").await;
+    assert!(!response.text().await.unwrap().contains("Coming soon"));
+
+    let response = get(&app, "/archive/coming-soon").await;
+    assert_eq!(404, response.status().as_u16());
+}
+
+#[tokio::test]
+async fn archived_issues_are_sanitized() {
+    let app = TestApp::new().await;
+    publish(
+        &app,
+        "Scripted",
+        r#"<p onclick="x()">Hi</p><script>x()</script>"#,
+    )
+    .await;
+
+    let body = get(&app, "/archive/scripted").await.text().await.unwrap();
+    assert!(!body.contains("<script"));
+    assert!(!body.contains("onclick"));
+}
+
+#[tokio::test]
+async fn archive_is_paginated() {
+    let app = TestApp::new().await;
+    for i in 0..21 {
+        publish(&app, &format!("Issue {i}"), "<p>Body</p>").await;
+    }
+
+    let first = get(&app, "/archive").await.text().await.unwrap();
+    assert_eq!(first.matches("<li>").count(), 20);
+    assert!(first.contains(r#"<a rel="next" href="/archive?page=2">"#));
+    assert!(!first.contains(r#"rel="prev""#));
+    // Most recent first.
+    assert!(first.contains("/archive/issue-20"));
+    assert!(!first.contains("/archive/issue-0\""));
+
+    let second = get(&app, "/archive?page=2").await.text().await.unwrap();
+    assert_eq!(second.matches("<li>").count(), 1);
+    assert!(second.contains("/archive/issue-0\""));
+    assert!(second.contains(r#"<a rel="prev" href="/archive?page=1">"#));
+    assert!(!second.contains(r#"rel="next""#));
+
+    assert_eq!(404, get(&app, "/archive?page=3").await.status().as_u16());
+    assert_eq!(400, get(&app, "/archive?page=0").await.status().as_u16());
+}
+
+#[tokio::test]
+async fn feeds_list_published_issues() {
+    let app = TestApp::new().await;
+    publish(&app, "Fresh news", "<p>Read <b>this</b></p>").await;
+
+    let response = get(&app, "/feed.xml").await;
+    assert_eq!(200, response.status().as_u16());
+    assert_eq!(
+        response.headers()["content-type"],
+        "application/rss+xml; charset=utf-8"
+    );
+    let rss = response.text().await.unwrap();
+    assert!(rss.starts_with(r#"<?xml version="1.0" encoding="utf-8"?><rss version="2.0">"#));
+    assert!(rss.contains("<title>Fresh news</title>"));
+    assert!(rss.contains("/archive/fresh-news</link>"));
+    assert!(rss.contains("&lt;b&gt;this&lt;/b&gt;"));
+
+    let response = get(&app, "/atom.xml").await;
+    assert_eq!(200, response.status().as_u16());
+    assert_eq!(
+        response.headers()["content-type"],
+        "application/atom+xml; charset=utf-8"
+    );
+    let atom = response.text().await.unwrap();
+    assert!(atom.contains(r#"<feed xmlns="http://www.w3.org/2005/Atom">"#));
+    assert!(atom.contains("<entry><title>Fresh news</title><id>urn:uuid:"));
+}
+
+#[tokio::test]
+async fn archive_can_be_revalidated() {
+    let app = TestApp::new().await;
+    publish(&app, "Cached", "<p>Body</p>").await;
+
+    for path in ["/archive", "/archive/cached", "/feed.xml", "/atom.xml"] {
+        let response = get(&app, path).await;
+        assert_eq!(response.headers()["cache-control"], "public, max-age=300");
+        let last_modified = response.headers()["last-modified"].clone();
+
+        let response = reqwest::Client::new()
+            .get(format!("{}{path}", app.address))
+            .header("If-Modified-Since", last_modified)
+            .send()
+            .await
+            .unwrap();
+        assert_eq!(304, response.status().as_u16(), "{path}");
+        assert!(response.text().await.unwrap().is_empty());
+    }
+}
+
+#[tokio::test]
+async fn empty_archive_is_served() {
+    let app = TestApp::new().await;
+
+    let response = get(&app, "/archive").await;
+    assert_eq!(200, response.status().as_u16());
+    assert!(response.headers().get("last-modified").is_none());
+
+    let atom = get(&app, "/atom.xml").await.text().await.unwrap();
+    assert!(atom.contains("<updated>1970-01-01T00:00:00Z</updated>"));
+}
@@ -0,0 +1,106 @@
+use reqwest::Url;
+use zero2prod::App;
+use zero2prod::config::{EmailProvider, Env, get_config};
+
+use crate::TestApp;
+
+async fn mail_catcher_app() -> TestApp {
+    TestApp::with_config(|c| c.email_client_config.provider = EmailProvider::MailCatcher).await
+}
+
+#[tokio::test]
+async fn mailbox_is_not_served_by_other_providers() {
+    let app = TestApp::new().await;
+
+    let response = reqwest::get(format!("{}/dev/mailbox", app.address))
+        .await
+        .unwrap();
+
+    assert_eq!(404, response.status().as_u16());
+}
+
+#[tokio::test]
+async fn the_mail_catcher_is_refused_outside_of_development() {
+    let mut config = get_config().await.expect("Failed to read configuration.");
+    config.env = Env::Prod;
+    config.app_config.port = 0;
+    config.app_config.admin = None;
+    config.email_client_config.provider = EmailProvider::MailCatcher;
+
+    assert!(App::build(config).await.is_err());
+}
+
+#[tokio::test]
+async fn mailbox_lists_caught_emails_with_their_links() {
+    let app = mail_catcher_app().await;
+
+    app.post_subscriptions("name=lzzzt&email=main%40lzzzt.cc".into())
+        .await
+        .error_for_status()
+        .unwrap();
+
+    let page = reqwest::get(format!("{}/dev/mailbox", app.address))
+        .await
+        .unwrap()
+        .error_for_status()
+        .unwrap()
+        .text()
+        .await
+        .unwrap();
+
+    assert!(page.contains("main@lzzzt.cc"));
+    assert!(page.contains(r#"href="/dev/mailbox/1""#));
+    assert!(page.contains("/subscriptions/confirm?token="));
+}
+
+#[tokio::test]
+async fn confirmation_link_from_the_mailbox_confirms_the_subscriber() {
+    let app = mail_catcher_app().await;
+
+    app.post_subscriptions("name=lzzzt&email=main%40lzzzt.cc".into())
+        .await
+        .error_for_status()
+        .unwrap();
+
+    let message = reqwest::get(format!("{}/dev/mailbox/1", app.address))
+        .await
+        .unwrap()
+        .error_for_status()
+        .unwrap()
+        .text()
+        .await
+        .unwrap();
+
+    let link = linkify::LinkFinder::new()
+        .links(&message)
+        .map(|l| l.as_str())
+        .find(|l| l.contains("/subscriptions/confirm"))
+        .unwrap();
+    let mut link = Url::parse(link).unwrap();
+    link.set_port(Some(app.port)).unwrap();
+
+    reqwest::get(link)
+        .await
+        .unwrap()
+        .error_for_status()
+        .unwrap();
+
+    let saved = sqlx::query!("SELECT status FROM subscriptions")
+        .fetch_one(&app.conn_pool)
+        .await
+        .expect("Failed to fetch saved subscription.");
+    assert_eq!(saved.status, "confirmed");
+}
+
+#[tokio::test]
+async fn unknown_messages_are_not_found() {
+    let app = mail_catcher_app().await;
+
+    let response = reqwest::get(format!("{}/dev/mailbox/42", app.address))
+        .await
+        .unwrap();
+
+    assert_eq!(404, response.status().as_u16());
+    let problem: serde_json::Value = response.json().await.unwrap();
+    assert_eq!(problem["code"], "not_found");
+}
@@ -0,0 +1,75 @@
+use wiremock::matchers::{any, path};
+use wiremock::{Mock, ResponseTemplate};
+
+use crate::TestApp;
+use crate::admin_newsletters::{create_confirmed_subscriber, newsletter};
+
+async fn quota_usage(app: &TestApp) -> i32 {
+    sqlx::query_scalar!("SELECT sent FROM email_quota_usage")
+        .fetch_one(&app.conn_pool)
+        .await
+        .expect("Failed to fetch the quota usage.")
+}
+
+#[tokio::test]
+async fn deliveries_over_the_daily_quota_are_postponed_to_the_next_day() {
+    let app =
+        TestApp::with_config(|c| c.email_client_config.rate_limit.daily_quota = Some(3)).await;
+    create_confirmed_subscriber(&app, "main@lzzzt.cc").await;
+    create_confirmed_subscriber(&app, "second@lzzzt.cc").await;
+    assert_eq!(quota_usage(&app).await, 2);
+
+    // What is left of the quota still goes out.
+    Mock::given(any())
+        .respond_with(ResponseTemplate::new(200))
+        .expect(1)
+        .mount(&app.email_server)
+        .await;
+
+    let response = app.post_newsletters(&newsletter()).await;
+    assert_eq!(202, response.status().as_u16());
+    app.dispatch_all_pending_emails().await;
+
+    let postponed = sqlx::query!(
+        r#"
+        SELECT n_attempts, execute_after::date = current_date + 1 AS "tomorrow!", last_error
+        FROM issue_delivery_queue
+        WHERE status = 'pending'
+        "#
+    )
+    .fetch_all(&app.conn_pool)
+    .await
+    .unwrap();
+    assert_eq!(postponed.len(), 1);
+    for task in postponed {
+        // Waiting for the quota does not use up attempts.
+        assert_eq!(task.n_attempts, 0);
+        assert!(task.tomorrow);
+        assert!(task.last_error.unwrap().contains("quota"));
+    }
+    assert_eq!(quota_usage(&app).await, 3);
+}
+
+#[tokio::test]
+async fn confirmation_emails_over_the_daily_quota_are_refused() {
+    let app =
+        TestApp::with_config(|c| c.email_client_config.rate_limit.daily_quota = Some(1)).await;
+
+    Mock::given(path("/v3/mail/send"))
+        .respond_with(ResponseTemplate::new(200))
+        .expect(1)
+        .mount(&app.email_server)
+        .await;
+
+    let response = app
+        .post_subscriptions("name=lzzzt&email=main%40lzzzt.cc".into())
+        .await;
+    assert_eq!(200, response.status().as_u16());
+
+    let response = app
+        .post_subscriptions("name=lzzzt&email=second%40lzzzt.cc".into())
+        .await;
+    assert_eq!(503, response.status().as_u16());
+    let problem: serde_json::Value = response.json().await.unwrap();
+    assert_eq!(problem["code"], "email_quota_exceeded");
+}
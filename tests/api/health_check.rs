@@ -1,3 +1,6 @@
+use wiremock::matchers::path;
+use wiremock::{Mock, ResponseTemplate};
+
 use crate::TestApp;
 
 #[tokio::test]
@@ -16,3 +19,66 @@ async fn health_check_works() {
     assert!(response.status().is_success());
     assert_eq!(response.content_length(), Some(0));
 }
+
+#[tokio::test]
+async fn health_check_details_report_a_closed_circuit() {
+    let app = TestApp::new().await;
+
+    let response = reqwest::get(format!("{}/health_check/details", app.address))
+        .await
+        .expect("Failed to send request.");
+
+    assert_eq!(200, response.status().as_u16());
+    let details: serde_json::Value = response.json().await.unwrap();
+    assert_eq!(details["status"], "ok");
+    assert_eq!(details["email_circuit"]["state"], "closed");
+}
+
+#[tokio::test]
+async fn sends_fail_fast_once_the_email_circuit_opens() {
+    let app = TestApp::with_config(|c| {
+        c.email_client_config.circuit_breaker.failure_threshold = 2;
+        c.email_client_config.circuit_breaker.open_ms = 60_000;
+    })
+    .await;
+
+    Mock::given(path("/v3/mail/send"))
+        .respond_with(ResponseTemplate::new(503))
+        .expect(2)
+        .mount(&app.email_server)
+        .await;
+
+    for email in ["first%40lzzzt.cc", "second%40lzzzt.cc"] {
+        let response = app
+            .post_subscriptions(format!("name=lzzzt&email={email}"))
+            .await;
+        assert_eq!(500, response.status().as_u16());
+    }
+
+    // The provider is not called again.
+    let response = app
+        .post_subscriptions("name=lzzzt&email=third%40lzzzt.cc".into())
+        .await;
+    assert_eq!(503, response.status().as_u16());
+    let problem: serde_json::Value = response.json().await.unwrap();
+    assert_eq!(problem["code"], "email_provider_unavailable");
+
+    let details: serde_json::Value = reqwest::get(format!("{}/health_check/details", app.address))
+        .await
+        .unwrap()
+        .json()
+        .await
+        .unwrap();
+    assert_eq!(details["status"], "degraded");
+    assert_eq!(details["email_circuit"]["state"], "open");
+    assert_eq!(details["email_circuit"]["opened_total"], 1);
+
+    let metrics = reqwest::get(format!("{}/metrics", app.address))
+        .await
+        .unwrap()
+        .text()
+        .await
+        .unwrap();
+    assert!(metrics.contains(r#"email_circuit_state{state="open"} 1"#));
+    assert!(metrics.contains("email_circuit_rejected_total 1"));
+}
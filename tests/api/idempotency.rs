@@ -0,0 +1,175 @@
+use std::time::Duration;
+
+use sha2::{Digest, Sha256};
+
+use crate::TestApp;
+use crate::admin_newsletters::newsletter;
+
+async fn saved_issues(app: &TestApp) -> i64 {
+    sqlx::query!(r#"SELECT COUNT(*) AS "count!" FROM newsletter_issues"#)
+        .fetch_one(&app.conn_pool)
+        .await
+        .expect("Failed to count issues.")
+        .count
+}
+
+#[tokio::test]
+async fn requests_without_an_idempotency_key_are_rejected() {
+    let app = TestApp::new().await;
+
+    let response = reqwest::Client::new()
+        .post(format!("{}/admin/newsletters", app.address))
+        .basic_auth(&app.test_user.username, Some(&app.test_user.password))
+        .json(&newsletter())
+        .send()
+        .await
+        .expect("Failed to send request.");
+
+    assert_eq!(400, response.status().as_u16());
+    let problem: serde_json::Value = response.json().await.unwrap();
+    assert_eq!(problem["code"], "missing_idempotency_key");
+}
+
+#[tokio::test]
+async fn overlong_idempotency_keys_are_rejected() {
+    let app = TestApp::new().await;
+
+    let response = app
+        .post_newsletters_with_key(&newsletter(), &"k".repeat(51))
+        .await;
+
+    assert_eq!(400, response.status().as_u16());
+    let problem: serde_json::Value = response.json().await.unwrap();
+    assert_eq!(problem["code"], "invalid_idempotency_key");
+}
+
+#[tokio::test]
+async fn retried_requests_replay_the_first_response() {
+    let app = TestApp::new().await;
+    let key = uuid::Uuid::new_v4().to_string();
+
+    let first = app.post_newsletters_with_key(&newsletter(), &key).await;
+    assert_eq!(202, first.status().as_u16());
+    let first_body = first.text().await.unwrap();
+
+    let second = app.post_newsletters_with_key(&newsletter(), &key).await;
+    assert_eq!(202, second.status().as_u16());
+    assert_eq!(second.headers()["Idempotent-Replayed"], "true");
+    assert_eq!(second.text().await.unwrap(), first_body);
+
+    assert_eq!(saved_issues(&app).await, 1);
+}
+
+#[tokio::test]
+async fn concurrent_duplicates_are_processed_once() {
+    let app = TestApp::new().await;
+    let key = uuid::Uuid::new_v4().to_string();
+    let body = newsletter();
+
+    let (first, second) = tokio::join!(
+        app.post_newsletters_with_key(&body, &key),
+        app.post_newsletters_with_key(&body, &key),
+    );
+
+    assert_eq!(first.status(), second.status());
+    assert_eq!(first.text().await.unwrap(), second.text().await.unwrap());
+    assert_eq!(saved_issues(&app).await, 1);
+}
+
+#[tokio::test]
+async fn keys_are_scoped_to_the_request_they_were_used_for() {
+    let app = TestApp::new().await;
+    let key = uuid::Uuid::new_v4().to_string();
+
+    app.post_newsletters_with_key(&newsletter(), &key).await;
+
+    let mut other = newsletter();
+    other["title"] = "Another title".into();
+    let response = app.post_newsletters_with_key(&other, &key).await;
+
+    assert_eq!(422, response.status().as_u16());
+    let problem: serde_json::Value = response.json().await.unwrap();
+    assert_eq!(problem["code"], "idempotency_key_reused");
+    assert_eq!(saved_issues(&app).await, 1);
+}
+
+#[tokio::test]
+async fn expired_keys_can_be_reused() {
+    let app = TestApp::with_config(|c| c.app_config.idempotency_key_ttl_secs = 1).await;
+    let key = uuid::Uuid::new_v4().to_string();
+
+    app.post_newsletters_with_key(&newsletter(), &key).await;
+    tokio::time::sleep(Duration::from_millis(1100)).await;
+    let response = app.post_newsletters_with_key(&newsletter(), &key).await;
+
+    assert_eq!(202, response.status().as_u16());
+    assert!(response.headers().get("Idempotent-Replayed").is_none());
+    assert_eq!(saved_issues(&app).await, 2);
+}
+
+#[tokio::test]
+async fn server_errors_are_not_saved() {
+    let app = TestApp::new().await;
+    let key = uuid::Uuid::new_v4().to_string();
+
+    // Break the handler's insert without touching the idempotency table.
+    sqlx::query("ALTER TABLE newsletter_issues DROP COLUMN title")
+        .execute(&app.conn_pool)
+        .await
+        .unwrap();
+
+    let response = app.post_newsletters_with_key(&newsletter(), &key).await;
+    assert_eq!(500, response.status().as_u16());
+
+    let saved = sqlx::query!(r#"SELECT COUNT(*) AS "count!" FROM idempotency"#)
+        .fetch_one(&app.conn_pool)
+        .await
+        .unwrap();
+    assert_eq!(saved.count, 0);
+}
+
+#[tokio::test]
+async fn keys_left_in_progress_are_taken_over_once_their_lease_is_over() {
+    let app = TestApp::new().await;
+    let key = uuid::Uuid::new_v4().to_string();
+
+    // What a request still running, or stopped along with its process, leaves
+    // behind.
+    let fingerprint = Sha256::new()
+        .chain_update("POST\0/admin/newsletters\0")
+        .chain_update(serde_json::to_vec(&newsletter()).unwrap())
+        .finalize()
+        .to_vec();
+    sqlx::query!(
+        r#"
+        INSERT INTO idempotency (
+            user_id, idempotency_key, request_fingerprint, created_at, locked_until
+        )
+        SELECT user_id, $2, $3, now(), now() + interval '1 minute'
+        FROM users WHERE username = $1
+        "#,
+        app.test_user.username,
+        key,
+        fingerprint,
+    )
+    .execute(&app.conn_pool)
+    .await
+    .unwrap();
+
+    let response = app.post_newsletters_with_key(&newsletter(), &key).await;
+
+    assert_eq!(409, response.status().as_u16());
+    let problem: serde_json::Value = response.json().await.unwrap();
+    assert_eq!(problem["code"], "idempotency_key_in_progress");
+    assert_eq!(saved_issues(&app).await, 0);
+
+    sqlx::query!("UPDATE idempotency SET locked_until = now() - interval '1 second'")
+        .execute(&app.conn_pool)
+        .await
+        .unwrap();
+
+    let response = app.post_newsletters_with_key(&newsletter(), &key).await;
+
+    assert_eq!(202, response.status().as_u16());
+    assert_eq!(saved_issues(&app).await, 1);
+}
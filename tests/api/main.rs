@@ -1,13 +1,26 @@
+mod admin_drafts;
+mod admin_newsletters;
+mod archive;
+mod dev_mailbox;
+mod email_quota;
 mod health_check;
+mod idempotency;
+mod scheduled_newsletters;
 mod subscriptions;
+mod subscriptions_confirm;
+mod subscriptions_unsubscribe;
 
 use std::sync::LazyLock;
 
 use percent_encoding::{NON_ALPHANUMERIC, PercentEncode, utf8_percent_encode};
+use reqwest::Url;
 use sqlx::{Connection, Executor, PgConnection, PgPool};
+use wiremock::MockServer;
 use zero2prod::{
     App,
-    config::{DBConfig, get_config},
+    config::{AdminConfig, Config, DBConfig, EmailProvider, get_config},
+    issue_delivery_worker::{DeliveryWorker, ExecutionOutcome},
+    issue_scheduler::IssueScheduler,
     telemetry::{create_subscriber, setup_subscriber},
 };
 
@@ -20,26 +33,73 @@ static TRACING_SUBSCRIBER: LazyLock<()> = LazyLock::new(|| {
 
 pub struct TestApp {
     pub address: String,
+    pub port: u16,
     pub conn_pool: PgPool,
+    pub email_server: MockServer,
+    pub test_user: TestUser,
+    pub delivery_worker: DeliveryWorker,
+    pub issue_scheduler: IssueScheduler,
+}
+
+pub struct TestUser {
+    pub username: String,
+    pub password: String,
+}
+
+pub struct ConfirmationLinks {
+    pub html: Url,
+    pub raw: Url,
 }
 
 impl TestApp {
     pub async fn new() -> Self {
+        Self::with_config(|_| {}).await
+    }
+
+    /// Spawns the app after letting the test adjust the configuration.
+    pub async fn with_config(configure: impl FnOnce(&mut Config)) -> Self {
         LazyLock::force(&TRACING_SUBSCRIBER);
 
+        let email_server = MockServer::start().await;
+        let test_user = TestUser {
+            username: uuid::Uuid::new_v4().to_string(),
+            password: uuid::Uuid::new_v4().to_string(),
+        };
+
         let config = {
             let mut c = get_config().await.expect("Failed to read config.");
             c.db_config.db_name = uuid::Uuid::new_v4().to_string();
             c.app_config.port = 0;
+            // Tests drive the queue themselves through `dispatch_all_pending_emails`
+            // and `publish_due_issues`.
+            c.delivery_config.workers = 0;
+            c.delivery_config.scheduler = false;
+            c.app_config.admin = Some(AdminConfig {
+                username: test_user.username.clone(),
+                password: test_user.password.clone().into(),
+            });
+            // Failed sends are left to the tests asserting on them.
+            c.email_client_config.retry.max_attempts = 1;
+            c.email_client_config.provider = EmailProvider::SendGrid;
+            c.email_client_config.base_url = email_server.uri().parse().unwrap();
+            configure(&mut c);
             c
         };
         let conn_pool = setup_database(&config.db_config).await;
 
         let app = App::build(config).await.expect("Failed to build app.");
+        let port = app.port();
+        let delivery_worker = app.delivery_worker();
+        let issue_scheduler = app.issue_scheduler();
 
         let test_app = TestApp {
-            address: format!("http://127.0.0.1:{}", app.port()),
+            address: format!("http://127.0.0.1:{port}"),
+            port,
             conn_pool,
+            email_server,
+            test_user,
+            delivery_worker,
+            issue_scheduler,
         };
 
         // Run the server at background
@@ -57,6 +117,103 @@ impl TestApp {
             .await
             .expect("Failed to send request.")
     }
+
+    pub async fn post_subscriptions_json(&self, body: &serde_json::Value) -> reqwest::Response {
+        reqwest::Client::new()
+            .post(format!("{}/subscriptions", &self.address))
+            .json(body)
+            .send()
+            .await
+            .expect("Failed to send request.")
+    }
+
+    /// Runs delivery tasks until none is due.
+    pub async fn dispatch_all_pending_emails(&self) {
+        loop {
+            let outcome = self
+                .delivery_worker
+                .try_execute_task()
+                .await
+                .expect("Failed to execute delivery task.");
+
+            if outcome == ExecutionOutcome::EmptyQueue {
+                break;
+            }
+        }
+    }
+
+    /// Runs the scheduler once, as its loop would.
+    pub async fn publish_due_issues(&self) -> Vec<uuid::Uuid> {
+        self.issue_scheduler
+            .publish_due_issues()
+            .await
+            .expect("Failed to publish due issues.")
+    }
+
+    /// A request to an admin route, authenticated as the test user and with a
+    /// fresh `Idempotency-Key`.
+    pub fn admin_request(&self, method: reqwest::Method, path: &str) -> reqwest::RequestBuilder {
+        reqwest::Client::new()
+            .request(method, format!("{}{path}", self.address))
+            .basic_auth(&self.test_user.username, Some(&self.test_user.password))
+            .header("Idempotency-Key", uuid::Uuid::new_v4().to_string())
+    }
+
+    pub async fn post_newsletters(&self, body: &serde_json::Value) -> reqwest::Response {
+        self.post_newsletters_with_key(body, &uuid::Uuid::new_v4().to_string())
+            .await
+    }
+
+    pub async fn post_newsletters_with_key(
+        &self,
+        body: &serde_json::Value,
+        idempotency_key: &str,
+    ) -> reqwest::Response {
+        reqwest::Client::new()
+            .post(format!("{}/admin/newsletters", &self.address))
+            .basic_auth(&self.test_user.username, Some(&self.test_user.password))
+            .header("Idempotency-Key", idempotency_key)
+            .json(body)
+            .send()
+            .await
+            .expect("Failed to send request.")
+    }
+
+    pub fn get_confirmation_links(&self, request: &wiremock::Request) -> ConfirmationLinks {
+        let body: serde_json::Value = serde_json::from_slice(&request.body).unwrap();
+
+        let get_link = |s: &str| {
+            let links: Vec<_> = linkify::LinkFinder::new()
+                .links(s)
+                .filter(|l| *l.kind() == linkify::LinkKind::Url)
+                .collect();
+            assert_eq!(links.len(), 1);
+
+            let mut link = Url::parse(links[0].as_str()).unwrap();
+            // Make sure we don't call random APIs on the web
+            assert_eq!(link.host_str().unwrap(), "127.0.0.1");
+            link.set_port(Some(self.port)).unwrap();
+            link
+        };
+
+        let raw = get_link(body["content"][0]["value"].as_str().unwrap());
+        let html = get_link(body["content"][1]["value"].as_str().unwrap());
+
+        ConfirmationLinks { html, raw }
+    }
+
+    pub fn get_unsubscribe_link(&self, request: &wiremock::Request) -> Url {
+        let body: serde_json::Value = serde_json::from_slice(&request.body).unwrap();
+
+        let header = body["personalizations"][0]["headers"]["List-Unsubscribe"]
+            .as_str()
+            .unwrap();
+
+        let mut link = Url::parse(header.trim_start_matches('<').trim_end_matches('>')).unwrap();
+        assert_eq!(link.host_str().unwrap(), "127.0.0.1");
+        link.set_port(Some(self.port)).unwrap();
+        link
+    }
 }
 
 async fn setup_database(config: &DBConfig) -> PgPool {
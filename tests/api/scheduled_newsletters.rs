@@ -0,0 +1,204 @@
+use chrono::{TimeDelta, Utc};
+use wiremock::matchers::{any, method, path};
+use wiremock::{Mock, ResponseTemplate};
+
+use crate::TestApp;
+use crate::admin_newsletters::{create_confirmed_subscriber, get_issue_report, newsletter};
+
+async fn schedule(app: &TestApp, from_now: TimeDelta) -> serde_json::Value {
+    let mut body = newsletter();
+    body["scheduled_at"] = serde_json::json!(Utc::now() + from_now);
+
+    let response = app.post_newsletters(&body).await;
+    assert_eq!(202, response.status().as_u16());
+
+    response.json().await.unwrap()
+}
+
+async fn cancel(app: &TestApp, issue_id: &str) -> reqwest::Response {
+    reqwest::Client::new()
+        .post(format!(
+            "{}/admin/newsletters/{issue_id}/cancel",
+            app.address
+        ))
+        .basic_auth(&app.test_user.username, Some(&app.test_user.password))
+        .header("Idempotency-Key", uuid::Uuid::new_v4().to_string())
+        .send()
+        .await
+        .expect("Failed to send request.")
+}
+
+async fn reschedule(app: &TestApp, issue_id: &str, body: &serde_json::Value) -> reqwest::Response {
+    reqwest::Client::new()
+        .put(format!(
+            "{}/admin/newsletters/{issue_id}/schedule",
+            app.address
+        ))
+        .basic_auth(&app.test_user.username, Some(&app.test_user.password))
+        .header("Idempotency-Key", uuid::Uuid::new_v4().to_string())
+        .json(body)
+        .send()
+        .await
+        .expect("Failed to send request.")
+}
+
+/// Moves the issue's time into the past instead of waiting for it.
+async fn make_due(app: &TestApp, issue_id: &str) {
+    sqlx::query!(
+        "UPDATE newsletter_issues SET scheduled_at = now() - interval '1 second' \
+         WHERE newsletter_issue_id = $1",
+        issue_id.parse::<uuid::Uuid>().unwrap(),
+    )
+    .execute(&app.conn_pool)
+    .await
+    .expect("Failed to move the schedule.");
+}
+
+#[tokio::test]
+async fn scheduled_issues_are_not_queued_before_they_are_due() {
+    let app = TestApp::new().await;
+    create_confirmed_subscriber(&app, "main@lzzzt.cc").await;
+
+    Mock::given(any())
+        .respond_with(ResponseTemplate::new(200))
+        .expect(0)
+        .mount(&app.email_server)
+        .await;
+
+    let summary = schedule(&app, TimeDelta::hours(1)).await;
+    assert_eq!(summary["status"], "scheduled");
+    assert_eq!(summary["queued"], 0);
+
+    assert!(app.publish_due_issues().await.is_empty());
+    app.dispatch_all_pending_emails().await;
+
+    let issue_id = summary["issue_id"].as_str().unwrap();
+    let report: serde_json::Value = get_issue_report(&app, issue_id).await.json().await.unwrap();
+    assert_eq!(report["status"], "scheduled");
+    assert_eq!(report["pending"], 0);
+    assert!(report["published_at"].is_null());
+}
+
+#[tokio::test]
+async fn due_issues_are_published_by_the_scheduler() {
+    let app = TestApp::new().await;
+    create_confirmed_subscriber(&app, "main@lzzzt.cc").await;
+
+    Mock::given(path("/v3/mail/send"))
+        .and(method("POST"))
+        .respond_with(ResponseTemplate::new(200))
+        .expect(1)
+        .mount(&app.email_server)
+        .await;
+
+    let summary = schedule(&app, TimeDelta::hours(1)).await;
+    let issue_id = summary["issue_id"].as_str().unwrap();
+    make_due(&app, issue_id).await;
+
+    let published = app.publish_due_issues().await;
+    assert_eq!(published.len(), 1);
+    assert_eq!(published[0].to_string(), issue_id);
+    assert!(app.publish_due_issues().await.is_empty());
+
+    app.dispatch_all_pending_emails().await;
+
+    let report: serde_json::Value = get_issue_report(&app, issue_id).await.json().await.unwrap();
+    assert_eq!(report["status"], "published");
+    assert_eq!(report["delivered"], 1);
+    assert!(report["published_at"].is_string());
+}
+
+#[tokio::test]
+async fn schedules_in_the_past_are_published_right_away() {
+    let app = TestApp::new().await;
+    create_confirmed_subscriber(&app, "main@lzzzt.cc").await;
+
+    let summary = schedule(&app, -TimeDelta::minutes(5)).await;
+
+    assert_eq!(summary["status"], "published");
+    assert_eq!(summary["queued"], 1);
+}
+
+#[tokio::test]
+async fn cancelled_issues_are_never_sent() {
+    let app = TestApp::new().await;
+    create_confirmed_subscriber(&app, "main@lzzzt.cc").await;
+
+    Mock::given(any())
+        .respond_with(ResponseTemplate::new(200))
+        .expect(0)
+        .mount(&app.email_server)
+        .await;
+
+    let summary = schedule(&app, TimeDelta::hours(1)).await;
+    let issue_id = summary["issue_id"].as_str().unwrap();
+
+    let response = cancel(&app, issue_id).await;
+    assert_eq!(200, response.status().as_u16());
+    let schedule: serde_json::Value = response.json().await.unwrap();
+    assert_eq!(schedule["status"], "cancelled");
+
+    make_due(&app, issue_id).await;
+    assert!(app.publish_due_issues().await.is_empty());
+    app.dispatch_all_pending_emails().await;
+
+    let response = cancel(&app, issue_id).await;
+    assert_eq!(409, response.status().as_u16());
+    let problem: serde_json::Value = response.json().await.unwrap();
+    assert_eq!(problem["code"], "issue_not_scheduled");
+}
+
+#[tokio::test]
+async fn scheduled_issues_can_be_rescheduled() {
+    let app = TestApp::new().await;
+
+    let summary = schedule(&app, TimeDelta::hours(1)).await;
+    let issue_id = summary["issue_id"].as_str().unwrap();
+
+    let scheduled_at = (Utc::now() + TimeDelta::days(2)).to_rfc3339();
+    let response = reschedule(
+        &app,
+        issue_id,
+        &serde_json::json!({ "scheduled_at": scheduled_at }),
+    )
+    .await;
+    assert_eq!(200, response.status().as_u16());
+
+    let report: serde_json::Value = get_issue_report(&app, issue_id).await.json().await.unwrap();
+    let saved: chrono::DateTime<Utc> = report["scheduled_at"].as_str().unwrap().parse().unwrap();
+    let expected: chrono::DateTime<Utc> = scheduled_at.parse().unwrap();
+    // Postgres keeps microseconds.
+    assert!((saved - expected).abs() < TimeDelta::milliseconds(1));
+    assert_eq!(report["status"], "scheduled");
+}
+
+#[tokio::test]
+async fn published_issues_cannot_be_changed() {
+    let app = TestApp::new().await;
+
+    let response = app.post_newsletters(&newsletter()).await;
+    let summary: serde_json::Value = response.json().await.unwrap();
+    let issue_id = summary["issue_id"].as_str().unwrap();
+
+    let response = cancel(&app, issue_id).await;
+    assert_eq!(409, response.status().as_u16());
+
+    let response = reschedule(
+        &app,
+        issue_id,
+        &serde_json::json!({ "scheduled_at": Utc::now() + TimeDelta::hours(1) }),
+    )
+    .await;
+    assert_eq!(409, response.status().as_u16());
+}
+
+#[tokio::test]
+async fn unknown_issues_cannot_be_cancelled() {
+    let app = TestApp::new().await;
+
+    let response = cancel(&app, &uuid::Uuid::new_v4().to_string()).await;
+    assert_eq!(404, response.status().as_u16());
+
+    let response = cancel(&app, "not-a-uuid").await;
+    assert_eq!(400, response.status().as_u16());
+}
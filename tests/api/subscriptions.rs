@@ -1,3 +1,8 @@
+use wiremock::matchers::{any, method, path};
+use wiremock::{Mock, ResponseTemplate};
+use zero2prod::domain::limits::{EMAIL_MAX_BYTES, EMAIL_MAX_CHARS, NAME_MAX_BYTES, NAME_MAX_CHARS};
+
+use crate::admin_newsletters::create_confirmed_subscriber;
 use crate::{TestApp, percent_encode};
 
 #[tokio::test]
@@ -12,17 +17,95 @@ async fn subscribe_returns_a_200_for_valid_form_data() {
         percent_encode(email)
     );
 
+    Mock::given(path("/v3/mail/send"))
+        .and(method("POST"))
+        .respond_with(ResponseTemplate::new(200))
+        .mount(&app.email_server)
+        .await;
+
     let response = app.post_subscriptions(body).await;
 
     assert_eq!(200, response.status().as_u16());
+}
 
-    let saved = sqlx::query!("SELECT email, name FROM subscriptions")
+#[tokio::test]
+async fn subscribe_persists_the_new_subscriber_as_pending() {
+    let app = TestApp::new().await;
+
+    let name = "lzzzt";
+    let email = "main@lzzzt.cc";
+    let body = format!(
+        "name={}&email={}",
+        percent_encode(name),
+        percent_encode(email)
+    );
+
+    Mock::given(path("/v3/mail/send"))
+        .and(method("POST"))
+        .respond_with(ResponseTemplate::new(200))
+        .mount(&app.email_server)
+        .await;
+
+    app.post_subscriptions(body).await;
+
+    let saved = sqlx::query!("SELECT email, name, status FROM subscriptions")
         .fetch_one(&app.conn_pool)
         .await
         .expect("Failed to read from Postgres");
 
     assert_eq!(saved.name, name);
     assert_eq!(saved.email, email);
+    assert_eq!(saved.status, "pending");
+}
+
+#[tokio::test]
+async fn subscribe_sends_a_confirmation_email_for_valid_data() {
+    let app = TestApp::new().await;
+    let body = "name=lzzzt&email=main%40lzzzt.cc";
+
+    Mock::given(path("/v3/mail/send"))
+        .and(method("POST"))
+        .respond_with(ResponseTemplate::new(200))
+        .expect(1)
+        .mount(&app.email_server)
+        .await;
+
+    app.post_subscriptions(body.into()).await;
+}
+
+#[tokio::test]
+async fn subscribe_sends_a_confirmation_email_with_a_link() {
+    let app = TestApp::new().await;
+    let body = "name=lzzzt&email=main%40lzzzt.cc";
+
+    Mock::given(path("/v3/mail/send"))
+        .and(method("POST"))
+        .respond_with(ResponseTemplate::new(200))
+        .mount(&app.email_server)
+        .await;
+
+    app.post_subscriptions(body.into()).await;
+
+    let email_request = &app.email_server.received_requests().await.unwrap()[0];
+    let confirmation_links = app.get_confirmation_links(email_request);
+
+    assert_eq!(confirmation_links.html, confirmation_links.raw);
+}
+
+#[tokio::test]
+async fn subscribe_returns_a_500_if_the_confirmation_email_cannot_be_sent() {
+    let app = TestApp::new().await;
+    let body = "name=lzzzt&email=main%40lzzzt.cc";
+
+    Mock::given(path("/v3/mail/send"))
+        .and(method("POST"))
+        .respond_with(ResponseTemplate::new(500))
+        .mount(&app.email_server)
+        .await;
+
+    let response = app.post_subscriptions(body.into()).await;
+
+    assert_eq!(500, response.status().as_u16());
 }
 
 #[tokio::test]
@@ -68,3 +151,594 @@ async fn subscribe_returns_a_422_when_fields_are_present_but_empty() {
         )
     }
 }
+
+#[tokio::test]
+async fn subscribing_twice_while_pending_sends_a_second_confirmation_email() {
+    let app = TestApp::new().await;
+    let body = "name=lzzzt&email=main%40lzzzt.cc";
+
+    Mock::given(path("/v3/mail/send"))
+        .and(method("POST"))
+        .respond_with(ResponseTemplate::new(200))
+        .expect(2)
+        .mount(&app.email_server)
+        .await;
+
+    let first = app.post_subscriptions(body.into()).await;
+    let second = app.post_subscriptions(body.into()).await;
+
+    assert_eq!(200, first.status().as_u16());
+    assert_eq!(200, second.status().as_u16());
+
+    let requests = app.email_server.received_requests().await.unwrap();
+    let first_link = app.get_confirmation_links(&requests[0]).html;
+    let second_link = app.get_confirmation_links(&requests[1]).html;
+
+    assert_ne!(first_link, second_link);
+}
+
+#[tokio::test]
+async fn subscribing_again_after_confirming_sends_a_notice_instead_of_a_confirmation() {
+    let app = TestApp::new().await;
+    let body = "name=lzzzt&email=main%40lzzzt.cc";
+
+    Mock::given(path("/v3/mail/send"))
+        .and(method("POST"))
+        .respond_with(ResponseTemplate::new(200))
+        .expect(2)
+        .mount(&app.email_server)
+        .await;
+
+    app.post_subscriptions(body.into()).await;
+    let email_request = &app.email_server.received_requests().await.unwrap()[0];
+    let confirmation_links = app.get_confirmation_links(email_request);
+    reqwest::get(confirmation_links.html)
+        .await
+        .unwrap()
+        .error_for_status()
+        .unwrap();
+
+    let response = app.post_subscriptions(body.into()).await;
+
+    assert_eq!(200, response.status().as_u16());
+    assert_eq!(0, response.content_length().unwrap_or_default());
+
+    let notice = &app.email_server.received_requests().await.unwrap()[1];
+    let notice: serde_json::Value = serde_json::from_slice(&notice.body).unwrap();
+    assert!(!notice.to_string().contains("/subscriptions/confirm"));
+}
+
+#[tokio::test]
+async fn subscribing_again_after_confirming_fails_like_a_new_subscription() {
+    let app = TestApp::new().await;
+    let body = "name=lzzzt&email=main%40lzzzt.cc";
+
+    create_confirmed_subscriber(&app, "main@lzzzt.cc").await;
+
+    Mock::given(path("/v3/mail/send"))
+        .and(method("POST"))
+        .respond_with(ResponseTemplate::new(500))
+        .mount(&app.email_server)
+        .await;
+
+    let response = app.post_subscriptions(body.into()).await;
+
+    assert_eq!(500, response.status().as_u16());
+}
+
+#[tokio::test]
+async fn concurrent_duplicate_subscriptions_create_a_single_subscriber() {
+    let app = TestApp::new().await;
+    let body = "name=lzzzt&email=main%40lzzzt.cc";
+
+    Mock::given(path("/v3/mail/send"))
+        .and(method("POST"))
+        .respond_with(ResponseTemplate::new(200))
+        .expect(2)
+        .mount(&app.email_server)
+        .await;
+
+    let (first, second) = tokio::join!(
+        app.post_subscriptions(body.into()),
+        app.post_subscriptions(body.into())
+    );
+
+    assert_eq!(200, first.status().as_u16());
+    assert_eq!(200, second.status().as_u16());
+
+    let saved = sqlx::query!("SELECT count(*) AS \"count!\" FROM subscriptions")
+        .fetch_one(&app.conn_pool)
+        .await
+        .expect("Failed to read from Postgres");
+
+    assert_eq!(saved.count, 1);
+}
+
+#[tokio::test]
+async fn subscribe_returns_problem_details_for_invalid_fields() {
+    let app = TestApp::new().await;
+
+    let response = app
+        .post_subscriptions("name=&email=main%40lzzzt.cc".into())
+        .await;
+
+    assert_eq!(422, response.status().as_u16());
+    assert_eq!(
+        response.headers()["Content-Type"],
+        "application/problem+json"
+    );
+
+    let request_id = response.headers()["x-request-id"]
+        .to_str()
+        .unwrap()
+        .to_owned();
+    let problem: serde_json::Value = response.json().await.unwrap();
+
+    assert_eq!(problem["status"], 422);
+    assert_eq!(problem["code"], "validation_failed");
+    assert_eq!(problem["errors"][0]["field"], "name");
+    assert!(problem["errors"][0]["message"].is_string());
+    assert_eq!(problem["request_id"], request_id.as_str());
+}
+
+#[tokio::test]
+async fn subscribe_returns_problem_details_for_missing_fields() {
+    let app = TestApp::new().await;
+
+    let response = app.post_subscriptions("name=lzzzt".into()).await;
+
+    assert_eq!(422, response.status().as_u16());
+
+    let problem: serde_json::Value = response.json().await.unwrap();
+
+    assert_eq!(problem["code"], "invalid_body");
+    assert_eq!(problem["errors"][0]["field"], "email");
+    assert_eq!(problem["errors"][0]["code"], "missing");
+}
+
+#[tokio::test]
+async fn subscribe_returns_problem_details_for_unexpected_errors() {
+    let app = TestApp::new().await;
+
+    Mock::given(path("/v3/mail/send"))
+        .and(method("POST"))
+        .respond_with(ResponseTemplate::new(500))
+        .mount(&app.email_server)
+        .await;
+
+    let response = app
+        .post_subscriptions("name=lzzzt&email=main%40lzzzt.cc".into())
+        .await;
+
+    assert_eq!(500, response.status().as_u16());
+
+    let problem: serde_json::Value = response.json().await.unwrap();
+
+    assert_eq!(problem["code"], "internal_error");
+    assert!(problem.get("errors").is_none());
+}
+
+#[tokio::test]
+async fn subscribe_reports_every_invalid_field() {
+    let app = TestApp::new().await;
+
+    let response = app.post_subscriptions("name=&email=12345".into()).await;
+
+    assert_eq!(422, response.status().as_u16());
+
+    let problem: serde_json::Value = response.json().await.unwrap();
+
+    assert_eq!(problem["errors"][0]["field"], "name");
+    assert_eq!(problem["errors"][0]["code"], "empty");
+    assert_eq!(problem["errors"][1]["field"], "email");
+    assert_eq!(problem["errors"][1]["code"], "syntax");
+}
+
+/// Builds a name of exactly `len` bytes out of mostly three-byte characters,
+/// so it stays well under the character limit.
+fn name_of_bytes(len: usize) -> String {
+    format!("{}{}", "€".repeat(len / 3), "a".repeat(len % 3))
+}
+
+/// Builds a syntactically valid address of exactly `len` characters.
+fn email_of_length(len: usize) -> String {
+    let local = "a".repeat(64);
+    let domain_len = len - local.len() - 1;
+    let labels = ["b".repeat(63), "c".repeat(63)].join(".");
+    let last = "d".repeat(domain_len - labels.len() - ".com".len() - 1);
+
+    format!("{local}@{labels}.{last}.com")
+}
+
+#[tokio::test]
+async fn subscribe_accepts_values_at_the_field_limits() {
+    let app = TestApp::new().await;
+
+    Mock::given(path("/v3/mail/send"))
+        .and(method("POST"))
+        .respond_with(ResponseTemplate::new(200))
+        .mount(&app.email_server)
+        .await;
+
+    let test_cases = vec![
+        (
+            "a".repeat(NAME_MAX_CHARS),
+            "main@lzzzt.cc".to_string(),
+            "longest name",
+        ),
+        (
+            name_of_bytes(NAME_MAX_BYTES),
+            "other@lzzzt.cc".to_string(),
+            "name at the byte limit",
+        ),
+        (
+            "lzzzt".to_string(),
+            email_of_length(EMAIL_MAX_CHARS),
+            "longest email",
+        ),
+    ];
+
+    for (name, email, description) in test_cases {
+        let body = format!(
+            "name={}&email={}",
+            percent_encode(&name),
+            percent_encode(&email)
+        );
+        let response = app.post_subscriptions(body).await;
+
+        assert_eq!(
+            200,
+            response.status().as_u16(),
+            "The API did not accept the {description}."
+        );
+    }
+}
+
+#[tokio::test]
+async fn subscribe_returns_a_422_for_values_over_the_field_limits() {
+    let app = TestApp::new().await;
+
+    let test_cases = vec![
+        (
+            "a".repeat(NAME_MAX_CHARS + 1),
+            "main@lzzzt.cc".to_string(),
+            "name",
+            "name too long",
+        ),
+        (
+            name_of_bytes(NAME_MAX_BYTES + 1),
+            "main@lzzzt.cc".to_string(),
+            "name",
+            "name over the byte limit",
+        ),
+        (
+            "lzzzt".to_string(),
+            email_of_length(EMAIL_MAX_CHARS + 1),
+            "email",
+            "email too long",
+        ),
+    ];
+
+    for (name, email, field, description) in test_cases {
+        let body = format!(
+            "name={}&email={}",
+            percent_encode(&name),
+            percent_encode(&email)
+        );
+        let response = app.post_subscriptions(body).await;
+
+        assert_eq!(
+            422,
+            response.status().as_u16(),
+            "The API did not fail with 422 Unprocessable Entity when the payload had a {description}."
+        );
+
+        let problem: serde_json::Value = response.json().await.unwrap();
+        assert_eq!(problem["errors"][0]["field"], field);
+        assert_eq!(problem["errors"][0]["code"], "too_long");
+    }
+}
+
+#[tokio::test]
+async fn subscriptions_table_matches_the_domain_limits() {
+    let app = TestApp::new().await;
+
+    let columns = sqlx::query!(
+        r#"
+        SELECT column_name AS "column_name!", character_maximum_length AS "max_chars!"
+        FROM information_schema.columns
+        WHERE table_name = 'subscriptions' AND column_name IN ('name', 'email')
+        "#
+    )
+    .fetch_all(&app.conn_pool)
+    .await
+    .expect("Failed to read from Postgres");
+
+    for column in columns {
+        let expected = match column.column_name.as_str() {
+            "name" => NAME_MAX_CHARS,
+            _ => EMAIL_MAX_CHARS,
+        };
+        assert_eq!(
+            column.max_chars as usize, expected,
+            "{}",
+            column.column_name
+        );
+    }
+
+    let constraints = sqlx::query!(
+        r#"
+        SELECT conname AS "name!", pg_get_constraintdef(oid) AS "definition!"
+        FROM pg_constraint
+        WHERE conname LIKE 'subscriptions_%_max_bytes'
+        "#
+    )
+    .fetch_all(&app.conn_pool)
+    .await
+    .expect("Failed to read from Postgres");
+
+    assert_eq!(constraints.len(), 2);
+
+    for constraint in constraints {
+        let expected = match constraint.name.as_str() {
+            "subscriptions_name_max_bytes" => NAME_MAX_BYTES,
+            _ => EMAIL_MAX_BYTES,
+        };
+        assert!(
+            constraint.definition.contains(&format!("<= {expected}")),
+            "{}",
+            constraint.definition
+        );
+    }
+}
+
+#[tokio::test]
+async fn subscribe_accepts_json_and_answers_with_json() {
+    let app = TestApp::new().await;
+
+    Mock::given(path("/v3/mail/send"))
+        .and(method("POST"))
+        .respond_with(ResponseTemplate::new(200))
+        .expect(1)
+        .mount(&app.email_server)
+        .await;
+
+    let response = app
+        .post_subscriptions_json(&serde_json::json!({
+            "name": "lzzzt",
+            "email": "main@lzzzt.cc",
+        }))
+        .await;
+
+    assert_eq!(200, response.status().as_u16());
+    assert_eq!(response.headers()["Content-Type"], "application/json");
+
+    let body: serde_json::Value = response.json().await.unwrap();
+    assert!(body["message"].is_string());
+
+    let saved = sqlx::query!("SELECT email, name, status FROM subscriptions")
+        .fetch_one(&app.conn_pool)
+        .await
+        .expect("Failed to read from Postgres");
+
+    assert_eq!(saved.name, "lzzzt");
+    assert_eq!(saved.email, "main@lzzzt.cc");
+    assert_eq!(saved.status, "pending");
+}
+
+#[tokio::test]
+async fn subscribe_stores_custom_attributes() {
+    let app = TestApp::new().await;
+
+    Mock::given(path("/v3/mail/send"))
+        .respond_with(ResponseTemplate::new(200))
+        .mount(&app.email_server)
+        .await;
+
+    let response = app
+        .post_subscriptions_json(&serde_json::json!({
+            "name": "lzzzt",
+            "email": "main@lzzzt.cc",
+            "attributes": { "company": "Lzzzt Inc." },
+        }))
+        .await;
+    assert_eq!(200, response.status().as_u16());
+
+    let saved = sqlx::query_scalar!("SELECT attributes FROM subscriptions")
+        .fetch_one(&app.conn_pool)
+        .await
+        .expect("Failed to read from Postgres");
+
+    assert_eq!(saved, serde_json::json!({ "company": "Lzzzt Inc." }));
+}
+
+#[tokio::test]
+async fn subscribe_validates_json_bodies_like_forms() {
+    let app = TestApp::new().await;
+
+    let test_cases = vec![
+        (serde_json::json!({ "name": "lzzzt" }), "email", "missing"),
+        (
+            serde_json::json!({ "name": "", "email": "main@lzzzt.cc" }),
+            "name",
+            "empty",
+        ),
+        (
+            serde_json::json!({ "name": "lzzzt", "email": "12345" }),
+            "email",
+            "syntax",
+        ),
+        (
+            serde_json::json!({
+                "name": "lzzzt",
+                "email": "main@lzzzt.cc",
+                "attributes": { "First Name": "Lzzzt" },
+            }),
+            "attributes",
+            "invalid_key",
+        ),
+    ];
+
+    for (body, field, code) in test_cases {
+        let response = app.post_subscriptions_json(&body).await;
+
+        assert_eq!(422, response.status().as_u16(), "payload was {body}");
+        assert_eq!(
+            response.headers()["Content-Type"],
+            "application/problem+json"
+        );
+
+        let problem: serde_json::Value = response.json().await.unwrap();
+        assert_eq!(problem["errors"][0]["field"], field, "payload was {body}");
+        assert_eq!(problem["errors"][0]["code"], code, "payload was {body}");
+    }
+}
+
+#[tokio::test]
+async fn subscribe_rejects_malformed_json_with_a_400() {
+    let app = TestApp::new().await;
+
+    let response = reqwest::Client::new()
+        .post(format!("{}/subscriptions", &app.address))
+        .header("Content-Type", "application/json")
+        .body("{\"name\": ")
+        .send()
+        .await
+        .expect("Failed to send request.");
+
+    assert_eq!(400, response.status().as_u16());
+
+    let problem: serde_json::Value = response.json().await.unwrap();
+    assert_eq!(problem["code"], "invalid_body");
+}
+
+#[tokio::test]
+async fn subscribe_detects_duplicates_on_the_canonical_email() {
+    let app = TestApp::new().await;
+
+    Mock::given(path("/v3/mail/send"))
+        .and(method("POST"))
+        .respond_with(ResponseTemplate::new(200))
+        .mount(&app.email_server)
+        .await;
+
+    for email in ["Main@LZZZT.cc", " main@lzzzt.cc "] {
+        let body = format!("name=lzzzt&email={}", percent_encode(email));
+        let response = app.post_subscriptions(body).await;
+
+        assert_eq!(200, response.status().as_u16());
+    }
+
+    let saved = sqlx::query!("SELECT email, email_canonical FROM subscriptions")
+        .fetch_all(&app.conn_pool)
+        .await
+        .expect("Failed to read from Postgres");
+
+    assert_eq!(saved.len(), 1);
+    assert_eq!(saved[0].email, "Main@LZZZT.cc");
+    assert_eq!(saved[0].email_canonical, "main@lzzzt.cc");
+}
+
+#[tokio::test]
+async fn subscribe_finds_addresses_saved_under_other_canonicalization_settings() {
+    let app =
+        TestApp::with_config(|c| c.app_config.email_canonicalization.provider_rules = true).await;
+
+    // Saved while provider rules were off.
+    sqlx::query!(
+        r#"
+        INSERT INTO subscriptions (id, email, email_canonical, name, subscribed_at, status)
+        VALUES ($1, 'a.b@gmail.com', 'a.b@gmail.com', 'lzzzt', now(), 'confirmed')
+        "#,
+        uuid::Uuid::new_v4(),
+    )
+    .execute(&app.conn_pool)
+    .await
+    .unwrap();
+
+    // The notice sent to confirmed subscribers.
+    Mock::given(any())
+        .respond_with(ResponseTemplate::new(200))
+        .expect(1)
+        .mount(&app.email_server)
+        .await;
+
+    let body = format!("name=lzzzt&email={}", percent_encode("a.b@gmail.com"));
+    let response = app.post_subscriptions(body).await;
+
+    assert_eq!(200, response.status().as_u16());
+
+    let saved = sqlx::query_scalar!(r#"SELECT COUNT(*) AS "count!" FROM subscriptions"#)
+        .fetch_one(&app.conn_pool)
+        .await
+        .expect("Failed to read from Postgres");
+
+    assert_eq!(saved, 1);
+}
+
+#[tokio::test]
+async fn subscribe_stores_internationalized_domains_in_punycode() {
+    let app = TestApp::new().await;
+
+    Mock::given(path("/v3/mail/send"))
+        .and(method("POST"))
+        .respond_with(ResponseTemplate::new(200))
+        .mount(&app.email_server)
+        .await;
+
+    let body = format!("name=lzzzt&email={}", percent_encode("main@Bücher.example"));
+    let response = app.post_subscriptions(body).await;
+
+    assert_eq!(200, response.status().as_u16());
+
+    let saved = sqlx::query!("SELECT email, email_canonical FROM subscriptions")
+        .fetch_one(&app.conn_pool)
+        .await
+        .expect("Failed to read from Postgres");
+
+    assert_eq!(saved.email, "main@Bücher.example");
+    assert_eq!(saved.email_canonical, "main@xn--bcher-kva.example");
+}
+
+#[tokio::test]
+async fn subscribe_rejects_addresses_with_nothing_left_after_provider_rules() {
+    let app =
+        TestApp::with_config(|c| c.app_config.email_canonicalization.provider_rules = true).await;
+
+    let body = format!("name=lzzzt&email={}", percent_encode("+news@gmail.com"));
+    let response = app.post_subscriptions(body).await;
+
+    assert_eq!(422, response.status().as_u16());
+
+    let problem: serde_json::Value = response.json().await.unwrap();
+
+    assert_eq!(problem["errors"][0]["field"], "email");
+    assert_eq!(problem["errors"][0]["code"], "empty_canonical_local_part");
+}
+
+#[tokio::test]
+async fn confirmation_emails_are_retried_after_transient_failures() {
+    let app = TestApp::with_config(|c| {
+        c.email_client_config.retry.max_attempts = 3;
+        c.email_client_config.retry.base_backoff_ms = 10;
+    })
+    .await;
+
+    Mock::given(path("/v3/mail/send"))
+        .respond_with(ResponseTemplate::new(503))
+        .up_to_n_times(1)
+        .expect(1)
+        .mount(&app.email_server)
+        .await;
+    Mock::given(path("/v3/mail/send"))
+        .respond_with(ResponseTemplate::new(200))
+        .expect(1)
+        .mount(&app.email_server)
+        .await;
+
+    let response = app
+        .post_subscriptions("name=lzzzt&email=main%40lzzzt.cc".into())
+        .await;
+
+    assert_eq!(200, response.status().as_u16());
+}
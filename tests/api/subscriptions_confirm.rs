@@ -0,0 +1,153 @@
+use wiremock::matchers::{method, path};
+use wiremock::{Mock, ResponseTemplate};
+
+use crate::TestApp;
+
+#[tokio::test]
+async fn confirmations_without_token_are_rejected_with_a_400() {
+    let app = TestApp::new().await;
+
+    let response = reqwest::get(format!("{}/subscriptions/confirm", app.address))
+        .await
+        .unwrap();
+
+    assert_eq!(400, response.status().as_u16());
+}
+
+#[tokio::test]
+async fn confirmations_with_an_unknown_token_are_rejected_with_a_401() {
+    let app = TestApp::new().await;
+
+    let response = reqwest::get(format!(
+        "{}/subscriptions/confirm?token=unknown",
+        app.address
+    ))
+    .await
+    .unwrap();
+
+    assert_eq!(401, response.status().as_u16());
+}
+
+#[tokio::test]
+async fn the_link_returned_by_subscribe_returns_a_200_if_called() {
+    let app = TestApp::new().await;
+    let body = "name=lzzzt&email=main%40lzzzt.cc";
+
+    Mock::given(path("/v3/mail/send"))
+        .and(method("POST"))
+        .respond_with(ResponseTemplate::new(200))
+        .mount(&app.email_server)
+        .await;
+
+    app.post_subscriptions(body.into()).await;
+    let email_request = &app.email_server.received_requests().await.unwrap()[0];
+    let confirmation_links = app.get_confirmation_links(email_request);
+
+    let response = reqwest::get(confirmation_links.html).await.unwrap();
+
+    assert_eq!(200, response.status().as_u16());
+}
+
+#[tokio::test]
+async fn clicking_on_the_confirmation_link_confirms_a_subscriber() {
+    let app = TestApp::new().await;
+    let body = "name=lzzzt&email=main%40lzzzt.cc";
+
+    Mock::given(path("/v3/mail/send"))
+        .and(method("POST"))
+        .respond_with(ResponseTemplate::new(200))
+        .mount(&app.email_server)
+        .await;
+
+    app.post_subscriptions(body.into()).await;
+    let email_request = &app.email_server.received_requests().await.unwrap()[0];
+    let confirmation_links = app.get_confirmation_links(email_request);
+
+    reqwest::get(confirmation_links.html)
+        .await
+        .unwrap()
+        .error_for_status()
+        .unwrap();
+
+    let saved = sqlx::query!("SELECT email, name, status FROM subscriptions")
+        .fetch_one(&app.conn_pool)
+        .await
+        .expect("Failed to read from Postgres");
+
+    assert_eq!(saved.email, "main@lzzzt.cc");
+    assert_eq!(saved.name, "lzzzt");
+    assert_eq!(saved.status, "confirmed");
+}
+
+async fn saved_status(app: &TestApp) -> String {
+    sqlx::query!("SELECT status FROM subscriptions")
+        .fetch_one(&app.conn_pool)
+        .await
+        .expect("Failed to read from Postgres")
+        .status
+}
+
+#[tokio::test]
+async fn confirmation_links_only_work_once() {
+    let app = TestApp::new().await;
+
+    Mock::given(path("/v3/mail/send"))
+        .and(method("POST"))
+        .respond_with(ResponseTemplate::new(200))
+        .mount(&app.email_server)
+        .await;
+
+    app.post_subscriptions("name=lzzzt&email=main%40lzzzt.cc".into())
+        .await;
+    let email_request = &app.email_server.received_requests().await.unwrap()[0];
+    let confirmation_links = app.get_confirmation_links(email_request);
+
+    reqwest::get(confirmation_links.html.clone())
+        .await
+        .unwrap()
+        .error_for_status()
+        .unwrap();
+    reqwest::Client::new()
+        .post(app.get_unsubscribe_link(email_request))
+        .send()
+        .await
+        .unwrap()
+        .error_for_status()
+        .unwrap();
+
+    let response = reqwest::get(confirmation_links.html).await.unwrap();
+
+    assert_eq!(401, response.status().as_u16());
+    assert_eq!(saved_status(&app).await, "unsubscribed");
+}
+
+#[tokio::test]
+async fn subscribers_who_left_before_confirming_are_not_confirmed() {
+    let app = TestApp::new().await;
+
+    Mock::given(path("/v3/mail/send"))
+        .and(method("POST"))
+        .respond_with(ResponseTemplate::new(200))
+        .mount(&app.email_server)
+        .await;
+
+    app.post_subscriptions("name=lzzzt&email=main%40lzzzt.cc".into())
+        .await;
+    let email_request = &app.email_server.received_requests().await.unwrap()[0];
+    let confirmation_links = app.get_confirmation_links(email_request);
+
+    reqwest::Client::new()
+        .post(app.get_unsubscribe_link(email_request))
+        .send()
+        .await
+        .unwrap()
+        .error_for_status()
+        .unwrap();
+    reqwest::get(confirmation_links.html)
+        .await
+        .unwrap()
+        .error_for_status()
+        .unwrap();
+
+    assert_eq!(saved_status(&app).await, "unsubscribed");
+}
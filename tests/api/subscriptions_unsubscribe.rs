@@ -0,0 +1,168 @@
+use wiremock::matchers::{method, path};
+use wiremock::{Mock, ResponseTemplate};
+
+use crate::TestApp;
+
+async fn subscribe(app: &TestApp) -> wiremock::Request {
+    Mock::given(path("/v3/mail/send"))
+        .and(method("POST"))
+        .respond_with(ResponseTemplate::new(200))
+        .mount(&app.email_server)
+        .await;
+
+    app.post_subscriptions("name=lzzzt&email=main%40lzzzt.cc".into())
+        .await
+        .error_for_status()
+        .unwrap();
+
+    app.email_server
+        .received_requests()
+        .await
+        .unwrap()
+        .remove(0)
+}
+
+async fn unsubscribe(link: reqwest::Url) {
+    reqwest::Client::new()
+        .post(link)
+        .send()
+        .await
+        .unwrap()
+        .error_for_status()
+        .unwrap();
+}
+
+async fn saved_status(app: &TestApp) -> String {
+    sqlx::query!("SELECT status FROM subscriptions")
+        .fetch_one(&app.conn_pool)
+        .await
+        .expect("Failed to read from Postgres")
+        .status
+}
+
+#[tokio::test]
+async fn emails_carry_one_click_unsubscribe_headers() {
+    let app = TestApp::new().await;
+
+    let email_request = subscribe(&app).await;
+    let body: serde_json::Value = serde_json::from_slice(&email_request.body).unwrap();
+    let headers = &body["personalizations"][0]["headers"];
+
+    assert!(headers["List-Unsubscribe"].is_string());
+    assert_eq!(
+        headers["List-Unsubscribe-Post"],
+        "List-Unsubscribe=One-Click"
+    );
+}
+
+#[tokio::test]
+async fn following_the_unsubscribe_link_asks_for_confirmation() {
+    let app = TestApp::new().await;
+
+    let email_request = subscribe(&app).await;
+    let link = app.get_unsubscribe_link(&email_request);
+    let token = link.query_pairs().next().unwrap().1.to_string();
+
+    let response = reqwest::get(link.clone()).await.unwrap();
+
+    assert_eq!(200, response.status().as_u16());
+    let page = response.text().await.unwrap();
+    assert!(page.contains(&format!(
+        r#"<form method="post" action="/subscriptions/unsubscribe?token={token}">"#
+    )));
+    assert_eq!(saved_status(&app).await, "pending");
+
+    // Submitting the form.
+    unsubscribe(link).await;
+
+    assert_eq!(saved_status(&app).await, "unsubscribed");
+}
+
+#[tokio::test]
+async fn one_click_post_unsubscribes_the_subscriber() {
+    let app = TestApp::new().await;
+
+    let email_request = subscribe(&app).await;
+    let link = app.get_unsubscribe_link(&email_request);
+
+    let response = reqwest::Client::new()
+        .post(link)
+        .header("Content-Type", "application/x-www-form-urlencoded")
+        .body("List-Unsubscribe=One-Click")
+        .send()
+        .await
+        .unwrap();
+
+    assert_eq!(200, response.status().as_u16());
+    assert_eq!(saved_status(&app).await, "unsubscribed");
+}
+
+#[tokio::test]
+async fn unsubscribing_with_a_tampered_token_is_rejected_with_a_401() {
+    let app = TestApp::new().await;
+
+    let email_request = subscribe(&app).await;
+    let mut link = app.get_unsubscribe_link(&email_request);
+    let token = link.query_pairs().next().unwrap().1.to_string();
+    link.set_query(Some(&format!("token={token}x")));
+
+    let response = reqwest::get(link).await.unwrap();
+
+    assert_eq!(401, response.status().as_u16());
+    assert_eq!(saved_status(&app).await, "pending");
+}
+
+#[tokio::test]
+async fn unsubscribing_without_a_token_is_rejected_with_a_400() {
+    let app = TestApp::new().await;
+
+    let response = reqwest::get(format!("{}/subscriptions/unsubscribe", app.address))
+        .await
+        .unwrap();
+
+    assert_eq!(400, response.status().as_u16());
+}
+
+#[tokio::test]
+async fn subscribing_again_after_unsubscribing_requires_a_new_confirmation() {
+    let app = TestApp::new().await;
+
+    let email_request = subscribe(&app).await;
+    let link = app.get_unsubscribe_link(&email_request);
+    unsubscribe(link).await;
+
+    let response = app
+        .post_subscriptions("name=lzzzt&email=main%40lzzzt.cc".into())
+        .await;
+
+    assert_eq!(200, response.status().as_u16());
+    assert_eq!(saved_status(&app).await, "pending");
+    assert_eq!(app.email_server.received_requests().await.unwrap().len(), 2);
+}
+
+#[tokio::test]
+async fn unsubscribing_matches_the_canonical_email() {
+    let app = TestApp::new().await;
+
+    Mock::given(path("/v3/mail/send"))
+        .and(method("POST"))
+        .respond_with(ResponseTemplate::new(200))
+        .mount(&app.email_server)
+        .await;
+
+    app.post_subscriptions("name=lzzzt&email=Main%40LZZZT.cc".into())
+        .await
+        .error_for_status()
+        .unwrap();
+    // Signing up again with another spelling sends the second email to it.
+    app.post_subscriptions("name=lzzzt&email=main%40lzzzt.cc".into())
+        .await
+        .error_for_status()
+        .unwrap();
+
+    let email_request = &app.email_server.received_requests().await.unwrap()[1];
+    let link = app.get_unsubscribe_link(email_request);
+    unsubscribe(link).await;
+
+    assert_eq!(saved_status(&app).await, "unsubscribed");
+}